@@ -2,14 +2,12 @@
 
 extern crate coreaudio;
 
-use std::collections::VecDeque;
-use std::sync::{Arc, Mutex};
-
 use coreaudio::audio_unit::macos_helpers::{
     audio_unit_from_device_id, get_default_device_id, get_device_name, RateListener,
 };
 use coreaudio::audio_unit::render_callback::{self, data};
 use coreaudio::audio_unit::{Element, Scope};
+use coreaudio::ring_buffer::RingBuffer;
 use coreaudio::sys::*;
 use coreaudio::{LinearPcmFlags, SampleFormat, StreamFormat};
 
@@ -71,12 +69,11 @@ fn main() -> Result<(), coreaudio::Error> {
     let asbd = out_stream_format.to_asbd();
     output_audio_unit.set_property(id, Scope::Input, Element::Output, Some(&asbd))?;
 
-    let buffer_left = Arc::new(Mutex::new(VecDeque::<S>::new()));
-    let producer_left = buffer_left.clone();
-    let consumer_left = buffer_left.clone();
-    let buffer_right = Arc::new(Mutex::new(VecDeque::<S>::new()));
-    let producer_right = buffer_right.clone();
-    let consumer_right = buffer_right.clone();
+    // The input and output callbacks run on different realtime threads, so the samples are passed
+    // between them through a lock-free ring buffer with room for two seconds of audio.
+    let capacity = 2 * out_stream_format.sample_rate as usize;
+    let channels = in_stream_format.channels as usize;
+    let (mut producer, mut consumer) = RingBuffer::<S>::new(capacity, channels).split();
 
     // Register a rate listener for playback
     let mut listener_pb = RateListener::new(output_device_id, None);
@@ -87,12 +84,8 @@ fn main() -> Result<(), coreaudio::Error> {
     listener_cap.register()?;
 
     // seed roughly 1 second of data to create a delay in the feedback loop for easier testing
-    for buffer in vec![buffer_left, buffer_right] {
-        let mut buffer = buffer.lock().unwrap();
-        for _ in 0..(out_stream_format.sample_rate as i32) {
-            buffer.push_back(0 as S);
-        }
-    }
+    let silence = vec![0 as S; capacity / 2 * channels];
+    producer.write_interleaved(&silence);
 
     type Args = render_callback::Args<data::Interleaved<S>>;
 
@@ -105,15 +98,7 @@ fn main() -> Result<(), coreaudio::Error> {
         // that may block for an unknown amount of time inside the callback
         // of a real application.
         println!("input cb {} frames", num_frames);
-        let buffer_left = producer_left.lock().unwrap();
-        let buffer_right = producer_right.lock().unwrap();
-        let mut buffers = vec![buffer_left, buffer_right];
-        for i in 0..num_frames {
-            for channel in 0..2 {
-                let value: S = data.buffer[2 * i + channel];
-                buffers[channel].push_back(value);
-            }
-        }
+        producer.write_interleaved(data.buffer);
         Ok(())
     })?;
     input_audio_unit.start()?;
@@ -124,18 +109,7 @@ fn main() -> Result<(), coreaudio::Error> {
         } = args;
         // Print the number of frames the callback requests.
         println!("output cb {} frames", num_frames);
-        let buffer_left = consumer_left.lock().unwrap();
-        let buffer_right = consumer_right.lock().unwrap();
-        let mut buffers = vec![buffer_left, buffer_right];
-        for i in 0..num_frames {
-            // Default other channels to copy value from first channel as a fallback
-            let zero: S = 0 as S;
-            let f: S = *buffers[0].front().unwrap_or(&zero);
-            for channel in 0..2 {
-                let sample: S = buffers[channel].pop_front().unwrap_or(f);
-                data.buffer[2 * i + channel] = sample;
-            }
-        }
+        consumer.read_interleaved(data.buffer);
         Ok(())
     })?;
     output_audio_unit.start()?;
@@ -1,57 +1,190 @@
 use std::ffi::c_void;
-use std::marker::PhantomData;
 use std::mem;
 use std::ops::{Deref, DerefMut};
+use std::ptr;
 
 use coreaudio_sys::{AudioBuffer, AudioBufferList as SysAudioBufferList};
 
-use crate::Sample;
+use crate::{Error, LinearPcmFlags, Sample, StreamFormat};
 
-//
+/// An owned `AudioBufferList` holding samples of type `S`.
+///
+/// The list either holds a single interleaved buffer containing all channels, or one buffer per
+/// channel for non-interleaved formats. Non-interleaved channel buffers are stored one after
+/// another in the backing storage, each `capacity` frames long.
 pub struct AudioBufferList<S: Sample> {
-    list: SysAudioBufferList,
-    _data: Box<[S]>,
-    _ph: PhantomData<S>,
+    // Storage for the `AudioBufferList` header followed by all of its `AudioBuffer`s. A
+    // `SysAudioBufferList` is used as the element type so that the storage is correctly aligned.
+    list: Box<[SysAudioBufferList]>,
+    data: Box<[S]>,
+    channels: usize,
+    capacity: usize,
+    frames: usize,
+    interleaved: bool,
 }
 
 impl<S: Sample> AudioBufferList<S> {
+    /// Create a list with a single interleaved buffer holding `size` frames of `channels`
+    /// channels.
     pub fn new(channels: usize, size: usize) -> Self {
-        let len = channels * size;
-        let byte_size = len * mem::size_of::<S>();
+        Self::with_layout(channels, size, true)
+    }
 
-        let mut data = (vec![S::default(); len]).into_boxed_slice();
+    /// Create a list with one buffer per channel, each holding `size` frames.
+    pub fn new_non_interleaved(channels: usize, size: usize) -> Self {
+        Self::with_layout(channels, size, false)
+    }
 
-        let buffer = AudioBuffer {
-            mNumberChannels: channels as u32,
-            mDataByteSize: byte_size as u32,
-            mData: data.as_mut_ptr() as *mut c_void,
-        };
+    /// Create a list matching the layout of the given `StreamFormat`, holding `size` frames.
+    ///
+    /// Returns an error if `S` does not match the sample format of the stream.
+    pub fn for_stream_format(stream_format: &StreamFormat, size: usize) -> Result<Self, Error> {
+        if S::sample_format() != stream_format.sample_format {
+            return Err(Error::RenderCallbackBufferFormatDoesNotMatchAudioUnitStreamFormat);
+        }
+        let channels = stream_format.channels as usize;
+        let interleaved = !stream_format
+            .flags
+            .contains(LinearPcmFlags::IS_NON_INTERLEAVED);
+        Ok(Self::with_layout(channels, size, interleaved))
+    }
 
-        let list = SysAudioBufferList {
-            mNumberBuffers: 1,
-            mBuffers: [buffer],
-        };
+    fn with_layout(channels: usize, size: usize, interleaved: bool) -> Self {
+        let num_buffers = if interleaved { 1 } else { channels };
+        let header_size = mem::size_of::<SysAudioBufferList>() - mem::size_of::<AudioBuffer>();
+        let list_size = header_size + num_buffers.max(1) * mem::size_of::<AudioBuffer>();
+        let n = mem::size_of::<SysAudioBufferList>();
+        // `usize::div_ceil` is too recent for the versions of Rust this crate supports.
+        #[allow(clippy::manual_div_ceil)]
+        let list_len = (list_size + n - 1) / n;
+        let list = vec![SysAudioBufferList::default(); list_len].into_boxed_slice();
+        let data = vec![S::default(); channels * size].into_boxed_slice();
 
-        Self {
+        let mut buffer_list = Self {
             list,
-            _data: data,
-            _ph: PhantomData,
+            data,
+            channels,
+            capacity: size,
+            frames: size,
+            interleaved,
+        };
+        buffer_list.reset(size);
+        buffer_list
+    }
+
+    /// The number of channels held by the list.
+    pub fn channels(&self) -> usize {
+        self.channels
+    }
+
+    /// The maximum number of frames the list can hold.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// The number of frames currently held by the list.
+    pub fn frames(&self) -> usize {
+        self.frames
+    }
+
+    /// Whether all channels are stored in a single interleaved buffer.
+    pub fn is_interleaved(&self) -> bool {
+        self.interleaved
+    }
+
+    /// The number of buffers in the list.
+    pub fn num_buffers(&self) -> usize {
+        if self.interleaved {
+            1
+        } else {
+            self.channels
         }
     }
+
+    /// The samples of the buffer at the given index.
+    ///
+    /// For interleaved lists this is the only buffer, for non-interleaved lists it is the channel
+    /// at the given index.
+    pub fn buffer(&self, index: usize) -> &[S] {
+        let range = self.buffer_range(index);
+        &self.data[range]
+    }
+
+    /// The samples of the buffer at the given index.
+    pub fn buffer_mut(&mut self, index: usize) -> &mut [S] {
+        let range = self.buffer_range(index);
+        &mut self.data[range]
+    }
+
+    fn buffer_range(&self, index: usize) -> std::ops::Range<usize> {
+        assert!(index < self.num_buffers(), "buffer index out of range");
+        if self.interleaved {
+            0..self.frames * self.channels
+        } else {
+            let start = index * self.capacity;
+            start..start + self.frames
+        }
+    }
+
+    /// Point each `AudioBuffer` at its storage and size it for `frames` frames.
+    ///
+    /// Audio units may change the size (and for some units the data pointer) of the buffers
+    /// while rendering, so this should be called before the list is handed to a render call.
+    pub(crate) fn reset(&mut self, frames: usize) {
+        assert!(
+            frames <= self.capacity,
+            "frames exceed the buffer list capacity"
+        );
+        self.frames = frames;
+        let num_buffers = self.num_buffers();
+        let (channels_per_buffer, stride) = if self.interleaved {
+            (self.channels, 0)
+        } else {
+            (1, self.capacity)
+        };
+        let byte_size = frames * channels_per_buffer * mem::size_of::<S>();
+        let data_ptr = self.data.as_mut_ptr();
+        unsafe {
+            let list = self.list.as_mut_ptr();
+            (*list).mNumberBuffers = num_buffers as u32;
+            // Go through a raw pointer, as the buffers extend past the declared array length.
+            let buffers = ptr::addr_of_mut!((*list).mBuffers) as *mut AudioBuffer;
+            for i in 0..num_buffers {
+                *buffers.add(i) = AudioBuffer {
+                    mNumberChannels: channels_per_buffer as u32,
+                    mDataByteSize: byte_size as u32,
+                    mData: data_ptr.add(i * stride) as *mut c_void,
+                };
+            }
+        }
+    }
+
+    /// A pointer to the underlying `AudioBufferList`, for passing to Core Audio.
+    pub(crate) fn as_mut_ptr(&mut self) -> *mut SysAudioBufferList {
+        self.list.as_mut_ptr()
+    }
 }
 
 impl<S: Sample> Deref for AudioBufferList<S> {
     type Target = [S];
 
     fn deref(&self) -> &Self::Target {
-        let len = self.list.mBuffers[0].mDataByteSize as usize / mem::size_of::<S>();
-        unsafe { std::slice::from_raw_parts(self.list.mBuffers[0].mData as *const S, len) }
+        if self.interleaved {
+            &self.data[..self.frames * self.channels]
+        } else {
+            &self.data
+        }
     }
 }
 
 impl<S: Sample> DerefMut for AudioBufferList<S> {
     fn deref_mut(&mut self) -> &mut Self::Target {
-        let len = self.list.mBuffers[0].mDataByteSize as usize / mem::size_of::<S>();
-        unsafe { std::slice::from_raw_parts_mut(self.list.mBuffers[0].mData as *mut S, len) }
+        if self.interleaved {
+            &mut self.data[..self.frames * self.channels]
+        } else {
+            &mut self.data
+        }
     }
 }
+
+unsafe impl<S: Sample + Send> Send for AudioBufferList<S> {}
@@ -0,0 +1,494 @@
+//! Channel configurations and channel layouts of **AudioUnit** buses.
+//!
+//! [**AudioUnit::supported_channel_configurations**](../struct.AudioUnit#method.supported_channel_configurations)
+//! lists the combinations of input and output channel counts a unit can process, and
+//! [**ChannelLayout**](./struct.ChannelLayout) describes the role of each channel of a bus. A
+//! layout is given either by a layout tag, by a bitmap of the speakers that are present, or by a
+//! description of each channel, and converts to and from the variable-length
+//! `AudioChannelLayout` struct used by Core Audio.
+//!
+//! ```no_run
+//! # use coreaudio::audio_unit::{AudioUnit, Element, MixerType, Scope};
+//! # use coreaudio::audio_unit::channel_layout::{ChannelLayout, ChannelLayoutTag};
+//! # fn main() -> Result<(), coreaudio::Error> {
+//! let mut unit = AudioUnit::new(MixerType::MultiChannelMixer)?;
+//! let layout = ChannelLayout::from_tag(ChannelLayoutTag::MPEG_5_1_A);
+//! unit.set_channel_layout(Scope::Output, Element::Output, &layout)?;
+//! assert_eq!(unit.channel_layout(Scope::Output, Element::Output)?.channel_count(), 6);
+//! # Ok(())
+//! # }
+//! ```
+
+use super::property::{self, VariableLength};
+use super::{AudioUnit, Bus, Element, Scope};
+use crate::error::{AudioUnitError, Error};
+use sys;
+
+/// A combination of input and output channel counts supported by a unit, as reported by
+/// `kAudioUnitProperty_SupportedNumChannels`.
+///
+/// Non-negative counts are exact. `-1` on both sides means any count as long as the input and
+/// output match, `-1` on one side means any count on that side, and `-2` on one side together
+/// with `-1` on the other means any count on both sides. Any other negative count `-n` means
+/// up to `n` channels.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ChannelConfiguration {
+    pub input: i16,
+    pub output: i16,
+}
+
+impl ChannelConfiguration {
+    /// Whether `input` input channels and `output` output channels are supported.
+    pub fn supports(&self, input: u32, output: u32) -> bool {
+        let matches = |count: i16, channels: u32| match count {
+            -2 | -1 => true,
+            n if n < 0 => channels <= (-(n as i32)) as u32,
+            n => channels == n as u32,
+        };
+        match (self.input, self.output) {
+            (-1, -1) => input == output,
+            (i, o) => matches(i, input) && matches(o, output),
+        }
+    }
+}
+
+impl From<sys::AUChannelInfo> for ChannelConfiguration {
+    fn from(info: sys::AUChannelInfo) -> Self {
+        ChannelConfiguration {
+            input: info.inChannels,
+            output: info.outChannels,
+        }
+    }
+}
+
+/// Identifies a standard channel layout, as an `AudioChannelLayoutTag`.
+///
+/// The lower 16 bits of a tag hold its number of channels.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ChannelLayoutTag(pub u32);
+
+impl ChannelLayoutTag {
+    /// The layout is given by the channel descriptions.
+    pub const USE_CHANNEL_DESCRIPTIONS: Self = ChannelLayoutTag(0);
+    /// The layout is given by the channel bitmap.
+    pub const USE_CHANNEL_BITMAP: Self = ChannelLayoutTag(1 << 16);
+    /// A single channel.
+    pub const MONO: Self = ChannelLayoutTag((100 << 16) | 1);
+    /// L R
+    pub const STEREO: Self = ChannelLayoutTag((101 << 16) | 2);
+    /// L R, for playback over headphones.
+    pub const STEREO_HEADPHONES: Self = ChannelLayoutTag((102 << 16) | 2);
+    /// L R Ls Rs
+    pub const QUADRAPHONIC: Self = ChannelLayoutTag((108 << 16) | 4);
+    /// L R C
+    pub const MPEG_3_0_A: Self = ChannelLayoutTag((113 << 16) | 3);
+    /// L R C Cs
+    pub const MPEG_4_0_A: Self = ChannelLayoutTag((115 << 16) | 4);
+    /// L R C Ls Rs
+    pub const MPEG_5_0_A: Self = ChannelLayoutTag((117 << 16) | 5);
+    /// L R C LFE Ls Rs
+    pub const MPEG_5_1_A: Self = ChannelLayoutTag((121 << 16) | 6);
+    /// L R C LFE Ls Rs Cs
+    pub const MPEG_6_1_A: Self = ChannelLayoutTag((125 << 16) | 7);
+    /// L R C LFE Ls Rs Lc Rc
+    pub const MPEG_7_1_A: Self = ChannelLayoutTag((126 << 16) | 8);
+    /// L R C LFE Ls Rs Rls Rrs
+    pub const MPEG_7_1_C: Self = ChannelLayoutTag((128 << 16) | 8);
+    /// Channels without a speaker position, in order. Combine with a channel count using
+    /// [**discrete_in_order**](./struct.ChannelLayoutTag#method.discrete_in_order).
+    pub const DISCRETE_IN_ORDER: Self = ChannelLayoutTag(147 << 16);
+    /// Channels with unknown roles. Combine with a channel count using
+    /// [**unknown**](./struct.ChannelLayoutTag#method.unknown).
+    pub const UNKNOWN: Self = ChannelLayoutTag(0xFFFF_0000);
+
+    /// `channels` channels without a speaker position.
+    pub fn discrete_in_order(channels: u16) -> Self {
+        ChannelLayoutTag(Self::DISCRETE_IN_ORDER.0 | channels as u32)
+    }
+
+    /// `channels` channels with unknown roles.
+    pub fn unknown(channels: u16) -> Self {
+        ChannelLayoutTag(Self::UNKNOWN.0 | channels as u32)
+    }
+
+    /// The number of channels of the layout, which is `0` for layouts given by their
+    /// descriptions or bitmap.
+    pub fn channel_count(&self) -> u32 {
+        self.0 & 0xFFFF
+    }
+}
+
+/// The role of a channel, as an `AudioChannelLabel`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ChannelLabel(pub u32);
+
+impl ChannelLabel {
+    pub const UNKNOWN: Self = ChannelLabel(0xFFFF_FFFF);
+    pub const UNUSED: Self = ChannelLabel(0);
+    /// The channel is positioned by its coordinates.
+    pub const USE_COORDINATES: Self = ChannelLabel(100);
+    pub const LEFT: Self = ChannelLabel(1);
+    pub const RIGHT: Self = ChannelLabel(2);
+    pub const CENTER: Self = ChannelLabel(3);
+    pub const LFE_SCREEN: Self = ChannelLabel(4);
+    pub const LEFT_SURROUND: Self = ChannelLabel(5);
+    pub const RIGHT_SURROUND: Self = ChannelLabel(6);
+    pub const LEFT_CENTER: Self = ChannelLabel(7);
+    pub const RIGHT_CENTER: Self = ChannelLabel(8);
+    pub const CENTER_SURROUND: Self = ChannelLabel(9);
+    pub const LEFT_SURROUND_DIRECT: Self = ChannelLabel(10);
+    pub const RIGHT_SURROUND_DIRECT: Self = ChannelLabel(11);
+    pub const TOP_CENTER_SURROUND: Self = ChannelLabel(12);
+    pub const VERTICAL_HEIGHT_LEFT: Self = ChannelLabel(13);
+    pub const VERTICAL_HEIGHT_CENTER: Self = ChannelLabel(14);
+    pub const VERTICAL_HEIGHT_RIGHT: Self = ChannelLabel(15);
+    pub const TOP_BACK_LEFT: Self = ChannelLabel(16);
+    pub const TOP_BACK_CENTER: Self = ChannelLabel(17);
+    pub const TOP_BACK_RIGHT: Self = ChannelLabel(18);
+    pub const REAR_SURROUND_LEFT: Self = ChannelLabel(33);
+    pub const REAR_SURROUND_RIGHT: Self = ChannelLabel(34);
+    pub const MONO: Self = ChannelLabel(42);
+    pub const HEADPHONES_LEFT: Self = ChannelLabel(301);
+    pub const HEADPHONES_RIGHT: Self = ChannelLabel(302);
+
+    /// The `n`th channel without a speaker position.
+    pub fn discrete(n: u16) -> Self {
+        ChannelLabel((1 << 16) | n as u32)
+    }
+}
+
+bitflags! {
+    /// The speakers present in a layout given by its bitmap, as an `AudioChannelBitmap`.
+    ///
+    /// The channels are ordered as the bits, from the lowest.
+    pub struct ChannelBitmap: u32 {
+        const LEFT = sys::kAudioChannelBit_Left;
+        const RIGHT = sys::kAudioChannelBit_Right;
+        const CENTER = sys::kAudioChannelBit_Center;
+        const LFE_SCREEN = sys::kAudioChannelBit_LFEScreen;
+        const LEFT_SURROUND = sys::kAudioChannelBit_LeftSurround;
+        const RIGHT_SURROUND = sys::kAudioChannelBit_RightSurround;
+        const LEFT_CENTER = sys::kAudioChannelBit_LeftCenter;
+        const RIGHT_CENTER = sys::kAudioChannelBit_RightCenter;
+        const CENTER_SURROUND = sys::kAudioChannelBit_CenterSurround;
+        const LEFT_SURROUND_DIRECT = sys::kAudioChannelBit_LeftSurroundDirect;
+        const RIGHT_SURROUND_DIRECT = sys::kAudioChannelBit_RightSurroundDirect;
+        const TOP_CENTER_SURROUND = sys::kAudioChannelBit_TopCenterSurround;
+        const VERTICAL_HEIGHT_LEFT = sys::kAudioChannelBit_VerticalHeightLeft;
+        const VERTICAL_HEIGHT_CENTER = sys::kAudioChannelBit_VerticalHeightCenter;
+        const VERTICAL_HEIGHT_RIGHT = sys::kAudioChannelBit_VerticalHeightRight;
+        const TOP_BACK_LEFT = sys::kAudioChannelBit_TopBackLeft;
+        const TOP_BACK_CENTER = sys::kAudioChannelBit_TopBackCenter;
+        const TOP_BACK_RIGHT = sys::kAudioChannelBit_TopBackRight;
+    }
+}
+
+/// The description of a single channel of a layout.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ChannelDescription {
+    pub label: ChannelLabel,
+    /// The `AudioChannelFlags`, saying how the coordinates are to be interpreted.
+    pub flags: u32,
+    /// The position of the speaker, used when `label` is `ChannelLabel::USE_COORDINATES`.
+    pub coordinates: [f32; 3],
+}
+
+impl ChannelDescription {
+    /// Describe a channel by its label alone.
+    pub fn new(label: ChannelLabel) -> Self {
+        ChannelDescription {
+            label,
+            flags: 0,
+            coordinates: [0.0; 3],
+        }
+    }
+}
+
+impl From<sys::AudioChannelDescription> for ChannelDescription {
+    fn from(description: sys::AudioChannelDescription) -> Self {
+        ChannelDescription {
+            label: ChannelLabel(description.mChannelLabel),
+            flags: description.mChannelFlags,
+            coordinates: description.mCoordinates,
+        }
+    }
+}
+
+impl From<ChannelDescription> for sys::AudioChannelDescription {
+    fn from(description: ChannelDescription) -> Self {
+        sys::AudioChannelDescription {
+            mChannelLabel: description.label.0,
+            mChannelFlags: description.flags,
+            mCoordinates: description.coordinates,
+        }
+    }
+}
+
+/// The layout of the channels of a bus.
+///
+/// `bitmap` is only used when `tag` is `USE_CHANNEL_BITMAP`, and `descriptions` are only used
+/// when `tag` is `USE_CHANNEL_DESCRIPTIONS`, though Core Audio may return descriptions along
+/// with other tags.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChannelLayout {
+    pub tag: ChannelLayoutTag,
+    pub bitmap: ChannelBitmap,
+    pub descriptions: Vec<ChannelDescription>,
+}
+
+impl ChannelLayout {
+    /// A standard layout given by its tag.
+    pub fn from_tag(tag: ChannelLayoutTag) -> Self {
+        ChannelLayout {
+            tag,
+            bitmap: ChannelBitmap::empty(),
+            descriptions: Vec::new(),
+        }
+    }
+
+    /// A layout of the speakers present in `bitmap`.
+    pub fn from_bitmap(bitmap: ChannelBitmap) -> Self {
+        ChannelLayout {
+            tag: ChannelLayoutTag::USE_CHANNEL_BITMAP,
+            bitmap,
+            descriptions: Vec::new(),
+        }
+    }
+
+    /// A layout with a channel for each of `labels`, in order.
+    pub fn from_labels(labels: &[ChannelLabel]) -> Self {
+        ChannelLayout {
+            tag: ChannelLayoutTag::USE_CHANNEL_DESCRIPTIONS,
+            bitmap: ChannelBitmap::empty(),
+            descriptions: labels
+                .iter()
+                .cloned()
+                .map(ChannelDescription::new)
+                .collect(),
+        }
+    }
+
+    /// The number of channels in the layout.
+    pub fn channel_count(&self) -> u32 {
+        match self.tag {
+            ChannelLayoutTag::USE_CHANNEL_DESCRIPTIONS => self.descriptions.len() as u32,
+            ChannelLayoutTag::USE_CHANNEL_BITMAP => self.bitmap.bits().count_ones(),
+            tag => tag.channel_count(),
+        }
+    }
+
+    /// The label of each channel, in order.
+    ///
+    /// Returns `None` for layout tags whose channel order is not known to this crate, such as
+    /// `UNKNOWN`.
+    pub fn labels(&self) -> Option<Vec<ChannelLabel>> {
+        use self::ChannelLabel as L;
+        let labels: &[ChannelLabel] = match self.tag {
+            ChannelLayoutTag::USE_CHANNEL_DESCRIPTIONS => {
+                return Some(self.descriptions.iter().map(|d| d.label).collect());
+            }
+            ChannelLayoutTag::USE_CHANNEL_BITMAP => {
+                // The bits are ordered as the labels, starting from `LEFT`.
+                let bits = self.bitmap.bits();
+                let labels = (0..32)
+                    .filter(|bit| bits & (1 << bit) != 0)
+                    .map(|bit| ChannelLabel(bit + 1))
+                    .collect();
+                return Some(labels);
+            }
+            tag if tag.0 & 0xFFFF_0000 == ChannelLayoutTag::DISCRETE_IN_ORDER.0 => {
+                let channels = tag.channel_count() as u16;
+                return Some((0..channels).map(ChannelLabel::discrete).collect());
+            }
+            ChannelLayoutTag::MONO => &[L::MONO],
+            ChannelLayoutTag::STEREO => &[L::LEFT, L::RIGHT],
+            ChannelLayoutTag::STEREO_HEADPHONES => &[L::HEADPHONES_LEFT, L::HEADPHONES_RIGHT],
+            ChannelLayoutTag::QUADRAPHONIC => {
+                &[L::LEFT, L::RIGHT, L::LEFT_SURROUND, L::RIGHT_SURROUND]
+            }
+            ChannelLayoutTag::MPEG_3_0_A => &[L::LEFT, L::RIGHT, L::CENTER],
+            ChannelLayoutTag::MPEG_4_0_A => &[L::LEFT, L::RIGHT, L::CENTER, L::CENTER_SURROUND],
+            ChannelLayoutTag::MPEG_5_0_A => &[
+                L::LEFT,
+                L::RIGHT,
+                L::CENTER,
+                L::LEFT_SURROUND,
+                L::RIGHT_SURROUND,
+            ],
+            ChannelLayoutTag::MPEG_5_1_A => &[
+                L::LEFT,
+                L::RIGHT,
+                L::CENTER,
+                L::LFE_SCREEN,
+                L::LEFT_SURROUND,
+                L::RIGHT_SURROUND,
+            ],
+            ChannelLayoutTag::MPEG_6_1_A => &[
+                L::LEFT,
+                L::RIGHT,
+                L::CENTER,
+                L::LFE_SCREEN,
+                L::LEFT_SURROUND,
+                L::RIGHT_SURROUND,
+                L::CENTER_SURROUND,
+            ],
+            ChannelLayoutTag::MPEG_7_1_A => &[
+                L::LEFT,
+                L::RIGHT,
+                L::CENTER,
+                L::LFE_SCREEN,
+                L::LEFT_SURROUND,
+                L::RIGHT_SURROUND,
+                L::LEFT_CENTER,
+                L::RIGHT_CENTER,
+            ],
+            ChannelLayoutTag::MPEG_7_1_C => &[
+                L::LEFT,
+                L::RIGHT,
+                L::CENTER,
+                L::LFE_SCREEN,
+                L::LEFT_SURROUND,
+                L::RIGHT_SURROUND,
+                L::REAR_SURROUND_LEFT,
+                L::REAR_SURROUND_RIGHT,
+            ],
+            _ => return None,
+        };
+        Some(labels.to_vec())
+    }
+
+    /// Convert to the variable-length `AudioChannelLayout` struct.
+    pub fn to_sys(&self) -> VariableLength<sys::AudioChannelLayout> {
+        VariableLength {
+            header: sys::AudioChannelLayout {
+                mChannelLayoutTag: self.tag.0,
+                mChannelBitmap: self.bitmap.bits(),
+                mNumberChannelDescriptions: 0,
+                mChannelDescriptions: [ChannelDescription::new(ChannelLabel::UNUSED).into()],
+            },
+            items: self.descriptions.iter().map(|&d| d.into()).collect(),
+        }
+    }
+}
+
+impl From<&VariableLength<sys::AudioChannelLayout>> for ChannelLayout {
+    fn from(layout: &VariableLength<sys::AudioChannelLayout>) -> Self {
+        ChannelLayout {
+            tag: ChannelLayoutTag(layout.header.mChannelLayoutTag),
+            bitmap: ChannelBitmap::from_bits_truncate(layout.header.mChannelBitmap),
+            descriptions: layout.items.iter().map(|&d| d.into()).collect(),
+        }
+    }
+}
+
+impl AudioUnit {
+    /// The combinations of input and output channel counts the unit supports.
+    ///
+    /// Units that don't report their configurations support any count as long as the input and
+    /// output match, which is returned as a single `{ input: -1, output: -1 }` configuration.
+    ///
+    /// **Available** in iOS 2.0 and later.
+    pub fn supported_channel_configurations(&self) -> Result<Vec<ChannelConfiguration>, Error> {
+        match self.get::<property::SupportedNumChannels>(Scope::Global, Element::Output) {
+            Ok(infos) => Ok(infos.into_iter().map(ChannelConfiguration::from).collect()),
+            Err(Error::AudioUnit(AudioUnitError::InvalidProperty)) => {
+                Ok(vec![ChannelConfiguration {
+                    input: -1,
+                    output: -1,
+                }])
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    /// The channel layout of an input or output bus.
+    ///
+    /// **Available** in iOS 2.0 and later.
+    pub fn channel_layout(
+        &self,
+        scope: Scope,
+        bus: impl Into<Bus>,
+    ) -> Result<ChannelLayout, Error> {
+        let layout = self.get::<property::AudioChannelLayout>(scope, bus)?;
+        Ok(ChannelLayout::from(&layout))
+    }
+
+    /// Sets the channel layout of an input or output bus.
+    ///
+    /// The layout must have as many channels as the stream format of the bus.
+    ///
+    /// **Available** in iOS 2.0 and later.
+    pub fn set_channel_layout(
+        &mut self,
+        scope: Scope,
+        bus: impl Into<Bus>,
+        layout: &ChannelLayout,
+    ) -> Result<(), Error> {
+        self.set::<property::AudioChannelLayout>(scope, bus, &layout.to_sys())
+    }
+
+    /// The layout tags an input or output bus supports.
+    ///
+    /// **Available** in iOS 2.0 and later.
+    pub fn supported_channel_layout_tags(
+        &self,
+        scope: Scope,
+        bus: impl Into<Bus>,
+    ) -> Result<Vec<ChannelLayoutTag>, Error> {
+        let tags = self.get::<property::SupportedChannelLayoutTags>(scope, bus)?;
+        Ok(tags.into_iter().map(ChannelLayoutTag).collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::audio_unit::property::PropertyValue;
+
+    #[test]
+    fn test_supports() {
+        let config = |input, output| ChannelConfiguration { input, output };
+        assert!(config(-1, -1).supports(6, 6));
+        assert!(!config(-1, -1).supports(1, 2));
+        assert!(config(-1, -2).supports(1, 8));
+        assert!(config(-1, 2).supports(5, 2));
+        assert!(!config(-1, 2).supports(5, 1));
+        assert!(config(1, 2).supports(1, 2));
+        assert!(!config(2, 2).supports(1, 2));
+        assert!(config(-8, 2).supports(8, 2));
+        assert!(!config(-8, 2).supports(9, 2));
+    }
+
+    #[test]
+    fn test_channel_count() {
+        assert_eq!(ChannelLayoutTag::MPEG_5_1_A.channel_count(), 6);
+        assert_eq!(ChannelLayoutTag::discrete_in_order(3).channel_count(), 3);
+        let bitmap = ChannelLayout::from_bitmap(ChannelBitmap::LEFT | ChannelBitmap::RIGHT);
+        assert_eq!(bitmap.channel_count(), 2);
+        let labels = [
+            ChannelLabel::LEFT,
+            ChannelLabel::RIGHT,
+            ChannelLabel::LFE_SCREEN,
+        ];
+        assert_eq!(ChannelLayout::from_labels(&labels).channel_count(), 3);
+        assert_eq!(ChannelLabel::discrete(2), ChannelLabel(0x1_0002));
+    }
+
+    #[test]
+    fn test_layout_round_trip() {
+        let mut layout =
+            ChannelLayout::from_labels(&[ChannelLabel::CENTER, ChannelLabel::LEFT_SURROUND]);
+        layout.descriptions[1].coordinates = [-1.0, 0.5, 0.0];
+        layout.to_sys().with_data(|data| {
+            let decoded = VariableLength::<sys::AudioChannelLayout>::from_data(data).unwrap();
+            assert_eq!(decoded.header.mNumberChannelDescriptions, 2);
+            assert_eq!(ChannelLayout::from(&decoded), layout);
+        });
+
+        let stereo = ChannelLayout::from_tag(ChannelLayoutTag::STEREO);
+        stereo.to_sys().with_data(|data| {
+            let decoded = VariableLength::<sys::AudioChannelLayout>::from_data(data).unwrap();
+            assert_eq!(ChannelLayout::from(&decoded), stereo);
+        });
+    }
+}
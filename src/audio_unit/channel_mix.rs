@@ -0,0 +1,449 @@
+//! Standard downmix and upmix matrices between channel layouts.
+//!
+//! [**MixMatrix::between**](./struct.MixMatrix#method.between) builds the gain from every source
+//! channel to every destination channel from the labels of two
+//! [**ChannelLayout**](../channel_layout/struct.ChannelLayout)s. Channels present in both
+//! layouts pass through unchanged, and each missing channel is folded into its nearest
+//! neighbours using the ITU-R BS.775 coefficients, e.g. the centre and surround channels of a
+//! 5.1 layout go into left and right at -3 dB. The LFE channel is dropped by downmixes, and
+//! upmixes leave the additional channels silent rather than synthesising them.
+//!
+//! ```
+//! use coreaudio::audio_unit::channel_layout::{ChannelLayout, ChannelLayoutTag};
+//! use coreaudio::audio_unit::channel_mix::MixMatrix;
+//!
+//! let surround = ChannelLayout::from_tag(ChannelLayoutTag::MPEG_5_1_A);
+//! let stereo = ChannelLayout::from_tag(ChannelLayoutTag::STEREO);
+//! let matrix = MixMatrix::between(&surround, &stereo);
+//!
+//! // One frame of L R C LFE Ls Rs.
+//! let input = [1.0, 0.0, 0.0, 1.0, 0.0, 0.0];
+//! let mut output = [0.0; 2];
+//! matrix.mix_interleaved(&input, &mut output).unwrap();
+//! assert_eq!(output, [1.0, 0.0]);
+//! ```
+//!
+//! Downmixes may exceed full scale when several source channels are loud at once.
+//! [**MixMatrix::normalize**](./struct.MixMatrix#method.normalize) scales the matrix so that
+//! they can't.
+
+use std::f32::consts::FRAC_1_SQRT_2;
+
+use super::channel_layout::{ChannelLabel, ChannelLayout};
+use crate::error::Error;
+use crate::AudioBufferList;
+
+/// -3 dB, the gain of a channel split between two others.
+const HALF_POWER: f32 = FRAC_1_SQRT_2;
+
+/// Where a source channel goes when the destination lacks it, from the closest match to the
+/// furthest. The first entry whose channels are all present in the destination is used.
+fn fallbacks(label: ChannelLabel) -> &'static [&'static [(ChannelLabel, f32)]] {
+    use self::ChannelLabel as L;
+    match label {
+        L::LEFT => &[
+            &[(L::HEADPHONES_LEFT, 1.0)],
+            &[(L::MONO, HALF_POWER)],
+            &[(L::CENTER, HALF_POWER)],
+        ],
+        L::RIGHT => &[
+            &[(L::HEADPHONES_RIGHT, 1.0)],
+            &[(L::MONO, HALF_POWER)],
+            &[(L::CENTER, HALF_POWER)],
+        ],
+        L::CENTER => &[
+            &[(L::MONO, 1.0)],
+            &[(L::LEFT, HALF_POWER), (L::RIGHT, HALF_POWER)],
+        ],
+        L::MONO => &[
+            &[(L::CENTER, 1.0)],
+            &[(L::LEFT, HALF_POWER), (L::RIGHT, HALF_POWER)],
+            &[
+                (L::HEADPHONES_LEFT, HALF_POWER),
+                (L::HEADPHONES_RIGHT, HALF_POWER),
+            ],
+        ],
+        L::LEFT_SURROUND => &[
+            &[(L::REAR_SURROUND_LEFT, 1.0)],
+            &[(L::LEFT, HALF_POWER)],
+            &[(L::MONO, 0.5)],
+        ],
+        L::LEFT_SURROUND_DIRECT => &[
+            &[(L::LEFT_SURROUND, 1.0)],
+            &[(L::REAR_SURROUND_LEFT, 1.0)],
+            &[(L::LEFT, HALF_POWER)],
+            &[(L::MONO, 0.5)],
+        ],
+        L::RIGHT_SURROUND => &[
+            &[(L::REAR_SURROUND_RIGHT, 1.0)],
+            &[(L::RIGHT, HALF_POWER)],
+            &[(L::MONO, 0.5)],
+        ],
+        L::RIGHT_SURROUND_DIRECT => &[
+            &[(L::RIGHT_SURROUND, 1.0)],
+            &[(L::REAR_SURROUND_RIGHT, 1.0)],
+            &[(L::RIGHT, HALF_POWER)],
+            &[(L::MONO, 0.5)],
+        ],
+        L::REAR_SURROUND_LEFT => &[
+            &[(L::LEFT_SURROUND, 1.0)],
+            &[(L::LEFT, HALF_POWER)],
+            &[(L::MONO, 0.5)],
+        ],
+        L::REAR_SURROUND_RIGHT => &[
+            &[(L::RIGHT_SURROUND, 1.0)],
+            &[(L::RIGHT, HALF_POWER)],
+            &[(L::MONO, 0.5)],
+        ],
+        L::CENTER_SURROUND => &[
+            &[
+                (L::LEFT_SURROUND, HALF_POWER),
+                (L::RIGHT_SURROUND, HALF_POWER),
+            ],
+            &[
+                (L::REAR_SURROUND_LEFT, HALF_POWER),
+                (L::REAR_SURROUND_RIGHT, HALF_POWER),
+            ],
+            &[(L::LEFT, 0.5), (L::RIGHT, 0.5)],
+            &[(L::MONO, 0.5)],
+        ],
+        L::LEFT_CENTER => &[
+            &[(L::LEFT, HALF_POWER), (L::CENTER, HALF_POWER)],
+            &[(L::LEFT, 1.0)],
+            &[(L::MONO, HALF_POWER)],
+        ],
+        L::RIGHT_CENTER => &[
+            &[(L::RIGHT, HALF_POWER), (L::CENTER, HALF_POWER)],
+            &[(L::RIGHT, 1.0)],
+            &[(L::MONO, HALF_POWER)],
+        ],
+        L::HEADPHONES_LEFT => &[&[(L::LEFT, 1.0)], &[(L::MONO, HALF_POWER)]],
+        L::HEADPHONES_RIGHT => &[&[(L::RIGHT, 1.0)], &[(L::MONO, HALF_POWER)]],
+        L::VERTICAL_HEIGHT_LEFT | L::TOP_BACK_LEFT => {
+            &[&[(L::LEFT, HALF_POWER)], &[(L::MONO, 0.5)]]
+        }
+        L::VERTICAL_HEIGHT_RIGHT | L::TOP_BACK_RIGHT => {
+            &[&[(L::RIGHT, HALF_POWER)], &[(L::MONO, 0.5)]]
+        }
+        L::VERTICAL_HEIGHT_CENTER | L::TOP_BACK_CENTER | L::TOP_CENTER_SURROUND => &[
+            &[(L::CENTER, HALF_POWER)],
+            &[(L::LEFT, 0.5), (L::RIGHT, 0.5)],
+            &[(L::MONO, 0.5)],
+        ],
+        // The LFE channel and any others are dropped.
+        _ => &[],
+    }
+}
+
+/// The gain from each source channel to each destination channel.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MixMatrix {
+    inputs: usize,
+    outputs: usize,
+    // The gains of each output channel, one row per output.
+    gains: Vec<f32>,
+}
+
+impl MixMatrix {
+    /// A matrix from `inputs` to `outputs` channels with all gains set to `0.0`.
+    pub fn new(inputs: usize, outputs: usize) -> Self {
+        MixMatrix {
+            inputs,
+            outputs,
+            gains: vec![0.0; inputs * outputs],
+        }
+    }
+
+    /// A matrix passing each of the first `min(inputs, outputs)` channels to the channel with
+    /// the same index.
+    pub fn identity(inputs: usize, outputs: usize) -> Self {
+        let mut matrix = Self::new(inputs, outputs);
+        for channel in 0..inputs.min(outputs) {
+            matrix.set(channel, channel, 1.0);
+        }
+        matrix
+    }
+
+    /// The standard mix from the `source` layout to the `destination` layout.
+    ///
+    /// Channels are matched by index if the channel order of either layout is unknown.
+    pub fn between(source: &ChannelLayout, destination: &ChannelLayout) -> Self {
+        match (source.labels(), destination.labels()) {
+            (Some(source), Some(destination)) => Self::between_labels(&source, &destination),
+            _ => Self::identity(
+                source.channel_count() as usize,
+                destination.channel_count() as usize,
+            ),
+        }
+    }
+
+    /// The standard mix from the source channels to the destination channels, given by their
+    /// labels in order.
+    pub fn between_labels(source: &[ChannelLabel], destination: &[ChannelLabel]) -> Self {
+        let mut matrix = Self::new(source.len(), destination.len());
+        let position = |label: ChannelLabel| destination.iter().position(|&l| l == label);
+        for (input, &label) in source.iter().enumerate() {
+            if let Some(output) = position(label) {
+                matrix.set(input, output, 1.0);
+                continue;
+            }
+            let targets = fallbacks(label)
+                .iter()
+                .find(|targets| targets.iter().all(|&(l, _)| position(l).is_some()));
+            for &(target, gain) in targets.map(|t| t.iter()).into_iter().flatten() {
+                if let Some(output) = position(target) {
+                    matrix.set(input, output, gain);
+                }
+            }
+        }
+        matrix
+    }
+
+    /// The number of source channels.
+    pub fn inputs(&self) -> usize {
+        self.inputs
+    }
+
+    /// The number of destination channels.
+    pub fn outputs(&self) -> usize {
+        self.outputs
+    }
+
+    /// The gain from source channel `input` to destination channel `output`.
+    pub fn get(&self, input: usize, output: usize) -> f32 {
+        assert!(
+            input < self.inputs && output < self.outputs,
+            "channel out of range"
+        );
+        self.gains[output * self.inputs + input]
+    }
+
+    /// Sets the gain from source channel `input` to destination channel `output`.
+    pub fn set(&mut self, input: usize, output: usize, gain: f32) {
+        assert!(
+            input < self.inputs && output < self.outputs,
+            "channel out of range"
+        );
+        self.gains[output * self.inputs + input] = gain;
+    }
+
+    /// Scale all gains so that no destination channel can exceed full scale, i.e. so that the
+    /// gains into each destination channel sum to at most `1.0`.
+    pub fn normalize(&mut self) {
+        let max = self
+            .gains
+            .chunks(self.inputs.max(1))
+            .map(|row| row.iter().map(|gain| gain.abs()).sum::<f32>())
+            .fold(0.0, f32::max);
+        if max > 1.0 {
+            for gain in &mut self.gains {
+                *gain /= max;
+            }
+        }
+    }
+
+    /// Mix interleaved frames of the source channels into interleaved frames of the destination
+    /// channels.
+    ///
+    /// Returns `Error::ChannelCountMismatch` unless both buffers hold whole frames and the same
+    /// number of them.
+    pub fn mix_interleaved(&self, input: &[f32], output: &mut [f32]) -> Result<(), Error> {
+        let frames = self.frames(input.len(), output.len())?;
+        for frame in 0..frames {
+            let source = &input[frame * self.inputs..(frame + 1) * self.inputs];
+            let destination = &mut output[frame * self.outputs..(frame + 1) * self.outputs];
+            for (sample, row) in destination.iter_mut().zip(self.gains.chunks(self.inputs)) {
+                *sample = row.iter().zip(source).map(|(gain, s)| gain * s).sum();
+            }
+        }
+        Ok(())
+    }
+
+    /// Mix a buffer per source channel into a buffer per destination channel.
+    ///
+    /// Returns `Error::ChannelCountMismatch` unless there is a buffer for each channel and all
+    /// buffers have the same length.
+    pub fn mix_non_interleaved<I, O>(&self, input: &[I], output: &mut [O]) -> Result<(), Error>
+    where
+        I: AsRef<[f32]>,
+        O: AsMut<[f32]>,
+    {
+        if self.inputs == 0 || input.len() != self.inputs || output.len() != self.outputs {
+            return Err(Error::ChannelCountMismatch);
+        }
+        let frames = match input.first() {
+            Some(channel) => channel.as_ref().len(),
+            None => output
+                .first_mut()
+                .map_or(0, |channel| channel.as_mut().len()),
+        };
+        if input.iter().any(|channel| channel.as_ref().len() != frames)
+            || output
+                .iter_mut()
+                .any(|channel| channel.as_mut().len() != frames)
+        {
+            return Err(Error::ChannelCountMismatch);
+        }
+        for (destination, row) in output.iter_mut().zip(self.gains.chunks(self.inputs)) {
+            let destination = destination.as_mut();
+            for sample in destination.iter_mut() {
+                *sample = 0.0;
+            }
+            for (source, &gain) in input.iter().zip(row) {
+                if gain == 0.0 {
+                    continue;
+                }
+                for (sample, s) in destination.iter_mut().zip(source.as_ref()) {
+                    *sample += gain * s;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Mix the frames of `input` into `output`, either of which may be interleaved or not.
+    ///
+    /// Allocates nothing, so it may be used inside a render callback. `output` is resized to the
+    /// number of frames of `input`. Returns `Error::ChannelCountMismatch` if the channel counts
+    /// don't match the matrix, or if `output` can't hold as many frames as `input`.
+    pub fn mix(
+        &self,
+        input: &AudioBufferList<f32>,
+        output: &mut AudioBufferList<f32>,
+    ) -> Result<(), Error> {
+        if self.inputs == 0
+            || self.outputs == 0
+            || input.channels() != self.inputs
+            || output.channels() != self.outputs
+            || output.capacity() < input.frames()
+        {
+            return Err(Error::ChannelCountMismatch);
+        }
+        output.reset(input.frames());
+        if input.is_interleaved() && output.is_interleaved() {
+            return self.mix_interleaved(input.buffer(0), output.buffer_mut(0));
+        }
+        let read = |channel: usize, frame: usize| {
+            if input.is_interleaved() {
+                input.buffer(0)[frame * self.inputs + channel]
+            } else {
+                input.buffer(channel)[frame]
+            }
+        };
+        for frame in 0..input.frames() {
+            for (channel, row) in self.gains.chunks(self.inputs).enumerate() {
+                let sample = row
+                    .iter()
+                    .enumerate()
+                    .map(|(source, gain)| gain * read(source, frame))
+                    .sum();
+                if output.is_interleaved() {
+                    output.buffer_mut(0)[frame * self.outputs + channel] = sample;
+                } else {
+                    output.buffer_mut(channel)[frame] = sample;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn frames(&self, input_len: usize, output_len: usize) -> Result<usize, Error> {
+        if self.inputs == 0 || self.outputs == 0 {
+            return Err(Error::ChannelCountMismatch);
+        }
+        let frames = input_len / self.inputs;
+        if input_len != frames * self.inputs || output_len != frames * self.outputs {
+            return Err(Error::ChannelCountMismatch);
+        }
+        Ok(frames)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::audio_unit::channel_layout::ChannelLayoutTag;
+
+    fn layout(tag: ChannelLayoutTag) -> ChannelLayout {
+        ChannelLayout::from_tag(tag)
+    }
+
+    #[test]
+    fn test_standard_matrices() {
+        let stereo = layout(ChannelLayoutTag::STEREO);
+        let mono = layout(ChannelLayoutTag::MONO);
+
+        // L R C LFE Ls Rs to L R.
+        let downmix = MixMatrix::between(&layout(ChannelLayoutTag::MPEG_5_1_A), &stereo);
+        assert_eq!((downmix.inputs(), downmix.outputs()), (6, 2));
+        let left: Vec<f32> = (0..6).map(|input| downmix.get(input, 0)).collect();
+        let right: Vec<f32> = (0..6).map(|input| downmix.get(input, 1)).collect();
+        assert_eq!(left, [1.0, 0.0, HALF_POWER, 0.0, HALF_POWER, 0.0]);
+        assert_eq!(right, [0.0, 1.0, HALF_POWER, 0.0, 0.0, HALF_POWER]);
+
+        let to_mono = MixMatrix::between(&stereo, &mono);
+        assert_eq!(
+            (to_mono.get(0, 0), to_mono.get(1, 0)),
+            (HALF_POWER, HALF_POWER)
+        );
+
+        // A mono source is spread over left and right, and the surrounds of a quad layout are
+        // left silent.
+        let upmix = MixMatrix::between(&mono, &layout(ChannelLayoutTag::QUADRAPHONIC));
+        let gains: Vec<f32> = (0..4).map(|output| upmix.get(0, output)).collect();
+        assert_eq!(gains, [HALF_POWER, HALF_POWER, 0.0, 0.0]);
+
+        let identity = MixMatrix::between(&stereo, &stereo);
+        assert_eq!(identity, MixMatrix::identity(2, 2));
+        let unknown = MixMatrix::between(&layout(ChannelLayoutTag::unknown(3)), &stereo);
+        assert_eq!(unknown, MixMatrix::identity(3, 2));
+
+        let mut normalized = downmix.clone();
+        normalized.normalize();
+        let sum: f32 = (0..6).map(|input| normalized.get(input, 0)).sum();
+        assert!((sum - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_mix_buffers() {
+        let matrix = MixMatrix::between(
+            &layout(ChannelLayoutTag::MPEG_3_0_A),
+            &layout(ChannelLayoutTag::STEREO),
+        );
+        let c = HALF_POWER;
+
+        let input = [1.0, 0.0, 1.0, 0.0, 0.5, 0.0];
+        let mut output = [0.0; 4];
+        matrix.mix_interleaved(&input, &mut output).unwrap();
+        assert_eq!(output, [1.0 + c, c, 0.0, 0.5]);
+        assert!(matrix.mix_interleaved(&input[..5], &mut output).is_err());
+
+        let channels = [[1.0, 0.0], [0.0, 0.5], [1.0, 0.0]];
+        let mut mixed = [[9.0; 2]; 2];
+        matrix.mix_non_interleaved(&channels, &mut mixed).unwrap();
+        assert_eq!(mixed, [[1.0 + c, 0.0], [c, 0.5]]);
+        assert!(matrix
+            .mix_non_interleaved(&channels[..2], &mut mixed)
+            .is_err());
+
+        let mut source = AudioBufferList::<f32>::new(3, 2);
+        source.buffer_mut(0).copy_from_slice(&input);
+        for &interleaved in &[true, false] {
+            let mut destination = if interleaved {
+                AudioBufferList::<f32>::new(2, 4)
+            } else {
+                AudioBufferList::<f32>::new_non_interleaved(2, 4)
+            };
+            matrix.mix(&source, &mut destination).unwrap();
+            assert_eq!(destination.frames(), 2);
+            if interleaved {
+                assert_eq!(destination.buffer(0), &output[..]);
+            } else {
+                assert_eq!(destination.buffer(0), &mixed[0][..]);
+                assert_eq!(destination.buffer(1), &mixed[1][..]);
+            }
+        }
+        let mut too_small = AudioBufferList::<f32>::new(2, 1);
+        assert!(matrix.mix(&source, &mut too_small).is_err());
+    }
+}
@@ -0,0 +1,525 @@
+//! Typed wrappers around the effect units provided by Apple.
+//!
+//! Each wrapper owns an [**AudioUnit**](../struct.AudioUnit) of the matching `EffectType` and
+//! replaces its numeric parameter IDs with getters and setters. The range of each parameter is
+//! exposed as a [**ParameterRange**](../parameter/struct.ParameterRange) constant, and setters
+//! return `Error::ParameterOutOfRange` rather than passing invalid values to the OS.
+//!
+//! The wrappers deref to **AudioUnit**, so the unit can still be configured, initialized and
+//! started as usual.
+//!
+//! ```no_run
+//! # use coreaudio::audio_unit::effects::Reverb2;
+//! # fn main() -> Result<(), coreaudio::Error> {
+//! let mut reverb = Reverb2::new()?;
+//! reverb.set_dry_wet_mix(30.0)?;
+//! reverb.set_decay_time_at_0_hz(Reverb2::DECAY_TIME_AT_0_HZ.max)?;
+//! assert!(!Reverb2::GAIN.contains(100.0));
+//! reverb.initialize()?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The parameters are described in the Audio Unit Parameters Reference
+//! [here](https://developer.apple.com/documentation/audiotoolbox/audio_unit_parameters).
+
+use std::ops::{Deref, DerefMut};
+
+use super::{AudioUnit, EffectType, Element, ParameterRange, Scope};
+use crate::error::Error;
+
+macro_rules! effect {
+    (
+        $(#[$meta:meta])*
+        $name:ident = $subtype:expr;
+        $(
+            $(#[$param_meta:meta])*
+            $range:ident = $id:expr, $get:ident, $set:ident: ($min:expr, $max:expr, $default:expr);
+        )*
+    ) => {
+        $(#[$meta])*
+        pub struct $name {
+            unit: AudioUnit,
+        }
+
+        impl $name {
+            $(
+                $(#[$param_meta])*
+                pub const $range: ParameterRange = ParameterRange {
+                    min: $min,
+                    max: $max,
+                    default: $default,
+                };
+            )*
+
+            /// Create a new instance of the effect unit.
+            pub fn new() -> Result<Self, Error> {
+                let unit = AudioUnit::new($subtype)?;
+                Ok($name { unit })
+            }
+
+            /// Unwrap the underlying **AudioUnit**.
+            pub fn into_inner(self) -> AudioUnit {
+                self.unit
+            }
+
+            $(
+                $(#[$param_meta])*
+                pub fn $get(&self) -> Result<f32, Error> {
+                    self.unit.get_parameter($id, Scope::Global, Element::Output)
+                }
+
+                $(#[$param_meta])*
+                ///
+                /// Returns `Error::ParameterOutOfRange` for values outside of the range constant.
+                pub fn $set(&mut self, value: f32) -> Result<(), Error> {
+                    let value = Self::$range.check(value)?;
+                    self.unit.set_parameter($id, Scope::Global, Element::Output, value, 0)
+                }
+            )*
+        }
+
+        impl Deref for $name {
+            type Target = AudioUnit;
+            fn deref(&self) -> &AudioUnit {
+                &self.unit
+            }
+        }
+
+        impl DerefMut for $name {
+            fn deref_mut(&mut self) -> &mut AudioUnit {
+                &mut self.unit
+            }
+        }
+    };
+}
+
+effect! {
+    /// A delay line with feedback and a low-pass filter, wrapping `EffectType::Delay`.
+    ///
+    /// **Available** in OS X v10.2 and later and iOS 2.0 and later.
+    Delay = EffectType::Delay;
+    /// The balance between the dry and the delayed signal, in percent.
+    WET_DRY_MIX = 0, wet_dry_mix, set_wet_dry_mix: (0.0, 100.0, 50.0);
+    /// The delay time, in seconds.
+    DELAY_TIME = 1, delay_time, set_delay_time: (0.0, 2.0, 1.0);
+    /// The amount of the delayed signal fed back into the delay line, in percent.
+    FEEDBACK = 2, feedback, set_feedback: (-100.0, 100.0, 50.0);
+}
+
+// The ID of the cutoff parameter, whose range depends on the sample rate.
+const DELAY_LOWPASS_CUTOFF: u32 = 3;
+
+impl Delay {
+    /// The range of the cutoff frequency of the low-pass filter in the feedback path, which ends
+    /// at half of the sample rate of the unit.
+    pub fn lowpass_cutoff_range(&self) -> Result<ParameterRange, Error> {
+        frequency_range(&self.unit, 10.0, 15_000.0)
+    }
+
+    /// The cutoff frequency of the low-pass filter in the feedback path, in Hz.
+    pub fn lowpass_cutoff(&self) -> Result<f32, Error> {
+        self.unit
+            .get_parameter(DELAY_LOWPASS_CUTOFF, Scope::Global, Element::Output)
+    }
+
+    /// Set the cutoff frequency of the low-pass filter in the feedback path, in Hz.
+    ///
+    /// Returns `Error::ParameterOutOfRange` for values outside of
+    /// [**lowpass_cutoff_range**](./struct.Delay#method.lowpass_cutoff_range).
+    pub fn set_lowpass_cutoff(&mut self, cutoff: f32) -> Result<(), Error> {
+        let cutoff = self.lowpass_cutoff_range()?.check(cutoff)?;
+        self.unit.set_parameter(
+            DELAY_LOWPASS_CUTOFF,
+            Scope::Global,
+            Element::Output,
+            cutoff,
+            0,
+        )
+    }
+}
+
+effect! {
+    /// A reverb with controllable decay times and reflections, wrapping `EffectType::Reverb2`.
+    ///
+    /// **Available** in OS X v10.7 and later and iOS 5.0 and later.
+    Reverb2 = EffectType::Reverb2;
+    /// The balance between the dry and the reverberated signal, in percent.
+    DRY_WET_MIX = 0, dry_wet_mix, set_dry_wet_mix: (0.0, 100.0, 100.0);
+    /// The gain of the output, in decibels.
+    GAIN = 1, gain, set_gain: (-20.0, 20.0, 0.0);
+    /// The minimum delay time of the reflections, in seconds.
+    MIN_DELAY_TIME = 2, min_delay_time, set_min_delay_time: (0.0001, 1.0, 0.008);
+    /// The maximum delay time of the reflections, in seconds.
+    MAX_DELAY_TIME = 3, max_delay_time, set_max_delay_time: (0.0001, 1.0, 0.050);
+    /// The time for the reverb to decay by 60 dB at 0 Hz, in seconds.
+    DECAY_TIME_AT_0_HZ = 4, decay_time_at_0_hz, set_decay_time_at_0_hz: (0.001, 20.0, 1.0);
+    /// The time for the reverb to decay by 60 dB at the Nyquist frequency, in seconds.
+    DECAY_TIME_AT_NYQUIST = 5, decay_time_at_nyquist, set_decay_time_at_nyquist:
+        (0.001, 20.0, 0.5);
+    /// The number of times the reflections are randomized.
+    RANDOMIZE_REFLECTIONS = 6, randomize_reflections, set_randomize_reflections:
+        (1.0, 1000.0, 1.0);
+}
+
+effect! {
+    /// A compressor and expander, wrapping `EffectType::DynamicsProcessor`.
+    ///
+    /// **Available** in OS X v10.3 and later and iOS 5.0 and later.
+    DynamicsProcessor = EffectType::DynamicsProcessor;
+    /// The level above which the signal is compressed, in decibels.
+    THRESHOLD = 0, threshold, set_threshold: (-40.0, 20.0, -20.0);
+    /// The headroom above the threshold, in decibels.
+    HEAD_ROOM = 1, head_room, set_head_room: (0.1, 40.0, 5.0);
+    /// The ratio by which the signal below the expansion threshold is expanded.
+    EXPANSION_RATIO = 2, expansion_ratio, set_expansion_ratio: (1.0, 50.0, 2.0);
+    /// The level below which the signal is expanded, in decibels.
+    EXPANSION_THRESHOLD = 3, expansion_threshold, set_expansion_threshold: (-100.0, 20.0, -100.0);
+    /// The time for the compressor to react to a rising level, in seconds.
+    ATTACK_TIME = 4, attack_time, set_attack_time: (0.0001, 0.2, 0.001);
+    /// The time for the compressor to recover from a falling level, in seconds.
+    RELEASE_TIME = 5, release_time, set_release_time: (0.01, 3.0, 0.05);
+    /// The gain applied to the output, in decibels.
+    OVERALL_GAIN = 6, overall_gain, set_overall_gain: (-40.0, 40.0, 0.0);
+}
+
+// The IDs of the read-only meter parameters.
+const COMPRESSION_AMOUNT: u32 = 1000;
+const INPUT_AMPLITUDE: u32 = 2000;
+const OUTPUT_AMPLITUDE: u32 = 3000;
+
+impl DynamicsProcessor {
+    /// The amount of compression currently applied, in decibels.
+    pub fn compression_amount(&self) -> Result<f32, Error> {
+        self.unit
+            .get_parameter(COMPRESSION_AMOUNT, Scope::Global, Element::Output)
+    }
+
+    /// The current level of the input, in decibels.
+    pub fn input_amplitude(&self) -> Result<f32, Error> {
+        self.unit
+            .get_parameter(INPUT_AMPLITUDE, Scope::Global, Element::Output)
+    }
+
+    /// The current level of the output, in decibels.
+    pub fn output_amplitude(&self) -> Result<f32, Error> {
+        self.unit
+            .get_parameter(OUTPUT_AMPLITUDE, Scope::Global, Element::Output)
+    }
+}
+
+effect! {
+    /// A peak limiter, wrapping `EffectType::PeakLimiter`.
+    ///
+    /// **Available** in OS X v10.2 and later and iOS 2.0 and later.
+    PeakLimiter = EffectType::PeakLimiter;
+    /// The time for the limiter to react to a rising level, in seconds.
+    ATTACK_TIME = 0, attack_time, set_attack_time: (0.001, 0.03, 0.012);
+    /// The time for the limiter to recover from a falling level, in seconds.
+    DECAY_TIME = 1, decay_time, set_decay_time: (0.001, 0.06, 0.024);
+    /// The gain applied to the input before limiting, in decibels.
+    PRE_GAIN = 2, pre_gain, set_pre_gain: (-40.0, 40.0, 0.0);
+}
+
+effect! {
+    /// A multi-band equalizer, wrapping `EffectType::NBandEQ`.
+    ///
+    /// Parameters of the individual bands are reached through
+    /// [**band**](./struct.NBandEq#method.band).
+    ///
+    /// **Available** in OS X v10.9 and later and iOS 5.0 and later.
+    NBandEq = EffectType::NBandEQ;
+    /// The gain applied to all bands, in decibels.
+    GLOBAL_GAIN = 0, global_gain, set_global_gain: (-96.0, 24.0, 0.0);
+}
+
+// The IDs of the per-band parameters, to which the band index is added.
+const BAND_BYPASS: u32 = 1000;
+const BAND_FILTER_TYPE: u32 = 2000;
+const BAND_FREQUENCY: u32 = 3000;
+const BAND_GAIN: u32 = 4000;
+const BAND_BANDWIDTH: u32 = 5000;
+
+// kAUNBandEQProperty_NumberOfBands and kAUNBandEQProperty_MaxNumberOfBands.
+const NUMBER_OF_BANDS: u32 = 2200;
+const MAX_NUMBER_OF_BANDS: u32 = 2201;
+
+impl NBandEq {
+    /// The gain of a band, in decibels.
+    pub const BAND_GAIN: ParameterRange = ParameterRange {
+        min: -96.0,
+        max: 24.0,
+        default: 0.0,
+    };
+    /// The bandwidth of a band, in octaves.
+    pub const BANDWIDTH: ParameterRange = ParameterRange {
+        min: 0.05,
+        max: 5.0,
+        default: 0.5,
+    };
+
+    /// The range of the centre (or cutoff) frequency of a band, which ends at half of the sample
+    /// rate of the unit.
+    pub fn frequency_range(&self) -> Result<ParameterRange, Error> {
+        band_frequency_range(&self.unit)
+    }
+
+    /// The number of bands in use.
+    pub fn number_of_bands(&self) -> Result<u32, Error> {
+        self.unit
+            .get_property(NUMBER_OF_BANDS, Scope::Global, Element::Output)
+    }
+
+    /// Set the number of bands in use.
+    ///
+    /// This must be done before the unit is initialized.
+    pub fn set_number_of_bands(&mut self, bands: u32) -> Result<(), Error> {
+        self.unit.set_property(
+            NUMBER_OF_BANDS,
+            Scope::Global,
+            Element::Output,
+            Some(&bands),
+        )
+    }
+
+    /// The maximum number of bands supported by the unit.
+    pub fn max_number_of_bands(&self) -> Result<u32, Error> {
+        self.unit
+            .get_property(MAX_NUMBER_OF_BANDS, Scope::Global, Element::Output)
+    }
+
+    /// The parameters of the band at `index`.
+    ///
+    /// Indices at or above [**number_of_bands**](./struct.NBandEq#method.number_of_bands) are
+    /// rejected by the unit when the band's parameters are accessed.
+    pub fn band(&mut self, index: u32) -> Band<'_> {
+        Band {
+            unit: &mut self.unit,
+            index,
+        }
+    }
+}
+
+/// A single band of an [**NBandEq**](./struct.NBandEq).
+pub struct Band<'a> {
+    unit: &'a mut AudioUnit,
+    index: u32,
+}
+
+impl<'a> Band<'a> {
+    /// The index of the band.
+    pub fn index(&self) -> u32 {
+        self.index
+    }
+
+    fn get(&self, id: u32) -> Result<f32, Error> {
+        self.unit
+            .get_parameter(id + self.index, Scope::Global, Element::Output)
+    }
+
+    fn set(&mut self, id: u32, value: f32) -> Result<(), Error> {
+        self.unit
+            .set_parameter(id + self.index, Scope::Global, Element::Output, value, 0)
+    }
+
+    /// Whether the band is bypassed.
+    pub fn is_bypassed(&self) -> Result<bool, Error> {
+        self.get(BAND_BYPASS).map(|value| value != 0.0)
+    }
+
+    /// Bypass or enable the band.
+    pub fn set_bypass(&mut self, bypass: bool) -> Result<(), Error> {
+        self.set(BAND_BYPASS, if bypass { 1.0 } else { 0.0 })
+    }
+
+    /// The type of filter used by the band.
+    ///
+    /// Returns `None` for filter types unknown to this crate.
+    pub fn filter_type(&self) -> Result<Option<FilterType>, Error> {
+        self.get(BAND_FILTER_TYPE)
+            .map(|value| FilterType::from_u32(value as u32))
+    }
+
+    /// Set the type of filter used by the band.
+    pub fn set_filter_type(&mut self, filter_type: FilterType) -> Result<(), Error> {
+        self.set(BAND_FILTER_TYPE, filter_type as u32 as f32)
+    }
+
+    /// The centre (or cutoff) frequency of the band, in Hz.
+    pub fn frequency(&self) -> Result<f32, Error> {
+        self.get(BAND_FREQUENCY)
+    }
+
+    /// Set the centre (or cutoff) frequency of the band, in Hz.
+    ///
+    /// Returns `Error::ParameterOutOfRange` for values outside of
+    /// [**NBandEq::frequency_range**](./struct.NBandEq#method.frequency_range).
+    pub fn set_frequency(&mut self, frequency: f32) -> Result<(), Error> {
+        let frequency = band_frequency_range(self.unit)?.check(frequency)?;
+        self.set(BAND_FREQUENCY, frequency)
+    }
+
+    /// The gain of the band, in decibels.
+    pub fn gain(&self) -> Result<f32, Error> {
+        self.get(BAND_GAIN)
+    }
+
+    /// Set the gain of the band, in decibels.
+    ///
+    /// Returns `Error::ParameterOutOfRange` for values outside of `NBandEq::BAND_GAIN`.
+    pub fn set_gain(&mut self, gain: f32) -> Result<(), Error> {
+        let gain = NBandEq::BAND_GAIN.check(gain)?;
+        self.set(BAND_GAIN, gain)
+    }
+
+    /// The bandwidth of the band, in octaves.
+    pub fn bandwidth(&self) -> Result<f32, Error> {
+        self.get(BAND_BANDWIDTH)
+    }
+
+    /// Set the bandwidth of the band, in octaves.
+    ///
+    /// Returns `Error::ParameterOutOfRange` for values outside of `NBandEq::BANDWIDTH`.
+    pub fn set_bandwidth(&mut self, bandwidth: f32) -> Result<(), Error> {
+        let bandwidth = NBandEq::BANDWIDTH.check(bandwidth)?;
+        self.set(BAND_BANDWIDTH, bandwidth)
+    }
+}
+
+// A range of frequencies ending at the Nyquist frequency of the unit's sample rate.
+fn frequency_range(unit: &AudioUnit, min: f32, default: f32) -> Result<ParameterRange, Error> {
+    let nyquist = (unit.sample_rate()? / 2.0) as f32;
+    Ok(ParameterRange {
+        min,
+        max: nyquist,
+        default: default.min(nyquist),
+    })
+}
+
+fn band_frequency_range(unit: &AudioUnit) -> Result<ParameterRange, Error> {
+    frequency_range(unit, 20.0, 1_000.0)
+}
+
+/// The filter types of an [**NBandEq**](./struct.NBandEq) band.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum FilterType {
+    Parametric = 0,
+    ButterworthLowPass = 1,
+    ButterworthHighPass = 2,
+    ResonantLowPass = 3,
+    ResonantHighPass = 4,
+    BandPass = 5,
+    BandStop = 6,
+    LowShelf = 7,
+    HighShelf = 8,
+    ResonantLowShelf = 9,
+    ResonantHighShelf = 10,
+}
+
+impl FilterType {
+    /// Convert the `u32` value of a filter type parameter to a `FilterType`.
+    pub fn from_u32(u: u32) -> Option<Self> {
+        let filter_type = match u {
+            0 => FilterType::Parametric,
+            1 => FilterType::ButterworthLowPass,
+            2 => FilterType::ButterworthHighPass,
+            3 => FilterType::ResonantLowPass,
+            4 => FilterType::ResonantHighPass,
+            5 => FilterType::BandPass,
+            6 => FilterType::BandStop,
+            7 => FilterType::LowShelf,
+            8 => FilterType::HighShelf,
+            9 => FilterType::ResonantLowShelf,
+            10 => FilterType::ResonantHighShelf,
+            _ => return None,
+        };
+        Some(filter_type)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_ranges() {
+        let ranges = [
+            Delay::WET_DRY_MIX,
+            Delay::DELAY_TIME,
+            Delay::FEEDBACK,
+            Reverb2::DRY_WET_MIX,
+            Reverb2::GAIN,
+            Reverb2::MIN_DELAY_TIME,
+            Reverb2::MAX_DELAY_TIME,
+            Reverb2::DECAY_TIME_AT_0_HZ,
+            Reverb2::DECAY_TIME_AT_NYQUIST,
+            Reverb2::RANDOMIZE_REFLECTIONS,
+            DynamicsProcessor::THRESHOLD,
+            DynamicsProcessor::HEAD_ROOM,
+            DynamicsProcessor::EXPANSION_RATIO,
+            DynamicsProcessor::EXPANSION_THRESHOLD,
+            DynamicsProcessor::ATTACK_TIME,
+            DynamicsProcessor::RELEASE_TIME,
+            DynamicsProcessor::OVERALL_GAIN,
+            PeakLimiter::ATTACK_TIME,
+            PeakLimiter::DECAY_TIME,
+            PeakLimiter::PRE_GAIN,
+            NBandEq::GLOBAL_GAIN,
+            NBandEq::BAND_GAIN,
+            NBandEq::BANDWIDTH,
+        ];
+        for range in ranges.iter() {
+            assert!(range.min < range.max);
+            assert!(range.contains(range.default));
+        }
+
+        assert_eq!(Reverb2::GAIN.check(-6.0).unwrap(), -6.0);
+        match Reverb2::GAIN.check(21.0) {
+            Err(Error::ParameterOutOfRange) => (),
+            other => panic!("expected an out of range error, got {:?}", other),
+        }
+        assert_eq!(Reverb2::GAIN.clamp(21.0), 20.0);
+    }
+
+    #[test]
+    fn test_filter_type() {
+        for u in 0..11 {
+            assert_eq!(FilterType::from_u32(u).unwrap() as u32, u);
+        }
+        assert_eq!(FilterType::from_u32(11), None);
+    }
+
+    #[test]
+    fn test_reverb() {
+        let mut reverb = Reverb2::new().unwrap();
+        reverb.set_dry_wet_mix(25.0).unwrap();
+        assert_eq!(reverb.dry_wet_mix().unwrap(), 25.0);
+        assert!(reverb.set_gain(-21.0).is_err());
+        assert_eq!(reverb.gain().unwrap(), Reverb2::GAIN.default);
+    }
+
+    #[test]
+    fn test_delay_cutoff_range() {
+        let mut delay = Delay::new().unwrap();
+        delay.set_sample_rate(96_000.0).unwrap();
+        let range = delay.lowpass_cutoff_range().unwrap();
+        assert_eq!(range.max, 48_000.0);
+        delay.set_lowpass_cutoff(40_000.0).unwrap();
+        assert_eq!(delay.lowpass_cutoff().unwrap(), 40_000.0);
+        assert!(delay.set_lowpass_cutoff(50_000.0).is_err());
+        assert!(delay.set_lowpass_cutoff(5.0).is_err());
+    }
+
+    #[test]
+    fn test_n_band_eq() {
+        let mut eq = NBandEq::new().unwrap();
+        eq.set_number_of_bands(2).unwrap();
+        eq.initialize().unwrap();
+        let mut band = eq.band(1);
+        band.set_filter_type(FilterType::HighShelf).unwrap();
+        band.set_frequency(8_000.0).unwrap();
+        assert_eq!(band.filter_type().unwrap(), Some(FilterType::HighShelf));
+        assert_eq!(band.frequency().unwrap(), 8_000.0);
+        assert!(band.set_bandwidth(10.0).is_err());
+    }
+}
@@ -0,0 +1,528 @@
+//! A processing graph of connected **AudioUnit**s.
+//!
+//! A [**Graph**](./struct.Graph) owns a set of **AudioUnit** nodes and connects the output bus of
+//! one node to the input bus of another with `kAudioUnitProperty_MakeConnection`, so that
+//! rendering a node pulls audio through all of the nodes upstream of it.
+//!
+//! ```no_run
+//! # use coreaudio::audio_unit::graph::Graph;
+//! # use coreaudio::audio_unit::render_callback::{data, Args};
+//! # use coreaudio::audio_unit::{AudioUnit, EffectType, IOType};
+//! # fn main() -> Result<(), coreaudio::Error> {
+//! let mut graph = Graph::new();
+//! let delay = graph.add_node(AudioUnit::new(EffectType::Delay)?);
+//! let output = graph.add_node(AudioUnit::new(IOType::DefaultOutput)?);
+//! graph.connect(delay, 0, output, 0)?;
+//! // The first node is fed by a render callback.
+//! graph
+//!     .node_mut(delay)
+//!     .set_render_callback(|_: Args<data::NonInterleaved<f32>>| Ok(()))?;
+//! graph.start()?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Cycles and doubly connected inputs are rejected when connecting, and unconnected inputs when
+//! initializing, before any of the units are touched.
+
+use std::mem;
+use std::ptr;
+
+use super::{property, AudioUnit, Bus, Scope};
+use crate::error::Error;
+use crate::try_os_status;
+use sys;
+
+/// Identifies a node within a [**Graph**](./struct.Graph).
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct NodeId(usize);
+
+impl NodeId {
+    /// The index of the node, in the order in which nodes were added.
+    pub fn index(&self) -> usize {
+        self.0
+    }
+}
+
+/// A connection from an output bus of one node to an input bus of another.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Connection {
+    pub source: NodeId,
+    pub source_bus: u32,
+    pub dest: NodeId,
+    pub dest_bus: u32,
+}
+
+/// The connections between nodes, independent of the audio units themselves.
+#[derive(Clone, Debug, Default)]
+struct Topology {
+    node_count: usize,
+    connections: Vec<Connection>,
+}
+
+impl Topology {
+    fn add_node(&mut self) -> NodeId {
+        self.node_count += 1;
+        NodeId(self.node_count - 1)
+    }
+
+    fn input(&self, dest: NodeId, dest_bus: u32) -> Option<&Connection> {
+        self.connections
+            .iter()
+            .find(|c| c.dest == dest && c.dest_bus == dest_bus)
+    }
+
+    fn has_outputs(&self, node: NodeId) -> bool {
+        self.connections.iter().any(|c| c.source == node)
+    }
+
+    // The input buses of `node`, out of `bus_count`, that are neither connected nor `fed`.
+    fn unconnected_inputs(
+        &self,
+        node: NodeId,
+        bus_count: u32,
+        fed: impl Fn(u32) -> bool,
+    ) -> Vec<(NodeId, u32)> {
+        (0..bus_count)
+            .filter(|&bus| !fed(bus) && self.input(node, bus).is_none())
+            .map(|bus| (node, bus))
+            .collect()
+    }
+
+    // Whether `to` can be reached from `from` by following connections downstream.
+    fn reaches(&self, from: NodeId, to: NodeId) -> bool {
+        let mut visited = vec![false; self.node_count];
+        let mut stack = vec![from];
+        while let Some(node) = stack.pop() {
+            if node == to {
+                return true;
+            }
+            if mem::replace(&mut visited[node.0], true) {
+                continue;
+            }
+            stack.extend(
+                self.connections
+                    .iter()
+                    .filter(|c| c.source == node)
+                    .map(|c| c.dest),
+            );
+        }
+        false
+    }
+
+    fn check(&self, connection: &Connection) -> Result<(), Error> {
+        if self.input(connection.dest, connection.dest_bus).is_some() {
+            return Err(Error::BusAlreadyConnected);
+        }
+        if self.reaches(connection.dest, connection.source) {
+            return Err(Error::GraphCycle);
+        }
+        Ok(())
+    }
+
+    fn disconnect(&mut self, dest: NodeId, dest_bus: u32) -> Option<Connection> {
+        let position = self
+            .connections
+            .iter()
+            .position(|c| c.dest == dest && c.dest_bus == dest_bus)?;
+        Some(self.connections.remove(position))
+    }
+
+    // The nodes ordered so that every node comes after the nodes connected to its inputs.
+    fn order(&self) -> Result<Vec<NodeId>, Error> {
+        let mut inputs = vec![0; self.node_count];
+        for connection in &self.connections {
+            inputs[connection.dest.0] += 1;
+        }
+        let mut ready: Vec<NodeId> = (0..self.node_count)
+            .rev()
+            .filter(|&i| inputs[i] == 0)
+            .map(NodeId)
+            .collect();
+        let mut order = Vec::with_capacity(self.node_count);
+        while let Some(node) = ready.pop() {
+            order.push(node);
+            for connection in self.connections.iter().filter(|c| c.source == node) {
+                inputs[connection.dest.0] -= 1;
+                if inputs[connection.dest.0] == 0 {
+                    ready.push(connection.dest);
+                }
+            }
+        }
+        if order.len() != self.node_count {
+            return Err(Error::GraphCycle);
+        }
+        Ok(order)
+    }
+}
+
+/// A set of **AudioUnit** nodes and the connections between them.
+///
+/// Starting the graph starts its sink nodes (those whose outputs are not connected) that are I/O
+/// units, which pull audio from the rest of the graph. The graph is stopped when dropped.
+#[derive(Default)]
+pub struct Graph {
+    nodes: Vec<AudioUnit>,
+    topology: Topology,
+}
+
+impl Graph {
+    /// An empty graph.
+    pub fn new() -> Self {
+        Graph::default()
+    }
+
+    /// Add an **AudioUnit** to the graph.
+    pub fn add_node(&mut self, unit: AudioUnit) -> NodeId {
+        self.nodes.push(unit);
+        self.topology.add_node()
+    }
+
+    /// The number of nodes in the graph.
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// The **AudioUnit** of a node.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the node does not belong to this graph.
+    pub fn node(&self, node: NodeId) -> &AudioUnit {
+        &self.nodes[node.0]
+    }
+
+    /// The **AudioUnit** of a node.
+    ///
+    /// Stopping, uninitializing or reconnecting the unit directly bypasses the checks of the graph.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the node does not belong to this graph.
+    pub fn node_mut(&mut self, node: NodeId) -> &mut AudioUnit {
+        &mut self.nodes[node.0]
+    }
+
+    /// The connections between the nodes of the graph.
+    pub fn connections(&self) -> &[Connection] {
+        &self.topology.connections
+    }
+
+    /// Connect the output bus `source_bus` of `source` to the input bus `dest_bus` of `dest`.
+    ///
+    /// Returns `Error::BusAlreadyConnected` if the input bus is already connected,
+    /// `Error::GraphCycle` if the connection would create a cycle, and
+    /// `Error::StreamFormatMismatch` if the output and input stream formats differ.
+    ///
+    /// # Panics
+    ///
+    /// Panics if either node does not belong to this graph.
+    pub fn connect(
+        &mut self,
+        source: NodeId,
+        source_bus: impl Into<Bus>,
+        dest: NodeId,
+        dest_bus: impl Into<Bus>,
+    ) -> Result<(), Error> {
+        let connection = Connection {
+            source,
+            source_bus: source_bus.into().0,
+            dest,
+            dest_bus: dest_bus.into().0,
+        };
+        self.topology.check(&connection)?;
+
+        let output = self.nodes[source.0]
+            .get::<property::StreamFormat>(Scope::Output, connection.source_bus)?;
+        let input =
+            self.nodes[dest.0].get::<property::StreamFormat>(Scope::Input, connection.dest_bus)?;
+        if !formats_match(&output, &input) {
+            return Err(Error::StreamFormatMismatch);
+        }
+
+        let sys_connection = sys::AudioUnitConnection {
+            sourceAudioUnit: self.nodes[source.0].instance,
+            sourceOutputNumber: connection.source_bus,
+            destInputNumber: connection.dest_bus,
+        };
+        self.nodes[dest.0].set_property(
+            sys::kAudioUnitProperty_MakeConnection,
+            Scope::Input,
+            connection.dest_bus,
+            Some(&sys_connection),
+        )?;
+        self.topology.connections.push(connection);
+        Ok(())
+    }
+
+    /// Disconnect the input bus `dest_bus` of `dest`, returning the removed connection.
+    pub fn disconnect(
+        &mut self,
+        dest: NodeId,
+        dest_bus: impl Into<Bus>,
+    ) -> Result<Option<Connection>, Error> {
+        let dest_bus = dest_bus.into().0;
+        if self.topology.input(dest, dest_bus).is_none() {
+            return Ok(None);
+        }
+        let sys_connection = sys::AudioUnitConnection {
+            sourceAudioUnit: ptr::null_mut(),
+            sourceOutputNumber: 0,
+            destInputNumber: dest_bus,
+        };
+        self.nodes[dest.0].set_property(
+            sys::kAudioUnitProperty_MakeConnection,
+            Scope::Input,
+            dest_bus,
+            Some(&sys_connection),
+        )?;
+        Ok(self.topology.disconnect(dest, dest_bus))
+    }
+
+    /// The input buses that are neither connected nor fed by a render callback, as pairs of node
+    /// and bus.
+    ///
+    /// A render callback feeds the first input bus only.
+    pub fn unconnected_inputs(&self) -> Result<Vec<(NodeId, u32)>, Error> {
+        let mut unconnected = Vec::new();
+        for (index, unit) in self.nodes.iter().enumerate() {
+            let node = NodeId(index);
+            let bus_count = unit.element_count(Scope::Input)?;
+            let fed = |bus| bus == 0 && unit.maybe_render_callback.is_some();
+            unconnected.extend(self.topology.unconnected_inputs(node, bus_count, fed));
+        }
+        Ok(unconnected)
+    }
+
+    /// Initialize every node, in topological order.
+    ///
+    /// Returns `Error::UnconnectedInput` if any node has an unconnected input (see
+    /// [**unconnected_inputs**](./struct.Graph#method.unconnected_inputs)).
+    pub fn initialize(&mut self) -> Result<(), Error> {
+        if !self.unconnected_inputs()?.is_empty() {
+            return Err(Error::UnconnectedInput);
+        }
+        for node in self.topology.order()? {
+            self.nodes[node.0].initialize()?;
+        }
+        Ok(())
+    }
+
+    /// Stop the graph and uninitialize every node, in reverse topological order.
+    pub fn uninitialize(&mut self) -> Result<(), Error> {
+        self.stop()?;
+        for node in self.topology.order()?.into_iter().rev() {
+            self.nodes[node.0].uninitialize()?;
+        }
+        Ok(())
+    }
+
+    /// Initialize the graph if necessary and start its sink nodes that are I/O units.
+    ///
+    /// Returns `Error::NoOutputUnit` if none of the sink nodes is an I/O unit, as nothing would
+    /// pull audio through the graph.
+    pub fn start(&mut self) -> Result<(), Error> {
+        let sinks = self.io_sinks()?;
+        if sinks.is_empty() {
+            return Err(Error::NoOutputUnit);
+        }
+        self.initialize()?;
+        for node in sinks {
+            self.nodes[node.0].start()?;
+        }
+        Ok(())
+    }
+
+    /// Stop the sink nodes of the graph that are I/O units.
+    pub fn stop(&mut self) -> Result<(), Error> {
+        for node in self.io_sinks()?.into_iter().rev() {
+            self.nodes[node.0].stop()?;
+        }
+        Ok(())
+    }
+
+    /// The first sink node of the graph that is an I/O unit, in topological order.
+    ///
+    /// This is the node that pulls audio through the graph, for example when rendering offline.
+    /// Returns `Error::NoOutputUnit` if none of the sink nodes is an I/O unit.
+    pub fn output_node(&self) -> Result<NodeId, Error> {
+        self.io_sinks()?.first().copied().ok_or(Error::NoOutputUnit)
+    }
+
+    // The sink nodes that are I/O units, in topological order.
+    fn io_sinks(&self) -> Result<Vec<NodeId>, Error> {
+        let mut sinks = Vec::new();
+        for node in self.topology.order()? {
+            if !self.topology.has_outputs(node) && is_io_unit(&self.nodes[node.0])? {
+                sinks.push(node);
+            }
+        }
+        Ok(sinks)
+    }
+}
+
+impl Drop for Graph {
+    fn drop(&mut self) {
+        // Stop pulling before the nodes are disposed, as a node may be dropped before the nodes
+        // downstream of it.
+        for unit in &mut self.nodes {
+            let _ = unit.stop();
+        }
+    }
+}
+
+fn is_io_unit(unit: &AudioUnit) -> Result<bool, Error> {
+    let mut description = sys::AudioComponentDescription::default();
+    unsafe {
+        let component = sys::AudioComponentInstanceGetComponent(unit.instance);
+        try_os_status!(sys::AudioComponentGetDescription(
+            component,
+            &mut description
+        ));
+    }
+    Ok(description.componentType == sys::kAudioUnitType_Output)
+}
+
+fn formats_match(
+    a: &sys::AudioStreamBasicDescription,
+    b: &sys::AudioStreamBasicDescription,
+) -> bool {
+    a.mSampleRate == b.mSampleRate
+        && a.mFormatID == b.mFormatID
+        && a.mFormatFlags == b.mFormatFlags
+        && a.mBytesPerPacket == b.mBytesPerPacket
+        && a.mFramesPerPacket == b.mFramesPerPacket
+        && a.mBytesPerFrame == b.mBytesPerFrame
+        && a.mChannelsPerFrame == b.mChannelsPerFrame
+        && a.mBitsPerChannel == b.mBitsPerChannel
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::audio_unit::EffectType;
+
+    fn topology(node_count: usize, edges: &[(usize, usize)]) -> Topology {
+        let mut topology = Topology::default();
+        for _ in 0..node_count {
+            topology.add_node();
+        }
+        for (i, &(source, dest)) in edges.iter().enumerate() {
+            topology.connections.push(Connection {
+                source: NodeId(source),
+                source_bus: 0,
+                dest: NodeId(dest),
+                dest_bus: i as u32,
+            });
+        }
+        topology
+    }
+
+    #[test]
+    fn test_order() {
+        // 3 → 1 → 0, 2 → 0
+        let topology = topology(4, &[(3, 1), (1, 0), (2, 0)]);
+        let order = topology.order().unwrap();
+        let position = |i| order.iter().position(|&n| n == NodeId(i)).unwrap();
+        assert_eq!(order.len(), 4);
+        assert!(position(3) < position(1));
+        assert!(position(1) < position(0));
+        assert!(position(2) < position(0));
+        assert!(topology.has_outputs(NodeId(3)));
+        assert!(!topology.has_outputs(NodeId(0)));
+    }
+
+    #[test]
+    fn test_cycles() {
+        let topology = topology(3, &[(0, 1), (1, 2)]);
+        let back = Connection {
+            source: NodeId(2),
+            source_bus: 0,
+            dest: NodeId(0),
+            dest_bus: 0,
+        };
+        assert!(matches!(topology.check(&back), Err(Error::GraphCycle)));
+        let to_self = Connection {
+            dest: NodeId(2),
+            dest_bus: 3,
+            ..back
+        };
+        assert!(matches!(topology.check(&to_self), Err(Error::GraphCycle)));
+        let forward = Connection {
+            source: NodeId(0),
+            dest: NodeId(2),
+            dest_bus: 5,
+            ..back
+        };
+        assert!(topology.check(&forward).is_ok());
+
+        let cyclic = self::topology(2, &[(0, 1), (1, 0)]);
+        assert!(matches!(cyclic.order(), Err(Error::GraphCycle)));
+    }
+
+    #[test]
+    fn test_bus_already_connected() {
+        let mut topology = topology(3, &[(0, 2)]);
+        let connection = Connection {
+            source: NodeId(1),
+            source_bus: 0,
+            dest: NodeId(2),
+            dest_bus: 0,
+        };
+        assert!(matches!(
+            topology.check(&connection),
+            Err(Error::BusAlreadyConnected)
+        ));
+        assert_eq!(topology.disconnect(NodeId(2), 0).unwrap().source, NodeId(0));
+        assert!(topology.check(&connection).is_ok());
+    }
+
+    #[test]
+    fn test_unconnected_inputs() {
+        // 0 → bus 0 of 2, 1 → bus 2 of 2
+        let mut topology = topology(3, &[]);
+        for &(source, dest_bus) in &[(0, 0), (1, 2)] {
+            topology.connections.push(Connection {
+                source: NodeId(source),
+                source_bus: 0,
+                dest: NodeId(2),
+                dest_bus,
+            });
+        }
+        assert_eq!(
+            topology.unconnected_inputs(NodeId(2), 4, |_| false),
+            vec![(NodeId(2), 1), (NodeId(2), 3)]
+        );
+        assert_eq!(
+            topology.unconnected_inputs(NodeId(2), 4, |bus| bus == 1),
+            vec![(NodeId(2), 3)]
+        );
+        assert_eq!(
+            topology.unconnected_inputs(NodeId(0), 1, |bus| bus == 0),
+            vec![]
+        );
+        assert_eq!(
+            topology.unconnected_inputs(NodeId(1), 2, |_| false),
+            vec![(NodeId(1), 0), (NodeId(1), 1)]
+        );
+    }
+
+    #[test]
+    fn test_graph() {
+        let mut graph = Graph::new();
+        let first = graph.add_node(AudioUnit::new(EffectType::Delay).unwrap());
+        let second = graph.add_node(AudioUnit::new(EffectType::Delay).unwrap());
+        graph.connect(first, 0, second, 0).unwrap();
+        assert_eq!(graph.unconnected_inputs().unwrap(), vec![(first, 0)]);
+        assert!(matches!(graph.initialize(), Err(Error::UnconnectedInput)));
+        assert!(matches!(
+            graph.connect(second, 0, first, 0),
+            Err(Error::GraphCycle)
+        ));
+        assert_eq!(graph.disconnect(second, 0).unwrap().unwrap().source, first);
+        assert!(graph.connections().is_empty());
+        // Neither node is an I/O unit, so nothing would pull audio through the graph.
+        assert!(matches!(graph.start(), Err(Error::NoOutputUnit)));
+        assert!(matches!(graph.output_node(), Err(Error::NoOutputUnit)));
+    }
+}
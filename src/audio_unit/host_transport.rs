@@ -0,0 +1,575 @@
+//! Host transport callbacks for hosted units.
+//!
+//! Tempo-synced effects and instruments ask their host for the current beat, tempo, time
+//! signature and transport state through the callbacks installed with
+//! `kAudioUnitProperty_HostCallbacks`. Implement [**HostTransport**](./trait.HostTransport) to
+//! provide them, and install it with
+//! [**AudioUnit::set_host_transport**](../struct.AudioUnit#method.set_host_transport).
+//!
+//! [**SampleClock**](./struct.SampleClock) is a transport driven by a sample counter, for hosts
+//! without a timeline of their own. Advance it by the number of frames rendered in each render
+//! cycle.
+//!
+//! ```no_run
+//! # use std::sync::Arc;
+//! # use coreaudio::audio_unit::{AudioUnit, EffectType};
+//! # use coreaudio::audio_unit::host_transport::SampleClock;
+//! # fn main() -> Result<(), coreaudio::Error> {
+//! let mut unit = AudioUnit::new(EffectType::Delay)?;
+//! let clock = Arc::new(SampleClock::new(48_000.0, 120.0));
+//! unit.set_host_transport(clock.clone())?;
+//! clock.play();
+//! // In the render callback, after rendering `frames` frames:
+//! clock.advance(512);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The callbacks are called on the render thread, so implementations must not block.
+
+use std::os::raw::c_void;
+use std::ptr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+
+use super::{AudioUnit, Element, Scope};
+use crate::error::{AudioUnitError, Error};
+use sys;
+
+/// The current position in beats and the tempo.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct BeatAndTempo {
+    /// The position in beats, i.e. quarter notes, from the start of the timeline.
+    pub beat: f64,
+    /// The tempo in beats per minute.
+    pub tempo: f64,
+}
+
+/// The current position within the musical time of the timeline.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct MusicalTimeLocation {
+    /// The number of samples from the start of the render cycle to the next beat.
+    pub samples_to_next_beat: u32,
+    pub time_signature_numerator: u32,
+    pub time_signature_denominator: u32,
+    /// The beat on which the current measure started.
+    pub measure_down_beat: f64,
+}
+
+/// The state of the transport.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TransportState {
+    pub playing: bool,
+    pub recording: bool,
+    /// Whether the transport started, stopped or jumped to a new position since the state was
+    /// last reported.
+    pub changed: bool,
+    /// The position in samples from the start of the timeline.
+    pub sample_position: f64,
+    /// The start and end beats of the cycle, if the transport is looping.
+    pub cycle: Option<(f64, f64)>,
+}
+
+/// Provides the host timeline to hosted units.
+///
+/// Each method returns `None` if the information is not available, in which case the unit is
+/// told it can't be retrieved in the current context.
+pub trait HostTransport: Send + Sync {
+    /// The current beat and tempo.
+    fn beat_and_tempo(&self) -> Option<BeatAndTempo>;
+
+    /// The time signature and the position within the current measure.
+    fn musical_time_location(&self) -> Option<MusicalTimeLocation> {
+        None
+    }
+
+    /// Whether the transport is playing, and where.
+    fn transport_state(&self) -> Option<TransportState> {
+        None
+    }
+}
+
+/// A transport whose position is a sample counter, advanced by the host after each render
+/// cycle.
+///
+/// Beats are derived from the position at the current tempo. All methods are lock-free, so the
+/// clock may be shared with the render thread.
+#[derive(Debug)]
+pub struct SampleClock {
+    sample_rate: f64,
+    position: AtomicU64,
+    tempo: AtomicU64,
+    // The numerator in the upper 32 bits and the denominator in the lower, so they change
+    // together.
+    time_signature: AtomicU64,
+    playing: AtomicBool,
+    changed: AtomicBool,
+    cycling: AtomicBool,
+    cycle_start: AtomicU64,
+    cycle_end: AtomicU64,
+}
+
+impl SampleClock {
+    /// A stopped clock at the start of the timeline, in 4/4 time.
+    pub fn new(sample_rate: f64, tempo: f64) -> Self {
+        SampleClock {
+            sample_rate,
+            position: AtomicU64::new(0),
+            tempo: AtomicU64::new(tempo.to_bits()),
+            time_signature: AtomicU64::new((4 << 32) | 4),
+            playing: AtomicBool::new(false),
+            changed: AtomicBool::new(false),
+            cycling: AtomicBool::new(false),
+            cycle_start: AtomicU64::new(0f64.to_bits()),
+            cycle_end: AtomicU64::new(0f64.to_bits()),
+        }
+    }
+
+    pub fn sample_rate(&self) -> f64 {
+        self.sample_rate
+    }
+
+    /// The tempo in beats per minute.
+    pub fn tempo(&self) -> f64 {
+        f64::from_bits(self.tempo.load(Ordering::Relaxed))
+    }
+
+    /// Sets the tempo in beats per minute.
+    pub fn set_tempo(&self, tempo: f64) {
+        self.tempo.store(tempo.to_bits(), Ordering::Relaxed);
+    }
+
+    /// The numerator and denominator of the time signature.
+    pub fn time_signature(&self) -> (u32, u32) {
+        let time_signature = self.time_signature.load(Ordering::Relaxed);
+        ((time_signature >> 32) as u32, time_signature as u32)
+    }
+
+    /// Sets the time signature, e.g. `(6, 8)` for 6/8 time.
+    pub fn set_time_signature(&self, numerator: u32, denominator: u32) {
+        let time_signature = ((numerator as u64) << 32) | denominator as u64;
+        self.time_signature.store(time_signature, Ordering::Relaxed);
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.playing.load(Ordering::Relaxed)
+    }
+
+    /// Start advancing the position.
+    pub fn play(&self) {
+        if !self.playing.swap(true, Ordering::Relaxed) {
+            self.changed.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// Stop advancing the position.
+    pub fn stop(&self) {
+        if self.playing.swap(false, Ordering::Relaxed) {
+            self.changed.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// The position in samples from the start of the timeline.
+    pub fn position(&self) -> u64 {
+        self.position.load(Ordering::Relaxed)
+    }
+
+    /// Jump to a position in samples from the start of the timeline.
+    pub fn seek(&self, position: u64) {
+        self.position.store(position, Ordering::Relaxed);
+        self.changed.store(true, Ordering::Relaxed);
+    }
+
+    /// Loop between two beats while playing, or stop looping with `None`.
+    pub fn set_cycle(&self, cycle: Option<(f64, f64)>) {
+        if let Some((start, end)) = cycle {
+            self.cycle_start.store(start.to_bits(), Ordering::Relaxed);
+            self.cycle_end.store(end.to_bits(), Ordering::Relaxed);
+        }
+        self.cycling.store(cycle.is_some(), Ordering::Relaxed);
+    }
+
+    /// The start and end beats of the cycle, if looping.
+    pub fn cycle(&self) -> Option<(f64, f64)> {
+        if !self.cycling.load(Ordering::Relaxed) {
+            return None;
+        }
+        let start = f64::from_bits(self.cycle_start.load(Ordering::Relaxed));
+        let end = f64::from_bits(self.cycle_end.load(Ordering::Relaxed));
+        Some((start, end))
+    }
+
+    /// Advance the position by `frames` if playing, wrapping around the cycle if looping.
+    pub fn advance(&self, frames: u32) {
+        if !self.is_playing() {
+            return;
+        }
+        let position = self.position() + frames as u64;
+        let beat = self.beat_at(position);
+        let position = match self.cycle() {
+            Some((start, end)) if end > start && beat >= end => {
+                let beat = start + (beat - end) % (end - start);
+                self.sample_at(beat).round() as u64
+            }
+            _ => position,
+        };
+        self.position.store(position, Ordering::Relaxed);
+    }
+
+    /// The current position in beats.
+    pub fn beat(&self) -> f64 {
+        self.beat_at(self.position())
+    }
+
+    fn beat_at(&self, position: u64) -> f64 {
+        position as f64 / self.sample_rate * self.tempo() / 60.0
+    }
+
+    fn sample_at(&self, beat: f64) -> f64 {
+        beat * 60.0 / self.tempo() * self.sample_rate
+    }
+}
+
+impl HostTransport for SampleClock {
+    fn beat_and_tempo(&self) -> Option<BeatAndTempo> {
+        Some(BeatAndTempo {
+            beat: self.beat(),
+            tempo: self.tempo(),
+        })
+    }
+
+    fn musical_time_location(&self) -> Option<MusicalTimeLocation> {
+        let (numerator, denominator) = self.time_signature();
+        let beat = self.beat();
+        // Beats are quarter notes, whatever the denominator.
+        let beats_per_measure = numerator as f64 * 4.0 / denominator as f64;
+        let samples_to_next_beat = self.sample_at(beat.ceil() - beat).round() as u32;
+        Some(MusicalTimeLocation {
+            samples_to_next_beat,
+            time_signature_numerator: numerator,
+            time_signature_denominator: denominator,
+            measure_down_beat: (beat / beats_per_measure).floor() * beats_per_measure,
+        })
+    }
+
+    fn transport_state(&self) -> Option<TransportState> {
+        Some(TransportState {
+            playing: self.is_playing(),
+            recording: false,
+            changed: self.changed.swap(false, Ordering::Relaxed),
+            sample_position: self.position() as f64,
+            cycle: self.cycle(),
+        })
+    }
+}
+
+/// The transport installed on a unit. Boxed so that its address, passed to the callbacks, is
+/// stable.
+type UserData = Box<Arc<dyn HostTransport>>;
+
+const UNAVAILABLE: sys::OSStatus = AudioUnitError::CannotDoInCurrentContext as sys::OSStatus;
+
+unsafe fn write<T>(ptr: *mut T, value: T) {
+    if !ptr.is_null() {
+        *ptr = value;
+    }
+}
+
+unsafe fn transport<'a>(user_data: *mut c_void) -> &'a dyn HostTransport {
+    &**(user_data as *const Arc<dyn HostTransport>)
+}
+
+unsafe extern "C" fn beat_and_tempo_proc(
+    user_data: *mut c_void,
+    beat: *mut f64,
+    tempo: *mut f64,
+) -> sys::OSStatus {
+    match transport(user_data).beat_and_tempo() {
+        Some(info) => {
+            write(beat, info.beat);
+            write(tempo, info.tempo);
+            0
+        }
+        None => UNAVAILABLE,
+    }
+}
+
+unsafe extern "C" fn musical_time_location_proc(
+    user_data: *mut c_void,
+    samples_to_next_beat: *mut u32,
+    numerator: *mut f32,
+    denominator: *mut u32,
+    measure_down_beat: *mut f64,
+) -> sys::OSStatus {
+    match transport(user_data).musical_time_location() {
+        Some(info) => {
+            write(samples_to_next_beat, info.samples_to_next_beat);
+            write(numerator, info.time_signature_numerator as f32);
+            write(denominator, info.time_signature_denominator);
+            write(measure_down_beat, info.measure_down_beat);
+            0
+        }
+        None => UNAVAILABLE,
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+unsafe extern "C" fn transport_state_proc2(
+    user_data: *mut c_void,
+    playing: *mut sys::Boolean,
+    recording: *mut sys::Boolean,
+    changed: *mut sys::Boolean,
+    sample_position: *mut f64,
+    cycling: *mut sys::Boolean,
+    cycle_start: *mut f64,
+    cycle_end: *mut f64,
+) -> sys::OSStatus {
+    match transport(user_data).transport_state() {
+        Some(state) => {
+            let (start, end) = state.cycle.unwrap_or((0.0, 0.0));
+            write(playing, state.playing as sys::Boolean);
+            write(recording, state.recording as sys::Boolean);
+            write(changed, state.changed as sys::Boolean);
+            write(sample_position, state.sample_position);
+            write(cycling, state.cycle.is_some() as sys::Boolean);
+            write(cycle_start, start);
+            write(cycle_end, end);
+            0
+        }
+        None => UNAVAILABLE,
+    }
+}
+
+unsafe extern "C" fn transport_state_proc(
+    user_data: *mut c_void,
+    playing: *mut sys::Boolean,
+    changed: *mut sys::Boolean,
+    sample_position: *mut f64,
+    cycling: *mut sys::Boolean,
+    cycle_start: *mut f64,
+    cycle_end: *mut f64,
+) -> sys::OSStatus {
+    transport_state_proc2(
+        user_data,
+        playing,
+        ptr::null_mut(),
+        changed,
+        sample_position,
+        cycling,
+        cycle_start,
+        cycle_end,
+    )
+}
+
+fn callback_info(user_data: Option<&UserData>) -> sys::HostCallbackInfo {
+    match user_data {
+        Some(transport) => sys::HostCallbackInfo {
+            hostUserData: &**transport as *const Arc<dyn HostTransport> as *mut c_void,
+            beatAndTempoProc: Some(beat_and_tempo_proc),
+            musicalTimeLocationProc: Some(musical_time_location_proc),
+            transportStateProc: Some(transport_state_proc),
+            transportStateProc2: Some(transport_state_proc2),
+        },
+        None => sys::HostCallbackInfo {
+            hostUserData: ptr::null_mut(),
+            beatAndTempoProc: None,
+            musicalTimeLocationProc: None,
+            transportStateProc: None,
+            transportStateProc2: None,
+        },
+    }
+}
+
+impl AudioUnit {
+    /// Provide the host timeline to the unit through `transport`, replacing any previous
+    /// transport.
+    ///
+    /// As the unit may be rendering while the transport is replaced, the unit keeps a previous
+    /// transport alive until it is uninitialized.
+    ///
+    /// **Available** in iOS 2.0 and later.
+    pub fn set_host_transport<T>(&mut self, transport: Arc<T>) -> Result<(), Error>
+    where
+        T: HostTransport + 'static,
+    {
+        let transport: UserData = Box::new(transport);
+        let info = callback_info(Some(&transport));
+        self.set_property(
+            sys::kAudioUnitProperty_HostCallbacks,
+            Scope::Global,
+            Element::Output,
+            Some(&info),
+        )?;
+        self.host_transports.push(transport);
+        self.host_transport_installed = true;
+        self.release_host_transports();
+        Ok(())
+    }
+
+    /// Removes the host transport from the **AudioUnit** and returns it.
+    ///
+    /// An initialized unit keeps its own reference to the transport until it is uninitialized.
+    pub fn free_host_transport(&mut self) -> Option<Arc<dyn HostTransport>> {
+        if !self.host_transport_installed {
+            return None;
+        }
+        // We can't report errors here, as we may be called from `drop`.
+        let info = callback_info(None);
+        self.set_property(
+            sys::kAudioUnitProperty_HostCallbacks,
+            Scope::Global,
+            Element::Output,
+            Some(&info),
+        )
+        .ok();
+        self.host_transport_installed = false;
+        let transport = self
+            .host_transports
+            .last()
+            .map(|transport| Arc::clone(transport));
+        self.release_host_transports();
+        transport
+    }
+
+    // Drops the transports that are no longer installed, unless a render in progress may still be
+    // calling into them.
+    pub(crate) fn release_host_transports(&mut self) {
+        if self.initialized {
+            return;
+        }
+        let installed = if self.host_transport_installed {
+            self.host_transports.pop()
+        } else {
+            None
+        };
+        self.host_transports.clear();
+        self.host_transports.extend(installed);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_sample_clock() {
+        // At 120 BPM, a beat lasts 24000 samples.
+        let clock = SampleClock::new(48_000.0, 120.0);
+        clock.advance(24_000);
+        assert_eq!(clock.beat(), 0.0);
+
+        clock.play();
+        clock.advance(36_000);
+        assert_eq!(clock.beat(), 1.5);
+        let location = clock.musical_time_location().unwrap();
+        assert_eq!(location.samples_to_next_beat, 12_000);
+        assert_eq!(location.measure_down_beat, 0.0);
+
+        clock.advance(84_000);
+        assert_eq!(clock.beat(), 5.0);
+        assert_eq!(
+            clock.musical_time_location().unwrap().measure_down_beat,
+            4.0
+        );
+        // A measure of 6/8 lasts three quarter notes.
+        clock.set_time_signature(6, 8);
+        assert_eq!(
+            clock.musical_time_location().unwrap().measure_down_beat,
+            3.0
+        );
+
+        let state = clock.transport_state().unwrap();
+        assert!(state.playing && state.changed);
+        assert_eq!(state.sample_position, 120_000.0);
+        assert!(!clock.transport_state().unwrap().changed);
+
+        clock.set_cycle(Some((0.0, 4.0)));
+        clock.advance(24_000);
+        assert_eq!(clock.beat(), 2.0);
+        clock.stop();
+        assert!(clock.transport_state().unwrap().changed);
+    }
+
+    #[test]
+    fn test_callbacks() {
+        let clock = Arc::new(SampleClock::new(44_100.0, 90.0));
+        clock.set_time_signature(3, 4);
+        clock.seek(44_100);
+        clock.set_cycle(Some((1.0, 5.0)));
+        let user_data: UserData = Box::new(clock.clone());
+        let info = callback_info(Some(&user_data));
+        let data = info.hostUserData;
+
+        unsafe {
+            let (mut beat, mut tempo) = (0.0, 0.0);
+            assert_eq!(
+                info.beatAndTempoProc.unwrap()(data, &mut beat, &mut tempo),
+                0
+            );
+            assert_eq!((beat, tempo), (1.5, 90.0));
+
+            let (mut next, mut numerator, mut denominator, mut down_beat) = (0, 0.0, 0, 0.0);
+            let musical_time = info.musicalTimeLocationProc.unwrap();
+            let status = musical_time(
+                data,
+                &mut next,
+                &mut numerator,
+                &mut denominator,
+                &mut down_beat,
+            );
+            assert_eq!(status, 0);
+            assert_eq!(
+                (next, numerator, denominator, down_beat),
+                (14_700, 3.0, 4, 0.0)
+            );
+
+            let (mut playing, mut changed, mut cycling) = (1, 0, 0);
+            let (mut position, mut start, mut end) = (0.0, 0.0, 0.0);
+            let status = info.transportStateProc.unwrap()(
+                data,
+                &mut playing,
+                &mut changed,
+                &mut position,
+                &mut cycling,
+                &mut start,
+                &mut end,
+            );
+            assert_eq!(status, 0);
+            assert_eq!((playing, changed, cycling), (0, 1, 1));
+            assert_eq!((position, start, end), (44_100.0, 1.0, 5.0));
+
+            // Units may pass null for the values they don't need.
+            let null = ptr::null_mut();
+            let status = info.transportStateProc2.unwrap()(
+                data,
+                null,
+                null,
+                null,
+                &mut position,
+                null,
+                ptr::null_mut(),
+                ptr::null_mut(),
+            );
+            assert_eq!(status, 0);
+        }
+
+        struct Silent;
+        impl HostTransport for Silent {
+            fn beat_and_tempo(&self) -> Option<BeatAndTempo> {
+                None
+            }
+        }
+        let user_data: UserData = Box::new(Arc::new(Silent));
+        let info = callback_info(Some(&user_data));
+        let status = unsafe {
+            info.musicalTimeLocationProc.unwrap()(
+                info.hostUserData,
+                ptr::null_mut(),
+                ptr::null_mut(),
+                ptr::null_mut(),
+                ptr::null_mut(),
+            )
+        };
+        assert_eq!(status, UNAVAILABLE);
+    }
+}
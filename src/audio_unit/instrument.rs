@@ -0,0 +1,286 @@
+//! Loading instruments into the sampler and DLS synth **MusicDevice** units.
+//!
+//! [**Sampler**](./struct.Sampler) wraps `MusicDeviceType::Sampler` and loads SoundFont and DLS
+//! presets, `.aupreset` files and sets of audio files. [**DlsSynth**](./struct.DlsSynth) wraps
+//! `MusicDeviceType::DLSSynth` and replaces its General MIDI sound bank.
+//!
+//! Paths are checked before they are handed to the unit, so a missing file returns
+//! `Error::Io(ErrorKind::NotFound)` and a file of the wrong kind returns
+//! `Error::UnsupportedInstrumentFile` instead of an opaque `OSStatus`.
+//!
+//! ```no_run
+//! # use coreaudio::audio_unit::instrument::{Bank, Sampler};
+//! # use coreaudio::audio_unit::music_device::MidiMessage;
+//! # fn main() -> Result<(), coreaudio::Error> {
+//! let mut sampler = Sampler::new()?;
+//! sampler.load_sound_font("/Library/Audio/Sounds/Banks/piano.sf2", 0, Bank::MELODIC)?;
+//! sampler.initialize()?;
+//! sampler.send_midi(MidiMessage::NoteOn { channel: 0, note: 60, velocity: 100 }, 0)?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::fs;
+use std::io;
+use std::ops::{Deref, DerefMut};
+use std::path::Path;
+
+use super::{AudioUnit, Element, MusicDeviceType, Scope};
+use crate::cf;
+use crate::error::Error;
+use sys;
+
+/// The file extensions of SoundFont banks.
+const SOUND_FONT_EXTENSIONS: &[&str] = &["sf2"];
+/// The file extensions of DLS banks, which the DLS synth also accepts SoundFonts for.
+const DLS_EXTENSIONS: &[&str] = &["dls", "sf2"];
+/// The file extensions of audio unit presets.
+const AU_PRESET_EXTENSIONS: &[&str] = &["aupreset"];
+/// The file extensions of audio files the sampler can map to keys.
+const AUDIO_FILE_EXTENSIONS: &[&str] = &["aif", "aifc", "aiff", "caf", "m4a", "mp3", "wav"];
+
+/// The kind of file loaded by `kAUSamplerProperty_LoadInstrument`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum InstrumentType {
+    /// A DLS or SoundFont preset. Both share the same value.
+    SoundBankPreset = 1,
+    AuPreset = 2,
+}
+
+/// The MIDI bank of a preset within a SoundFont or DLS bank.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Bank {
+    pub msb: u8,
+    pub lsb: u8,
+}
+
+impl Bank {
+    /// The default bank of melodic instruments.
+    pub const MELODIC: Bank = Bank { msb: 0x79, lsb: 0 };
+    /// The default bank of percussion kits.
+    pub const PERCUSSION: Bank = Bank { msb: 0x78, lsb: 0 };
+
+    /// A variation within the melodic bank, such as those of a GS sound set.
+    pub fn melodic(lsb: u8) -> Self {
+        Bank {
+            lsb,
+            ..Bank::MELODIC
+        }
+    }
+}
+
+/// Check that `path` is an existing file with one of the given extensions, ignoring case.
+fn check_file(path: &Path, extensions: &[&str]) -> Result<(), Error> {
+    if !fs::metadata(path)?.is_file() {
+        return Err(Error::Io(io::ErrorKind::InvalidInput));
+    }
+    let extension = path.extension().and_then(|ext| ext.to_str());
+    match extension {
+        Some(ext) if extensions.iter().any(|e| e.eq_ignore_ascii_case(ext)) => Ok(()),
+        _ => Err(Error::UnsupportedInstrumentFile),
+    }
+}
+
+/// A sampler-synthesizer, wrapping `MusicDeviceType::Sampler`.
+///
+/// Loading an instrument replaces the current one, and may be done while the unit is running.
+///
+/// **Available** in OS X v10.7 and later and iOS 5.0 and later.
+pub struct Sampler {
+    unit: AudioUnit,
+}
+
+impl Sampler {
+    /// Create a new instance of the sampler unit.
+    pub fn new() -> Result<Self, Error> {
+        let unit = AudioUnit::new(MusicDeviceType::Sampler)?;
+        Ok(Sampler { unit })
+    }
+
+    /// Unwrap the underlying **AudioUnit**.
+    pub fn into_inner(self) -> AudioUnit {
+        self.unit
+    }
+
+    fn load_instrument(
+        &mut self,
+        path: &Path,
+        ty: InstrumentType,
+        preset: u8,
+        bank: Bank,
+    ) -> Result<(), Error> {
+        if preset > 0x7F || bank.msb > 0x7F || bank.lsb > 0x7F {
+            return Err(Error::InvalidMidiMessage);
+        }
+        let unit = &mut self.unit;
+        cf::with_cfurl(path, |url| {
+            let data = sys::AUSamplerInstrumentData {
+                fileURL: url as sys::CFURLRef,
+                instrumentType: ty as u8,
+                bankMSB: bank.msb,
+                bankLSB: bank.lsb,
+                presetID: preset,
+            };
+            unit.set_property(
+                sys::kAUSamplerProperty_LoadInstrument,
+                Scope::Global,
+                Element::Output,
+                Some(&data),
+            )
+        })
+    }
+
+    /// Load the preset numbered `preset` in `bank` from a SoundFont 2 file.
+    ///
+    /// Returns `Error::InvalidMidiMessage` if the preset or bank is not a 7-bit value.
+    pub fn load_sound_font<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        preset: u8,
+        bank: Bank,
+    ) -> Result<(), Error> {
+        let path = path.as_ref();
+        check_file(path, SOUND_FONT_EXTENSIONS)?;
+        self.load_instrument(path, InstrumentType::SoundBankPreset, preset, bank)
+    }
+
+    /// Load the preset numbered `preset` in `bank` from a DLS file.
+    ///
+    /// Returns `Error::InvalidMidiMessage` if the preset or bank is not a 7-bit value.
+    pub fn load_dls_preset<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        preset: u8,
+        bank: Bank,
+    ) -> Result<(), Error> {
+        let path = path.as_ref();
+        check_file(path, DLS_EXTENSIONS)?;
+        self.load_instrument(path, InstrumentType::SoundBankPreset, preset, bank)
+    }
+
+    /// Load an `.aupreset` file saved by the sampler, along with the audio files it refers to.
+    pub fn load_aupreset<P: AsRef<Path>>(&mut self, path: P) -> Result<(), Error> {
+        let path = path.as_ref();
+        check_file(path, AU_PRESET_EXTENSIONS)?;
+        self.load_instrument(path, InstrumentType::AuPreset, 0, Bank::MELODIC)
+    }
+
+    /// Load a set of audio files, each of which is mapped to the key given by its root note.
+    ///
+    /// Every path is checked before any file is loaded.
+    pub fn load_audio_files<P: AsRef<Path>>(&mut self, paths: &[P]) -> Result<(), Error> {
+        for path in paths {
+            check_file(path.as_ref(), AUDIO_FILE_EXTENSIONS)?;
+        }
+        let unit = &mut self.unit;
+        cf::with_cfurl_array(paths, |array| {
+            unit.set_property(
+                sys::kAUSamplerProperty_LoadAudioFiles,
+                Scope::Global,
+                Element::Output,
+                Some(&array),
+            )
+        })
+    }
+}
+
+impl Deref for Sampler {
+    type Target = AudioUnit;
+    fn deref(&self) -> &AudioUnit {
+        &self.unit
+    }
+}
+
+impl DerefMut for Sampler {
+    fn deref_mut(&mut self) -> &mut AudioUnit {
+        &mut self.unit
+    }
+}
+
+/// A General MIDI synthesizer, wrapping `MusicDeviceType::DLSSynth`.
+///
+/// **Available** in OS X v10.2 and later.
+pub struct DlsSynth {
+    unit: AudioUnit,
+}
+
+impl DlsSynth {
+    /// Create a new instance of the DLS synth unit.
+    pub fn new() -> Result<Self, Error> {
+        let unit = AudioUnit::new(MusicDeviceType::DLSSynth)?;
+        Ok(DlsSynth { unit })
+    }
+
+    /// Unwrap the underlying **AudioUnit**.
+    pub fn into_inner(self) -> AudioUnit {
+        self.unit
+    }
+
+    /// Replace the built-in General MIDI bank with a DLS or SoundFont 2 bank.
+    ///
+    /// Must be called before the unit is initialized.
+    pub fn load_sound_bank<P: AsRef<Path>>(&mut self, path: P) -> Result<(), Error> {
+        let path = path.as_ref();
+        check_file(path, DLS_EXTENSIONS)?;
+        let unit = &mut self.unit;
+        cf::with_cfurl(path, |url| {
+            unit.set_property(
+                sys::kMusicDeviceProperty_SoundBankURL,
+                Scope::Global,
+                Element::Output,
+                Some(&url),
+            )
+        })
+    }
+}
+
+impl Deref for DlsSynth {
+    type Target = AudioUnit;
+    fn deref(&self) -> &AudioUnit {
+        &self.unit
+    }
+}
+
+impl DerefMut for DlsSynth {
+    fn deref_mut(&mut self) -> &mut AudioUnit {
+        &mut self.unit
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_check_file() {
+        let dir = std::env::temp_dir().join("coreaudio-rs-test-check-file");
+        fs::create_dir_all(&dir).unwrap();
+        let bank = dir.join("Bank.SF2");
+        fs::write(&bank, b"RIFF").unwrap();
+
+        assert!(check_file(&bank, SOUND_FONT_EXTENSIONS).is_ok());
+        assert!(check_file(&bank, DLS_EXTENSIONS).is_ok());
+        match check_file(&bank, AU_PRESET_EXTENSIONS) {
+            Err(Error::UnsupportedInstrumentFile) => (),
+            other => panic!("unexpected result {:?}", other),
+        }
+        match check_file(&dir.join("missing.sf2"), SOUND_FONT_EXTENSIONS) {
+            Err(Error::Io(io::ErrorKind::NotFound)) => (),
+            other => panic!("unexpected result {:?}", other),
+        }
+        match check_file(&dir, SOUND_FONT_EXTENSIONS) {
+            Err(Error::Io(io::ErrorKind::InvalidInput)) => (),
+            other => panic!("unexpected result {:?}", other),
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_sampler_rejects_missing_files() {
+        let mut sampler = Sampler::new().unwrap();
+        assert!(sampler
+            .load_sound_font("does-not-exist.sf2", 0, Bank::MELODIC)
+            .is_err());
+        assert!(sampler.load_audio_files(&["does-not-exist.wav"]).is_err());
+    }
+}
@@ -1,12 +1,13 @@
-use std::ffi::{c_uint, CStr};
+use std::ffi::c_uint;
 use std::fmt;
 use std::ptr;
 
 use sys::{
-    self, kCFStringEncodingUTF8, AudioComponentCopyName, AudioComponentGetDescription,
-    AudioComponentGetVersion, CFStringGetCString, CFStringGetLength, CFStringRef,
+    self, AudioComponentCopyName, AudioComponentGetDescription, AudioComponentGetVersion,
+    CFStringRef,
 };
 
+use crate::cf;
 use crate::Error;
 
 use super::Type;
@@ -61,7 +62,8 @@ pub fn list_unit_info(ty: Type) -> Result<Vec<AudioUnitInfo>, Error> {
 
             let mut name_ref: CFStringRef = std::ptr::null();
             try_os_status!(AudioComponentCopyName(component, &mut name_ref));
-            let name = cfstring_ref_to_string(name_ref);
+            // The name is returned retained, so it's released once it has been copied.
+            let name = cf::take_cfstring(name_ref as _);
 
             let mut version = 0_u32;
             try_os_status!(AudioComponentGetVersion(component, &mut version));
@@ -90,16 +92,6 @@ pub fn list_unit_info(ty: Type) -> Result<Vec<AudioUnitInfo>, Error> {
     Ok(ret)
 }
 
-unsafe fn cfstring_ref_to_string(r: CFStringRef) -> String {
-    let len = CFStringGetLength(r) + 1;
-    let mut bytes = vec![0_i8; len as usize];
-
-    CFStringGetCString(r, bytes.as_mut_ptr(), len, kCFStringEncodingUTF8);
-
-    let c_str = CStr::from_ptr(bytes.as_ptr());
-    c_str.to_str().unwrap().to_owned()
-}
-
 impl fmt::Debug for AudioUnitInfo {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("AudioUnitInfo")
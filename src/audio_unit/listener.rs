@@ -0,0 +1,384 @@
+//! Listening for changes to the properties of an **AudioUnit**.
+//!
+//! [**AudioUnit::add_property_listener**](../struct.AudioUnit#method.add_property_listener)
+//! calls a closure whenever a property changes, for example when the stream format is changed by
+//! a connected unit or when an output unit starts or stops running.
+//! [**AudioUnit::watch_property**](../struct.AudioUnit#method.watch_property) instead delivers
+//! the new value of a property in the [**property**](../property/index.html) catalogue over a
+//! channel.
+//!
+//! ```no_run
+//! # use coreaudio::audio_unit::{property, AudioUnit, Element, IOType, Scope};
+//! # fn main() -> Result<(), coreaudio::Error> {
+//! let mut unit = AudioUnit::new(IOType::DefaultOutput)?;
+//! let running = unit.watch_property::<property::IsRunning>(Scope::Global, Element::Output)?;
+//! unit.start()?;
+//! assert_eq!(running.recv()?, true);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Each listener is removed when its [**PropertyListener**](./struct.PropertyListener) or
+//! [**PropertyWatch**](./struct.PropertyWatch) is dropped.
+
+use std::os::raw::c_void;
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use super::property::{self, Property};
+use super::{AudioUnit, Bus, Scope};
+use crate::error::Error;
+use crate::try_os_status;
+use sys;
+
+/// A change to a property of an **AudioUnit**.
+#[derive(Copy, Clone, Debug)]
+pub struct PropertyEvent {
+    pub id: u32,
+    pub scope: Scope,
+    pub elem: Bus,
+}
+
+#[derive(Copy, Clone)]
+struct Instance(sys::AudioUnit);
+
+// The instance is only used to add and remove listeners, which Core Audio allows from any thread.
+unsafe impl Send for Instance {}
+
+/// The state of a listener that Core Audio calls into from its own threads.
+pub(crate) trait Detach: Send + Sync {
+    /// Drops the callback of the listener, after which calls into the state do nothing.
+    fn detach(&self);
+}
+
+struct Shared {
+    instance: Option<Instance>,
+    // The states of the listeners added to the instance. A listener may still be running on
+    // another thread after it is removed, so they are only freed once the instance is disposed.
+    states: Vec<Arc<dyn Detach>>,
+    // The user data installed for properties that hold a single listener, by property ID.
+    installed: Vec<(u32, usize)>,
+}
+
+/// The instance of an **AudioUnit**, shared with its listeners until it is disposed.
+#[derive(Clone)]
+pub(crate) struct SharedInstance(Arc<Mutex<Shared>>);
+
+impl SharedInstance {
+    pub(crate) fn new(instance: sys::AudioUnit) -> Self {
+        SharedInstance(Arc::new(Mutex::new(Shared {
+            instance: Some(Instance(instance)),
+            states: Vec::new(),
+            installed: Vec::new(),
+        })))
+    }
+
+    /// Disposes of the instance with `f`, then detaches every listener so that the resources
+    /// held by their callbacks are dropped, e.g. closing the channel of a `PropertyWatch`.
+    pub(crate) fn dispose<F>(&self, f: F)
+    where
+        F: FnOnce(sys::AudioUnit),
+    {
+        let mut shared = self.lock();
+        if let Some(Instance(instance)) = shared.instance.take() {
+            f(instance);
+        }
+        for state in shared.states.drain(..) {
+            state.detach();
+        }
+    }
+
+    /// Calls `f` with the instance unless it has been disposed, holding the lock so that it
+    /// can't be disposed in the meantime.
+    pub(crate) fn with<R, F>(&self, f: F) -> Option<R>
+    where
+        F: FnOnce(sys::AudioUnit) -> R,
+    {
+        let shared = self.0.lock().ok()?;
+        shared.instance.map(|Instance(instance)| f(instance))
+    }
+
+    /// Keeps the state of a listener alive until the instance is disposed.
+    pub(crate) fn keep(&self, state: Arc<dyn Detach>) {
+        let mut shared = self.lock();
+        if shared.instance.is_some() {
+            shared.states.push(state);
+        } else {
+            state.detach();
+        }
+    }
+
+    /// Installs the listener identified by `user_data` as the only listener of the property `id`
+    /// with `f`, replacing any previous one.
+    pub(crate) fn install<F>(&self, id: u32, user_data: usize, f: F) -> Result<(), Error>
+    where
+        F: FnOnce(sys::AudioUnit) -> Result<(), Error>,
+    {
+        let mut shared = self.lock();
+        let Instance(instance) = shared.instance.ok_or(Error::AudioUnitDisposed)?;
+        f(instance)?;
+        shared.installed.retain(|&(installed, _)| installed != id);
+        shared.installed.push((id, user_data));
+        Ok(())
+    }
+
+    /// Removes the listener of the property `id` with `f`, only if it is still the one identified
+    /// by `user_data`.
+    pub(crate) fn uninstall<F>(&self, id: u32, user_data: usize, f: F)
+    where
+        F: FnOnce(sys::AudioUnit),
+    {
+        let mut shared = self.lock();
+        let position = shared
+            .installed
+            .iter()
+            .position(|&entry| entry == (id, user_data));
+        if let (Some(Instance(instance)), Some(position)) = (shared.instance, position) {
+            f(instance);
+            shared.installed.remove(position);
+        }
+    }
+
+    // Freeing a listener's state early is unsound, so a poisoned lock is used regardless.
+    fn lock(&self) -> MutexGuard<'_, Shared> {
+        self.0
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+type Callback = Box<dyn FnMut(sys::AudioUnit, PropertyEvent) + Send>;
+
+struct ListenerState {
+    event: PropertyEvent,
+    // `None` once the listener is removed or the unit is disposed.
+    callback: Mutex<Option<Callback>>,
+}
+
+impl Detach for ListenerState {
+    fn detach(&self) {
+        if let Ok(mut callback) = self.callback.lock() {
+            *callback = None;
+        }
+    }
+}
+
+/// Removes a property listener when dropped.
+///
+/// The closure is dropped with the handle, or when the **AudioUnit** is disposed if the handle
+/// outlives it. The small state Core Audio refers to is kept until the unit is disposed, as the
+/// listener may still be running on another thread.
+pub struct PropertyListener {
+    instance: SharedInstance,
+    state: Arc<ListenerState>,
+}
+
+impl PropertyListener {
+    /// The property, scope and element being listened to.
+    pub fn event(&self) -> PropertyEvent {
+        self.state.event
+    }
+}
+
+impl Drop for PropertyListener {
+    fn drop(&mut self) {
+        // The callback may own resources, e.g. the sender of a watch, so it is dropped right away.
+        self.state.detach();
+        let state = &*self.state;
+        self.instance.with(|instance| unsafe {
+            sys::AudioUnitRemovePropertyListenerWithUserData(
+                instance,
+                state.event.id,
+                Some(listener_proc),
+                state as *const ListenerState as *mut c_void,
+            )
+        });
+    }
+}
+
+unsafe extern "C" fn listener_proc(
+    in_ref_con: *mut c_void,
+    in_unit: sys::AudioUnit,
+    _in_id: sys::AudioUnitPropertyID,
+    in_scope: sys::AudioUnitScope,
+    in_element: sys::AudioUnitElement,
+) {
+    let state = &*(in_ref_con as *const ListenerState);
+    // The listener is called for every scope and element of the property.
+    if in_scope != state.event.scope as u32 || in_element != state.event.elem.0 {
+        return;
+    }
+    if let Ok(mut callback) = state.callback.lock() {
+        if let Some(callback) = callback.as_mut() {
+            callback(in_unit, state.event);
+        }
+    }
+}
+
+/// Receives the new values of a property, returned by
+/// [**AudioUnit::watch_property**](../struct.AudioUnit#method.watch_property).
+///
+/// The listener is removed when the watch is dropped.
+pub struct PropertyWatch<T> {
+    receiver: Receiver<Result<T, Error>>,
+    _listener: PropertyListener,
+}
+
+impl<T> PropertyWatch<T> {
+    /// Block until the property changes, returning its new value.
+    ///
+    /// Returns `Error::AudioUnitDisposed` once the **AudioUnit** has been disposed and every
+    /// change before it has been received.
+    pub fn recv(&self) -> Result<T, Error> {
+        // The sender is dropped when the unit is disposed, as the watch keeps its listener.
+        self.receiver
+            .recv()
+            .unwrap_or(Err(Error::AudioUnitDisposed))
+    }
+
+    /// The new value of the property, if it has changed since the last call.
+    pub fn try_recv(&self) -> Option<Result<T, Error>> {
+        self.receiver.try_recv().ok()
+    }
+}
+
+impl AudioUnit {
+    fn add_listener(
+        &self,
+        event: PropertyEvent,
+        callback: Callback,
+    ) -> Result<PropertyListener, Error> {
+        let state = Arc::new(ListenerState {
+            event,
+            callback: Mutex::new(Some(callback)),
+        });
+        unsafe {
+            try_os_status!(sys::AudioUnitAddPropertyListener(
+                self.instance,
+                event.id,
+                Some(listener_proc),
+                Arc::as_ptr(&state) as *mut c_void,
+            ));
+        }
+        self.listener_instance.keep(state.clone());
+        Ok(PropertyListener {
+            instance: self.listener_instance.clone(),
+            state,
+        })
+    }
+
+    /// Calls `f` whenever the property `id` changes in the given scope and element.
+    ///
+    /// The closure is called on the thread that changed the property, or on a thread owned by
+    /// Core Audio. The listener is removed when the returned handle is dropped.
+    ///
+    /// **Available** in iOS 2.0 and later.
+    pub fn add_property_listener<F>(
+        &self,
+        id: u32,
+        scope: Scope,
+        elem: impl Into<Bus>,
+        mut f: F,
+    ) -> Result<PropertyListener, Error>
+    where
+        F: FnMut(PropertyEvent) + Send + 'static,
+    {
+        let event = PropertyEvent {
+            id,
+            scope,
+            elem: elem.into(),
+        };
+        self.add_listener(event, Box::new(move |_, event| f(event)))
+    }
+
+    /// Sends the new value of the property `P` over a channel whenever it changes in the given
+    /// scope and element.
+    ///
+    /// Errors reading the new value are sent as well.
+    ///
+    /// **Available** in iOS 2.0 and later.
+    pub fn watch_property<P>(
+        &self,
+        scope: Scope,
+        elem: impl Into<Bus>,
+    ) -> Result<PropertyWatch<P::Value>, Error>
+    where
+        P: Property,
+        P::Value: Send + 'static,
+    {
+        property::check_scope::<P>(scope)?;
+        let (sender, receiver) = mpsc::channel();
+        let event = PropertyEvent {
+            id: P::ID,
+            scope,
+            elem: elem.into(),
+        };
+        let callback = move |unit: sys::AudioUnit, event: PropertyEvent| {
+            let _ = sender.send(property::get::<P>(unit, event.scope, event.elem));
+        };
+        let listener = self.add_listener(event, Box::new(callback))?;
+        Ok(PropertyWatch {
+            receiver,
+            _listener: listener,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::audio_unit::{EffectType, Element};
+    use crate::StreamFormat;
+
+    #[test]
+    fn test_stream_format_listener() {
+        let mut unit = AudioUnit::new(EffectType::Delay).unwrap();
+        let (sender, events) = mpsc::channel();
+        let listener = unit
+            .add_property_listener(
+                sys::kAudioUnitProperty_StreamFormat,
+                Scope::Output,
+                Element::Output,
+                move |event| sender.send(event.id).unwrap(),
+            )
+            .unwrap();
+        let formats = unit
+            .watch_property::<property::StreamFormat>(Scope::Output, Element::Output)
+            .unwrap();
+
+        let mut format: StreamFormat = unit.output_stream_format().unwrap();
+        format.sample_rate = 22_050.0;
+        unit.set_stream_format(format, Scope::Output).unwrap();
+        assert_eq!(
+            events.try_recv().unwrap(),
+            sys::kAudioUnitProperty_StreamFormat
+        );
+        assert_eq!(formats.try_recv().unwrap().unwrap().mSampleRate, 22_050.0);
+
+        // The handle may outlive the unit, and a watch stops waiting once it is disposed.
+        drop(unit);
+        assert!(matches!(formats.recv(), Err(Error::AudioUnitDisposed)));
+        drop(listener);
+    }
+
+    #[test]
+    fn test_remove_listener() {
+        let mut unit = AudioUnit::new(EffectType::Delay).unwrap();
+        let (sender, events) = mpsc::channel();
+        let listener = unit
+            .add_property_listener(
+                sys::kAudioUnitProperty_StreamFormat,
+                Scope::Output,
+                Element::Output,
+                move |event| sender.send(event.id).unwrap(),
+            )
+            .unwrap();
+        drop(listener);
+        // The closure, and with it the sender, is dropped with the handle.
+        assert!(events.recv().is_err());
+
+        let mut format: StreamFormat = unit.output_stream_format().unwrap();
+        format.sample_rate = 22_050.0;
+        unit.set_stream_format(format, Scope::Output).unwrap();
+    }
+}
@@ -3,8 +3,7 @@
 use crate::error::Error;
 use crate::{AudioFormat, LinearPcmFlags, SampleFormat, StreamFormat};
 use std::collections::VecDeque;
-use std::ffi::CStr;
-use std::os::raw::{c_char, c_void};
+use std::os::raw::c_void;
 use std::ptr::null;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc::{channel, Sender};
@@ -12,25 +11,29 @@ use std::sync::Mutex;
 use std::time::Duration;
 use std::{mem, thread};
 
-use core_foundation_sys::string::{CFStringGetCString, CFStringGetCStringPtr, CFStringRef};
+use core_foundation_sys::string::CFStringRef;
 use sys;
 use sys::pid_t;
 use sys::{
-    kAudioDevicePropertyAvailableNominalSampleRates, kAudioDevicePropertyDeviceIsAlive,
-    kAudioDevicePropertyDeviceNameCFString, kAudioDevicePropertyHogMode,
-    kAudioDevicePropertyNominalSampleRate, kAudioDevicePropertyScopeOutput, kAudioHardwareNoError,
-    kAudioHardwarePropertyDefaultInputDevice, kAudioHardwarePropertyDefaultOutputDevice,
-    kAudioHardwarePropertyDevices, kAudioObjectPropertyElementMaster,
-    kAudioObjectPropertyScopeGlobal, kAudioObjectSystemObject,
+    kAudioDevicePropertyAvailableNominalSampleRates, kAudioDevicePropertyBufferFrameSize,
+    kAudioDevicePropertyDeviceIsAlive, kAudioDevicePropertyDeviceNameCFString,
+    kAudioDevicePropertyHogMode, kAudioDevicePropertyLatency,
+    kAudioDevicePropertyNominalSampleRate, kAudioDevicePropertySafetyOffset,
+    kAudioDevicePropertyScopeInput, kAudioDevicePropertyScopeOutput, kAudioDevicePropertyStreams,
+    kAudioHardwareNoError, kAudioHardwarePropertyDefaultInputDevice,
+    kAudioHardwarePropertyDefaultOutputDevice, kAudioHardwarePropertyDevices,
+    kAudioObjectPropertyElementMaster, kAudioObjectPropertyScopeGlobal, kAudioObjectSystemObject,
     kAudioOutputUnitProperty_CurrentDevice, kAudioOutputUnitProperty_EnableIO,
-    kAudioStreamPropertyAvailablePhysicalFormats, kAudioStreamPropertyPhysicalFormat,
-    kCFStringEncodingUTF8, AudioDeviceID, AudioObjectAddPropertyListener,
+    kAudioStreamPropertyAvailablePhysicalFormats, kAudioStreamPropertyLatency,
+    kAudioStreamPropertyPhysicalFormat, AudioDeviceID, AudioObjectAddPropertyListener,
     AudioObjectGetPropertyData, AudioObjectGetPropertyDataSize, AudioObjectID,
     AudioObjectPropertyAddress, AudioObjectRemovePropertyListener, AudioObjectSetPropertyData,
     AudioStreamBasicDescription, AudioStreamRangedDescription, AudioValueRange, OSStatus,
 };
 
-use crate::audio_unit::{AudioUnit, Element, IOType, Scope};
+use crate::audio_unit::property::{read_plain_array, Plain};
+use crate::audio_unit::{property, AudioUnit, Element, IOType, Scope};
+use crate::cf;
 
 /// Helper function to get the device id of the default input or output device.
 pub fn get_default_device_id(input: bool) -> Option<AudioDeviceID> {
@@ -112,6 +115,40 @@ pub fn audio_unit_from_device_id(
     Ok(audio_unit)
 }
 
+/// Get the value of a variable-length array property of an audio object.
+///
+/// The size of the property data is queried first, and the data is decoded into a `Vec<T>`.
+fn get_object_property_array<T: Plain>(
+    object_id: AudioObjectID,
+    property_address: &AudioObjectPropertyAddress,
+) -> Result<Vec<T>, Error> {
+    let mut data_size = 0u32;
+    unsafe {
+        let status = AudioObjectGetPropertyDataSize(
+            object_id,
+            property_address as *const _,
+            0,
+            null(),
+            &mut data_size as *mut _,
+        );
+        Error::from_os_status(status)?;
+    }
+    let mut data = vec![0u8; data_size as usize];
+    unsafe {
+        let status = AudioObjectGetPropertyData(
+            object_id,
+            property_address as *const _,
+            0,
+            null(),
+            &mut data_size as *mut _,
+            data.as_mut_ptr() as *mut _,
+        );
+        Error::from_os_status(status)?;
+    }
+    data.truncate(data_size as usize);
+    read_plain_array(&data)
+}
+
 /// List all audio device ids on the system.
 pub fn get_audio_device_ids() -> Result<Vec<AudioDeviceID>, Error> {
     let property_address = AudioObjectPropertyAddress {
@@ -119,44 +156,7 @@ pub fn get_audio_device_ids() -> Result<Vec<AudioDeviceID>, Error> {
         mScope: kAudioObjectPropertyScopeGlobal,
         mElement: kAudioObjectPropertyElementMaster,
     };
-
-    macro_rules! try_status_or_return {
-        ($status:expr) => {
-            if $status != kAudioHardwareNoError as i32 {
-                return Err(Error::Unknown($status));
-            }
-        };
-    }
-
-    let data_size = 0u32;
-    let status = unsafe {
-        AudioObjectGetPropertyDataSize(
-            kAudioObjectSystemObject,
-            &property_address as *const _,
-            0,
-            null(),
-            &data_size as *const _ as *mut _,
-        )
-    };
-    try_status_or_return!(status);
-
-    let device_count = data_size / mem::size_of::<AudioDeviceID>() as u32;
-    let mut audio_devices = vec![];
-    audio_devices.reserve_exact(device_count as usize);
-    unsafe { audio_devices.set_len(device_count as usize) };
-
-    let status = unsafe {
-        AudioObjectGetPropertyData(
-            kAudioObjectSystemObject,
-            &property_address as *const _,
-            0,
-            null(),
-            &data_size as *const _ as *mut _,
-            audio_devices.as_mut_ptr() as *mut _,
-        )
-    };
-    try_status_or_return!(status);
-    Ok(audio_devices)
+    get_object_property_array(kAudioObjectSystemObject, &property_address)
 }
 
 /// Get the device name for a device id.
@@ -167,54 +167,21 @@ pub fn get_device_name(device_id: AudioDeviceID) -> Result<String, Error> {
         mElement: kAudioObjectPropertyElementMaster,
     };
 
-    macro_rules! try_status_or_return {
-        ($status:expr) => {
-            if $status != kAudioHardwareNoError as i32 {
-                return Err(Error::Unknown($status));
-            }
-        };
-    }
-
-    let device_name: CFStringRef = null();
-    let data_size = mem::size_of::<CFStringRef>();
-    let c_str = unsafe {
+    let mut device_name: CFStringRef = null();
+    let mut data_size = mem::size_of::<CFStringRef>() as u32;
+    unsafe {
         let status = AudioObjectGetPropertyData(
             device_id,
             &property_address as *const _,
             0,
             null(),
-            &data_size as *const _ as *mut _,
-            &device_name as *const _ as *mut _,
+            &mut data_size as *mut _,
+            &mut device_name as *mut _ as *mut _,
         );
-        try_status_or_return!(status);
-
-        let c_string: *const c_char = CFStringGetCStringPtr(device_name, kCFStringEncodingUTF8);
-        if c_string.is_null() {
-            let status = AudioObjectGetPropertyData(
-                device_id,
-                &property_address as *const _,
-                0,
-                null(),
-                &data_size as *const _ as *mut _,
-                &device_name as *const _ as *mut _,
-            );
-            try_status_or_return!(status);
-            let mut buf: [i8; 255] = [0; 255];
-            let result = CFStringGetCString(
-                device_name,
-                buf.as_mut_ptr(),
-                buf.len() as _,
-                kCFStringEncodingUTF8,
-            );
-            if result == 0 {
-                return Err(Error::Unknown(result as i32));
-            }
-            let name: &CStr = CStr::from_ptr(buf.as_ptr());
-            return Ok(name.to_str().unwrap().to_owned());
-        }
-        CStr::from_ptr(c_string as *mut _)
-    };
-    Ok(c_str.to_string_lossy().into_owned())
+        Error::from_os_status(status)?;
+        // The name is returned retained, so it's released once it has been copied.
+        Ok(cf::take_cfstring(device_name))
+    }
 }
 
 /// Change the sample rate of a device.
@@ -244,28 +211,8 @@ pub fn set_device_sample_rate(device_id: AudioDeviceID, new_rate: f64) -> Result
         if sample_rate as u32 != new_rate as u32 {
             // Get available sample rate ranges.
             property_address.mSelector = kAudioDevicePropertyAvailableNominalSampleRates;
-            let data_size = 0u32;
-            let status = AudioObjectGetPropertyDataSize(
-                device_id,
-                &property_address as *const _,
-                0,
-                null(),
-                &data_size as *const _ as *mut _,
-            );
-            Error::from_os_status(status)?;
-            let n_ranges = data_size as usize / mem::size_of::<AudioValueRange>();
-            let mut ranges: Vec<AudioValueRange> = vec![];
-            ranges.reserve_exact(n_ranges as usize);
-            ranges.set_len(n_ranges);
-            let status = AudioObjectGetPropertyData(
-                device_id,
-                &property_address as *const _,
-                0,
-                null(),
-                &data_size as *const _ as *mut _,
-                ranges.as_mut_ptr() as *mut _,
-            );
-            Error::from_os_status(status)?;
+            let ranges: Vec<AudioValueRange> =
+                get_object_property_array(device_id, &property_address)?;
 
             // Now that we have the available ranges, pick the one matching the desired rate.
             let new_rate_integer = new_rate as u32;
@@ -467,34 +414,8 @@ pub fn get_supported_physical_stream_formats(
         mScope: kAudioObjectPropertyScopeGlobal,
         mElement: kAudioObjectPropertyElementMaster,
     };
-    let allformats = unsafe {
-        property_address.mSelector = kAudioStreamPropertyAvailablePhysicalFormats;
-        let mut data_size = 0u32;
-        let status = AudioObjectGetPropertyDataSize(
-            device_id,
-            &property_address as *const _,
-            0,
-            null(),
-            &mut data_size as *mut _,
-        );
-        Error::from_os_status(status)?;
-        let n_formats = data_size as usize / mem::size_of::<AudioStreamRangedDescription>();
-        let mut formats: Vec<AudioStreamRangedDescription> = vec![];
-        formats.reserve_exact(n_formats as usize);
-        formats.set_len(n_formats);
-
-        let status = AudioObjectGetPropertyData(
-            device_id,
-            &property_address as *const _,
-            0,
-            null(),
-            &data_size as *const _ as *mut _,
-            formats.as_mut_ptr() as *mut _,
-        );
-        Error::from_os_status(status)?;
-        formats
-    };
-    Ok(allformats)
+    property_address.mSelector = kAudioStreamPropertyAvailablePhysicalFormats;
+    get_object_property_array(device_id, &property_address)
 }
 
 /// Changing the sample rate is an asynchonous process.
@@ -790,3 +711,166 @@ pub fn toggle_hog_mode(device_id: AudioDeviceID) -> Result<pid_t, Error> {
     };
     Ok(pid)
 }
+
+/// Get a plain value property of a device in its input or output scope.
+fn get_device_property<T: Plain + Default>(
+    device_id: AudioDeviceID,
+    selector: u32,
+    input: bool,
+) -> Result<T, Error> {
+    let property_address = AudioObjectPropertyAddress {
+        mSelector: selector,
+        mScope: if input {
+            kAudioDevicePropertyScopeInput
+        } else {
+            kAudioDevicePropertyScopeOutput
+        },
+        mElement: kAudioObjectPropertyElementMaster,
+    };
+    let mut value = T::default();
+    let mut data_size = mem::size_of::<T>() as u32;
+    unsafe {
+        let status = AudioObjectGetPropertyData(
+            device_id,
+            &property_address as *const _,
+            0,
+            null(),
+            &mut data_size as *mut _,
+            &mut value as *mut _ as *mut _,
+        );
+        Error::from_os_status(status)?;
+    }
+    Ok(value)
+}
+
+/// Get the latency of the input or output of a device, in frames.
+pub fn get_device_latency(device_id: AudioDeviceID, input: bool) -> Result<u32, Error> {
+    get_device_property(device_id, kAudioDevicePropertyLatency, input)
+}
+
+/// Get the safety offset of the input or output of a device, in frames.
+/// This is the number of frames the device reads ahead of, or writes behind, the current time.
+pub fn get_device_safety_offset(device_id: AudioDeviceID, input: bool) -> Result<u32, Error> {
+    get_device_property(device_id, kAudioDevicePropertySafetyOffset, input)
+}
+
+/// Get the I/O buffer size of a device, in frames.
+pub fn get_device_buffer_frame_size(device_id: AudioDeviceID, input: bool) -> Result<u32, Error> {
+    get_device_property(device_id, kAudioDevicePropertyBufferFrameSize, input)
+}
+
+/// Get the latency of the first input or output stream of a device, in frames.
+/// Returns 0 if the device has no stream in that direction.
+pub fn get_device_stream_latency(device_id: AudioDeviceID, input: bool) -> Result<u32, Error> {
+    let property_address = AudioObjectPropertyAddress {
+        mSelector: kAudioDevicePropertyStreams,
+        mScope: if input {
+            kAudioDevicePropertyScopeInput
+        } else {
+            kAudioDevicePropertyScopeOutput
+        },
+        mElement: kAudioObjectPropertyElementMaster,
+    };
+    let streams: Vec<AudioObjectID> = get_object_property_array(device_id, &property_address)?;
+    let stream_id = match streams.first() {
+        Some(&stream_id) => stream_id,
+        None => return Ok(0),
+    };
+    // Stream properties live in the global scope of the stream object.
+    let property_address = AudioObjectPropertyAddress {
+        mSelector: kAudioStreamPropertyLatency,
+        mScope: kAudioObjectPropertyScopeGlobal,
+        mElement: kAudioObjectPropertyElementMaster,
+    };
+    let mut latency: u32 = 0;
+    let mut data_size = mem::size_of::<u32>() as u32;
+    unsafe {
+        let status = AudioObjectGetPropertyData(
+            stream_id,
+            &property_address as *const _,
+            0,
+            null(),
+            &mut data_size as *mut _,
+            &mut latency as *mut _ as *mut _,
+        );
+        Error::from_os_status(status)?;
+    }
+    Ok(latency)
+}
+
+/// The latencies that add up to the delay between the audio processed by an I/O unit and the
+/// sound at the device, or vice versa for input.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct LatencyReport {
+    /// The nominal sample rate of the device, used to convert between frames and seconds.
+    pub sample_rate: f64,
+    /// The processing latency of the audio unit, in seconds.
+    pub unit_latency: f64,
+    /// The latency of the device, in frames.
+    pub device_latency: u32,
+    /// The safety offset of the device, in frames.
+    pub safety_offset: u32,
+    /// The latency of the device stream, in frames.
+    pub stream_latency: u32,
+    /// The I/O buffer size of the device, in frames.
+    pub buffer_frames: u32,
+}
+
+impl LatencyReport {
+    /// The total latency, in frames.
+    pub fn total_frames(&self) -> f64 {
+        let device_frames = self.device_latency as u64
+            + self.safety_offset as u64
+            + self.stream_latency as u64
+            + self.buffer_frames as u64;
+        device_frames as f64 + self.unit_latency * self.sample_rate
+    }
+
+    /// The total latency, in seconds.
+    pub fn total_seconds(&self) -> f64 {
+        if self.sample_rate > 0.0 {
+            self.total_frames() / self.sample_rate
+        } else {
+            self.unit_latency
+        }
+    }
+
+    /// The total latency as a `Duration`.
+    pub fn total(&self) -> Duration {
+        Duration::from_secs_f64(self.total_seconds().max(0.0))
+    }
+}
+
+/// Collect the latencies of an I/O unit and the input or output of its current device.
+pub fn get_latency_report(audio_unit: &AudioUnit, input: bool) -> Result<LatencyReport, Error> {
+    let device_id = audio_unit.get::<property::CurrentDevice>(Scope::Global, Element::Output)?;
+    let sample_rate = get_device_property(device_id, kAudioDevicePropertyNominalSampleRate, input)?;
+    Ok(LatencyReport {
+        sample_rate,
+        unit_latency: audio_unit.latency()?,
+        device_latency: get_device_latency(device_id, input)?,
+        safety_offset: get_device_safety_offset(device_id, input)?,
+        stream_latency: get_device_stream_latency(device_id, input)?,
+        buffer_frames: get_device_buffer_frame_size(device_id, input)?,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_latency_report_totals() {
+        let report = LatencyReport {
+            sample_rate: 48_000.0,
+            unit_latency: 0.001,
+            device_latency: 24,
+            safety_offset: 16,
+            stream_latency: 8,
+            buffer_frames: 512,
+        };
+        assert_eq!(report.total_frames(), 608.0);
+        assert!((report.total_seconds() - 608.0 / 48_000.0).abs() < 1e-12);
+        assert_eq!(report.total().as_micros(), 12_666);
+    }
+}
@@ -0,0 +1,251 @@
+//! A typed façade for `MixerType::MatrixMixer`.
+//!
+//! The matrix mixer routes every input channel to every output channel with its own gain. Its
+//! volume parameters are addressed by packing channel numbers into the element index: the gain
+//! from input channel `i` to output channel `o` is the global volume of element
+//! `(i << 16) | o`, and the master volume is the global volume of element `0xFFFFFFFF`. Channels
+//! are numbered across all buses of a scope, so the first channel of the second input bus
+//! follows the last channel of the first.
+//!
+//! ```no_run
+//! # use coreaudio::audio_unit::matrix_mixer::MatrixMixer;
+//! # use coreaudio::audio_unit::Scope;
+//! # fn main() -> Result<(), coreaudio::Error> {
+//! let mut mixer = MatrixMixer::new()?;
+//! mixer.set_element_count(Scope::Input, 2)?;
+//! mixer.initialize()?;
+//! mixer.set_master_volume(1.0)?;
+//! mixer.set_input_volume(0, 1.0)?;
+//! mixer.set_output_volume(0, 1.0)?;
+//! mixer.set_crosspoint(0, 0, 0.5)?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Crosspoint volumes default to `0.0`, and the master, input and output volumes must also be
+//! raised for any audio to pass.
+
+use std::ops::{Deref, DerefMut};
+
+use super::{AudioUnit, Bus, Element, MixerType, Scope};
+use crate::error::{AudioUnitError, Error};
+use sys;
+
+/// `kMatrixMixerParam_Volume`
+const VOLUME: u32 = 0;
+/// `kMatrixMixerParam_Enable`
+const ENABLE: u32 = 1;
+/// `kMatrixMixerParam_PreAveragePower`
+const PRE_AVERAGE_POWER: u32 = 1000;
+/// `kMatrixMixerParam_PrePeakHoldLevel`
+const PRE_PEAK_HOLD_LEVEL: u32 = 2000;
+/// `kMatrixMixerParam_PostAveragePower`
+const POST_AVERAGE_POWER: u32 = 3000;
+/// `kMatrixMixerParam_PostPeakHoldLevel`
+const POST_PEAK_HOLD_LEVEL: u32 = 4000;
+
+/// The element index of the master volume.
+pub const MASTER_ELEMENT: u32 = 0xFFFF_FFFF;
+
+/// The element index of the crosspoint from input channel `input` to output channel `output`.
+///
+/// Returns `Error::AudioUnit(AudioUnitError::InvalidElement)` if either channel does not fit in
+/// 16 bits, or if both are `0xFFFF` as that is the master element.
+pub fn crosspoint_element(input: u32, output: u32) -> Result<u32, Error> {
+    let element = match (input, output) {
+        (0..=0xFFFF, 0..=0xFFFF) => (input << 16) | output,
+        _ => MASTER_ELEMENT,
+    };
+    if element == MASTER_ELEMENT {
+        return Err(Error::AudioUnit(AudioUnitError::InvalidElement));
+    }
+    Ok(element)
+}
+
+/// The input and output channels of a crosspoint element index.
+pub fn crosspoint_channels(element: u32) -> (u32, u32) {
+    (element >> 16, element & 0xFFFF)
+}
+
+/// The metered levels of a channel, in decibels.
+///
+/// The `pre` levels are measured before the channel's volume is applied and the `post` levels
+/// after.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Levels {
+    pub pre_average_power: f32,
+    pub pre_peak_hold: f32,
+    pub post_average_power: f32,
+    pub post_peak_hold: f32,
+}
+
+/// A mixer with a gain for every pair of input and output channels, wrapping
+/// `MixerType::MatrixMixer`.
+///
+/// **Available** in OS X v10.3 and later and iOS 2.0 and later.
+pub struct MatrixMixer {
+    unit: AudioUnit,
+}
+
+impl MatrixMixer {
+    /// Create a new instance of the matrix mixer unit.
+    pub fn new() -> Result<Self, Error> {
+        let unit = AudioUnit::new(MixerType::MatrixMixer)?;
+        Ok(MatrixMixer { unit })
+    }
+
+    /// Unwrap the underlying **AudioUnit**.
+    pub fn into_inner(self) -> AudioUnit {
+        self.unit
+    }
+
+    /// The gain from input channel `input` to output channel `output`.
+    pub fn crosspoint(&self, input: u32, output: u32) -> Result<f32, Error> {
+        let element = crosspoint_element(input, output)?;
+        self.unit.get_parameter(VOLUME, Scope::Global, element)
+    }
+
+    /// Sets the gain from input channel `input` to output channel `output`.
+    pub fn set_crosspoint(&mut self, input: u32, output: u32, gain: f32) -> Result<(), Error> {
+        let element = crosspoint_element(input, output)?;
+        self.unit
+            .set_parameter(VOLUME, Scope::Global, element, gain, 0)
+    }
+
+    /// The gain applied to all output channels.
+    pub fn master_volume(&self) -> Result<f32, Error> {
+        self.unit
+            .get_parameter(VOLUME, Scope::Global, MASTER_ELEMENT)
+    }
+
+    /// Sets the gain applied to all output channels.
+    pub fn set_master_volume(&mut self, gain: f32) -> Result<(), Error> {
+        self.unit
+            .set_parameter(VOLUME, Scope::Global, MASTER_ELEMENT, gain, 0)
+    }
+
+    /// The gain of an input channel, applied before the crosspoints.
+    pub fn input_volume(&self, channel: u32) -> Result<f32, Error> {
+        self.unit.get_parameter(VOLUME, Scope::Input, channel)
+    }
+
+    /// Sets the gain of an input channel, applied before the crosspoints.
+    pub fn set_input_volume(&mut self, channel: u32, gain: f32) -> Result<(), Error> {
+        self.unit
+            .set_parameter(VOLUME, Scope::Input, channel, gain, 0)
+    }
+
+    /// The gain of an output channel, applied after the crosspoints.
+    pub fn output_volume(&self, channel: u32) -> Result<f32, Error> {
+        self.unit.get_parameter(VOLUME, Scope::Output, channel)
+    }
+
+    /// Sets the gain of an output channel, applied after the crosspoints.
+    pub fn set_output_volume(&mut self, channel: u32, gain: f32) -> Result<(), Error> {
+        self.unit
+            .set_parameter(VOLUME, Scope::Output, channel, gain, 0)
+    }
+
+    /// Whether an input or output bus is enabled.
+    pub fn is_bus_enabled(&self, scope: Scope, bus: impl Into<Bus>) -> Result<bool, Error> {
+        let enabled = self.unit.get_parameter(ENABLE, scope, bus)?;
+        Ok(enabled != 0.0)
+    }
+
+    /// Enable or disable an input or output bus. Disabled input buses are not pulled.
+    pub fn set_bus_enabled(
+        &mut self,
+        scope: Scope,
+        bus: impl Into<Bus>,
+        enabled: bool,
+    ) -> Result<(), Error> {
+        let value = if enabled { 1.0 } else { 0.0 };
+        self.unit.set_parameter(ENABLE, scope, bus, value, 0)
+    }
+
+    /// Whether the levels of the channels are metered.
+    pub fn is_metering_enabled(&self) -> Result<bool, Error> {
+        let enabled: u32 = self.unit.get_property(
+            sys::kAudioUnitProperty_MeteringMode,
+            Scope::Global,
+            Element::Output,
+        )?;
+        Ok(enabled != 0)
+    }
+
+    /// Enable or disable metering of the channel levels, which is off by default.
+    pub fn set_metering_enabled(&mut self, enabled: bool) -> Result<(), Error> {
+        let enabled = enabled as u32;
+        self.unit.set_property(
+            sys::kAudioUnitProperty_MeteringMode,
+            Scope::Global,
+            Element::Output,
+            Some(&enabled),
+        )
+    }
+
+    fn levels(&self, scope: Scope, channel: u32) -> Result<Levels, Error> {
+        let get = |id| self.unit.get_parameter(id, scope, channel);
+        Ok(Levels {
+            pre_average_power: get(PRE_AVERAGE_POWER)?,
+            pre_peak_hold: get(PRE_PEAK_HOLD_LEVEL)?,
+            post_average_power: get(POST_AVERAGE_POWER)?,
+            post_peak_hold: get(POST_PEAK_HOLD_LEVEL)?,
+        })
+    }
+
+    /// The levels of an input channel. Requires metering to be enabled.
+    pub fn input_levels(&self, channel: u32) -> Result<Levels, Error> {
+        self.levels(Scope::Input, channel)
+    }
+
+    /// The levels of an output channel. Requires metering to be enabled.
+    pub fn output_levels(&self, channel: u32) -> Result<Levels, Error> {
+        self.levels(Scope::Output, channel)
+    }
+}
+
+impl Deref for MatrixMixer {
+    type Target = AudioUnit;
+    fn deref(&self) -> &AudioUnit {
+        &self.unit
+    }
+}
+
+impl DerefMut for MatrixMixer {
+    fn deref_mut(&mut self) -> &mut AudioUnit {
+        &mut self.unit
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_crosspoint_element() {
+        assert_eq!(crosspoint_element(0, 0).unwrap(), 0);
+        assert_eq!(crosspoint_element(1, 2).unwrap(), 0x0001_0002);
+        assert_eq!(crosspoint_element(0xFFFF, 0).unwrap(), 0xFFFF_0000);
+        assert_eq!(crosspoint_channels(0x0003_0004), (3, 4));
+        for &(input, output) in &[(0, 1), (7, 0), (0x1234, 0xFFFE)] {
+            let element = crosspoint_element(input, output).unwrap();
+            assert_eq!(crosspoint_channels(element), (input, output));
+        }
+        assert!(crosspoint_element(0x1_0000, 0).is_err());
+        assert!(crosspoint_element(0, 0x1_0000).is_err());
+        assert!(crosspoint_element(0xFFFF, 0xFFFF).is_err());
+    }
+
+    #[test]
+    fn test_matrix_mixer() {
+        let mut mixer = MatrixMixer::new().unwrap();
+        mixer.initialize().unwrap();
+        mixer.set_master_volume(1.0).unwrap();
+        mixer.set_crosspoint(1, 0, 0.25).unwrap();
+        assert_eq!(mixer.crosspoint(1, 0).unwrap(), 0.25);
+        assert_eq!(mixer.master_volume().unwrap(), 1.0);
+        mixer.set_bus_enabled(Scope::Input, 0, false).unwrap();
+        assert!(!mixer.is_bus_enabled(Scope::Input, 0).unwrap());
+    }
+}
@@ -28,6 +28,10 @@ use crate::try_os_status;
 use crate::{SampleFormat, StreamFormat};
 
 use self::list::AudioUnitInfo;
+pub use self::parameter::{
+    AudioUnitParameterUnit, ParameterDisplayType, ParameterFlags, ParameterInfo, ParameterRange,
+};
+pub use self::render::FrameCounter;
 pub use types::{
     EffectType, FormatConverterType, GeneratorType, IOType, MixerType, MusicDeviceType, Type,
 };
@@ -35,9 +39,26 @@ pub use types::{
 #[cfg(target_os = "macos")]
 pub mod macos_helpers;
 
+pub mod channel_layout;
+pub mod channel_mix;
+pub mod effects;
+pub mod graph;
+pub mod host_transport;
+pub mod instrument;
 pub mod list;
+pub mod listener;
+pub mod matrix_mixer;
+pub mod music_device;
+pub mod offline;
+pub mod parameter;
+pub mod player;
+pub mod preset;
+pub mod property;
+pub mod render;
 pub mod render_callback;
 pub mod types;
+pub mod typestate;
+pub mod voice_processing;
 
 /// The input and output **Scope**s.
 ///
@@ -64,6 +85,26 @@ pub enum Element {
     Input = 1,
 }
 
+/// The index of a bus (an **Element**) within a **Scope**.
+///
+/// Units such as `MixerType::MultiChannelMixer` or `FormatConverterType::Merger` have more than
+/// the two buses named by **Element**. Functions taking a bus accept either an **Element** or a
+/// `u32` index, e.g. `unit.bus_stream_format(Scope::Input, 5)`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Bus(pub u32);
+
+impl From<Element> for Bus {
+    fn from(elem: Element) -> Self {
+        Bus(elem as u32)
+    }
+}
+
+impl From<u32> for Bus {
+    fn from(index: u32) -> Self {
+        Bus(index)
+    }
+}
+
 /// A rust representation of the sys::AudioUnit, including a pointer to the current rendering callback.
 ///
 /// Find the original Audio Unit Programming Guide [here](https://developer.apple.com/library/mac/documentation/MusicAudio/Conceptual/AudioUnitProgrammingGuide/TheAudioUnit/TheAudioUnit.html).
@@ -73,6 +114,14 @@ pub struct AudioUnit {
     started: bool,
     maybe_render_callback: Option<*mut render_callback::InputProcFnWrapper>,
     maybe_input_callback: Option<InputCallback>,
+    render_notifies: Vec<*mut render_callback::InputProcFnWrapper>,
+    // The installed host transport is the last one if `host_transport_installed`. Replaced ones
+    // are kept until the unit is uninitialized, as the render thread may still be calling them.
+    #[allow(clippy::vec_box)]
+    host_transports: Vec<Box<std::sync::Arc<dyn host_transport::HostTransport>>>,
+    host_transport_installed: bool,
+    // Shared with property listeners, which must not remove themselves once the unit is disposed.
+    listener_instance: listener::SharedInstance,
 }
 
 struct InputCallback {
@@ -176,6 +225,10 @@ impl AudioUnit {
                 started: false,
                 maybe_render_callback: None,
                 maybe_input_callback: None,
+                render_notifies: Vec::new(),
+                host_transports: Vec::new(),
+                host_transport_installed: false,
+                listener_instance: listener::SharedInstance::new(instance),
             })
         }
     }
@@ -215,11 +268,16 @@ impl AudioUnit {
             try_os_status!(sys::AudioUnitUninitialize(self.instance));
         }
         self.initialized = false;
+        self.release_host_transports();
         Ok(())
     }
 
     /// Sets the value for some property of the **AudioUnit**.
     ///
+    /// The size of `T` is not checked against the property. Prefer
+    /// [**AudioUnit::set**](./struct.AudioUnit#method.set) for properties in the
+    /// [**property**](./property/index.html) catalogue.
+    ///
     /// To clear an audio unit property value, set the data paramater with `None::<()>`.
     ///
     /// Clearing properties only works for those properties that do not have a default value.
@@ -239,7 +297,7 @@ impl AudioUnit {
         &mut self,
         id: u32,
         scope: Scope,
-        elem: Element,
+        elem: impl Into<Bus>,
         maybe_data: Option<&T>,
     ) -> Result<(), Error> {
         set_property(self.instance, id, scope, elem, maybe_data)
@@ -247,6 +305,10 @@ impl AudioUnit {
 
     /// Gets the value of an **AudioUnit** property.
     ///
+    /// The size of `T` is not checked against the property. Prefer
+    /// [**AudioUnit::get**](./struct.AudioUnit#method.get) for properties in the
+    /// [**property**](./property/index.html) catalogue.
+    ///
     /// **Available** in iOS 2.0 and later.
     ///
     /// Parameters
@@ -255,7 +317,7 @@ impl AudioUnit {
     /// - **id**: The identifier of the property.
     /// - **scope**: The audio unit scope for the property.
     /// - **elem**: The audio unit element for the property.
-    pub fn get_property<T>(&self, id: u32, scope: Scope, elem: Element) -> Result<T, Error> {
+    pub fn get_property<T>(&self, id: u32, scope: Scope, elem: impl Into<Bus>) -> Result<T, Error> {
         get_property(self.instance, id, scope, elem)
     }
 
@@ -347,14 +409,12 @@ impl AudioUnit {
     ///
     /// **Available** in iOS 2.0 and later.
     pub fn set_sample_rate(&mut self, sample_rate: f64) -> Result<(), Error> {
-        let id = sys::kAudioUnitProperty_SampleRate;
-        self.set_property(id, Scope::Input, Element::Output, Some(&sample_rate))
+        self.set::<property::SampleRate>(Scope::Input, Element::Output, &sample_rate)
     }
 
     /// Get the **AudioUnit**'s sample rate.
     pub fn sample_rate(&self) -> Result<f64, Error> {
-        let id = sys::kAudioUnitProperty_SampleRate;
-        self.get_property(id, Scope::Input, Element::Output)
+        self.get::<property::SampleRate>(Scope::Input, Element::Output)
     }
 
     /// Sets the current **StreamFormat** for the AudioUnit.
@@ -376,18 +436,104 @@ impl AudioUnit {
         stream_format: StreamFormat,
         scope: Scope,
     ) -> Result<(), Error> {
-        let id = sys::kAudioUnitProperty_StreamFormat;
-        let asbd = stream_format.to_asbd();
-        self.set_property(id, scope, Element::Output, Some(&asbd))
+        self.set_bus_stream_format(stream_format, scope, Element::Output)
     }
 
     /// Return the current Stream Format for the AudioUnit.
     pub fn stream_format(&self, scope: Scope) -> Result<StreamFormat, Error> {
-        let id = sys::kAudioUnitProperty_StreamFormat;
-        let asbd = self.get_property(id, scope, Element::Output)?;
+        self.bus_stream_format(scope, Element::Output)
+    }
+
+    /// Sets the **StreamFormat** of a single bus within the given scope.
+    ///
+    /// [**set_stream_format**](./struct.AudioUnit#method.set_stream_format) only configures the
+    /// first bus.
+    pub fn set_bus_stream_format(
+        &mut self,
+        stream_format: StreamFormat,
+        scope: Scope,
+        bus: impl Into<Bus>,
+    ) -> Result<(), Error> {
+        let asbd = stream_format.to_asbd();
+        self.set::<property::StreamFormat>(scope, bus, &asbd)
+    }
+
+    /// Return the current **StreamFormat** of a single bus within the given scope.
+    pub fn bus_stream_format(
+        &self,
+        scope: Scope,
+        bus: impl Into<Bus>,
+    ) -> Result<StreamFormat, Error> {
+        let asbd = self.get::<property::StreamFormat>(scope, bus)?;
         StreamFormat::from_asbd(asbd)
     }
 
+    /// The number of buses within the given scope.
+    ///
+    /// **Available** in iOS 2.0 and later.
+    pub fn element_count(&self, scope: Scope) -> Result<u32, Error> {
+        self.get::<property::ElementCount>(scope, Element::Output)
+    }
+
+    /// Sets the number of buses within the given scope.
+    ///
+    /// Only units with a variable number of buses, such as `MixerType::MultiChannelMixer`, allow
+    /// this, and only before they are initialized.
+    ///
+    /// **Available** in iOS 2.0 and later.
+    pub fn set_element_count(&mut self, scope: Scope, count: u32) -> Result<(), Error> {
+        self.set::<property::ElementCount>(scope, Element::Output, &count)
+    }
+
+    /// The processing latency of the unit, in seconds.
+    ///
+    /// Hosts delay the other signal paths by this amount to keep them aligned with the output of
+    /// the unit.
+    ///
+    /// **Available** in iOS 2.0 and later.
+    pub fn latency(&self) -> Result<f64, Error> {
+        self.get::<property::Latency>(Scope::Global, Element::Output)
+    }
+
+    /// The time it takes for the output of the unit to decay to silence after its input stops,
+    /// in seconds.
+    ///
+    /// **Available** in iOS 2.0 and later.
+    pub fn tail_time(&self) -> Result<f64, Error> {
+        self.get::<property::TailTime>(Scope::Global, Element::Output)
+    }
+
+    /// The maximum portion of CPU time the unit may use, from `0.0` to `1.0`, where `0.0` means
+    /// there is no limit.
+    ///
+    /// **Available** in iOS 2.0 and later.
+    pub fn cpu_load(&self) -> Result<f64, Error> {
+        self.get::<property::CpuLoad>(Scope::Global, Element::Output)
+    }
+
+    /// Sets the maximum portion of CPU time the unit may use, from `0.0` to `1.0`.
+    ///
+    /// **Available** in iOS 2.0 and later.
+    pub fn set_cpu_load(&mut self, load: f64) -> Result<(), Error> {
+        self.set::<property::CpuLoad>(Scope::Global, Element::Output, &load)
+    }
+
+    /// The maximum number of frames the unit is asked to render at once.
+    ///
+    /// **Available** in iOS 2.0 and later.
+    pub fn maximum_frames_per_slice(&self) -> Result<u32, Error> {
+        self.get::<property::MaximumFramesPerSlice>(Scope::Global, Element::Output)
+    }
+
+    /// Sets the maximum number of frames the unit is asked to render at once.
+    ///
+    /// Must be set before the unit is initialized.
+    ///
+    /// **Available** in iOS 2.0 and later.
+    pub fn set_maximum_frames_per_slice(&mut self, frames: u32) -> Result<(), Error> {
+        self.set::<property::MaximumFramesPerSlice>(Scope::Global, Element::Output, &frames)
+    }
+
     /// Return the current output Stream Format for the AudioUnit.
     pub fn output_stream_format(&self) -> Result<StreamFormat, Error> {
         self.stream_format(Scope::Output)
@@ -413,8 +559,11 @@ impl Drop for AudioUnit {
 
             self.free_render_callback();
             self.free_input_callback();
-
-            sys::AudioComponentInstanceDispose(self.instance);
+            self.free_render_notifies();
+            self.free_host_transport();
+            self.listener_instance.dispose(|instance| {
+                sys::AudioComponentInstanceDispose(instance);
+            });
         }
     }
 }
@@ -441,7 +590,7 @@ pub fn set_property<T>(
     au: sys::AudioUnit,
     id: u32,
     scope: Scope,
-    elem: Element,
+    elem: impl Into<Bus>,
     maybe_data: Option<&T>,
 ) -> Result<(), Error> {
     let (data_ptr, size) = maybe_data
@@ -452,7 +601,7 @@ pub fn set_property<T>(
         })
         .unwrap_or_else(|| (::std::ptr::null(), 0));
     let scope = scope as c_uint;
-    let elem = elem as c_uint;
+    let elem = elem.into().0 as c_uint;
     unsafe {
         try_os_status!(sys::AudioUnitSetProperty(
             au, id, scope, elem, data_ptr, size
@@ -476,10 +625,10 @@ pub fn get_property<T>(
     au: sys::AudioUnit,
     id: u32,
     scope: Scope,
-    elem: Element,
+    elem: impl Into<Bus>,
 ) -> Result<T, Error> {
     let scope = scope as c_uint;
-    let elem = elem as c_uint;
+    let elem = elem.into().0 as c_uint;
     let mut size = ::std::mem::size_of::<T>() as u32;
     unsafe {
         let mut data_uninit = ::std::mem::MaybeUninit::<T>::uninit();
@@ -537,4 +686,35 @@ mod test {
             println!("{:?}", formats);
         }
     }
+
+    #[test]
+    fn test_latency_and_slice_size() {
+        let mut unit = AudioUnit::new(EffectType::Delay).unwrap();
+        unit.set_maximum_frames_per_slice(2048).unwrap();
+        assert_eq!(unit.maximum_frames_per_slice().unwrap(), 2048);
+        unit.initialize().unwrap();
+        assert!(unit.latency().unwrap() >= 0.0);
+        // The tail of a delay lasts at least as long as the delay time.
+        assert!(unit.tail_time().unwrap() > 0.0);
+    }
+
+    #[test]
+    fn test_bus_count_and_format() {
+        let mut unit = AudioUnit::new(MixerType::MultiChannelMixer).unwrap();
+        unit.set_element_count(Scope::Input, 4).unwrap();
+        assert_eq!(unit.element_count(Scope::Input).unwrap(), 4);
+
+        let mut format = unit.bus_stream_format(Scope::Input, 1).unwrap();
+        format.sample_rate = 22_050.0;
+        unit.set_bus_stream_format(format, Scope::Input, 1).unwrap();
+        assert_eq!(
+            unit.bus_stream_format(Scope::Input, 1).unwrap().sample_rate,
+            22_050.0
+        );
+        // The other buses keep their own formats.
+        assert_ne!(
+            unit.bus_stream_format(Scope::Input, 0).unwrap().sample_rate,
+            22_050.0
+        );
+    }
 }
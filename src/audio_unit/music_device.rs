@@ -0,0 +1,334 @@
+//! Playing **MusicDevice** units such as the DLS synth and the sampler.
+//!
+//! A [**MidiMessage**](./enum.MidiMessage) is sent to the unit with
+//! [**AudioUnit::send_midi**](../struct.AudioUnit#method.send_midi), and takes effect
+//! `sample_offset` frames into the next render cycle.
+//!
+//! ```no_run
+//! # use coreaudio::audio_unit::{AudioUnit, MusicDeviceType};
+//! # use coreaudio::audio_unit::music_device::MidiMessage;
+//! # fn main() -> Result<(), coreaudio::Error> {
+//! let mut synth = AudioUnit::new(MusicDeviceType::DLSSynth)?;
+//! synth.initialize()?;
+//! synth.send_midi(MidiMessage::ProgramChange { channel: 0, program: 19 }, 0)?;
+//! synth.send_midi(MidiMessage::NoteOn { channel: 0, note: 60, velocity: 100 }, 0)?;
+//! // ...
+//! synth.send_midi(MidiMessage::NoteOff { channel: 0, note: 60, velocity: 0 }, 128)?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::os::raw::c_uint;
+
+use super::AudioUnit;
+use crate::error::Error;
+use crate::try_os_status;
+use sys;
+
+/// The centre value of a 14-bit pitch bend, at which the pitch is unchanged.
+pub const PITCH_BEND_CENTER: u16 = 0x2000;
+
+/// A MIDI channel voice message.
+///
+/// Channels are numbered `0..=15`, and all other fields are 7-bit values in `0..=127` except
+/// the 14-bit `PitchBend` value in `0..=16383`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MidiMessage {
+    NoteOff {
+        channel: u8,
+        note: u8,
+        velocity: u8,
+    },
+    NoteOn {
+        channel: u8,
+        note: u8,
+        velocity: u8,
+    },
+    PolyPressure {
+        channel: u8,
+        note: u8,
+        pressure: u8,
+    },
+    ControlChange {
+        channel: u8,
+        controller: u8,
+        value: u8,
+    },
+    ProgramChange {
+        channel: u8,
+        program: u8,
+    },
+    ChannelPressure {
+        channel: u8,
+        pressure: u8,
+    },
+    PitchBend {
+        channel: u8,
+        value: u16,
+    },
+}
+
+impl MidiMessage {
+    /// The channel the message is sent on.
+    pub fn channel(&self) -> u8 {
+        match *self {
+            MidiMessage::NoteOff { channel, .. }
+            | MidiMessage::NoteOn { channel, .. }
+            | MidiMessage::PolyPressure { channel, .. }
+            | MidiMessage::ControlChange { channel, .. }
+            | MidiMessage::ProgramChange { channel, .. }
+            | MidiMessage::ChannelPressure { channel, .. }
+            | MidiMessage::PitchBend { channel, .. } => channel,
+        }
+    }
+
+    /// Encode the message as its status byte and two data bytes.
+    ///
+    /// Messages with a single data byte set the second to `0`. Returns
+    /// `Error::InvalidMidiMessage` if the channel or a data value is out of range.
+    pub fn to_bytes(&self) -> Result<[u8; 3], Error> {
+        let (kind, data1, data2) = match *self {
+            MidiMessage::NoteOff { note, velocity, .. } => (0x80, note, velocity),
+            MidiMessage::NoteOn { note, velocity, .. } => (0x90, note, velocity),
+            MidiMessage::PolyPressure { note, pressure, .. } => (0xA0, note, pressure),
+            MidiMessage::ControlChange {
+                controller, value, ..
+            } => (0xB0, controller, value),
+            MidiMessage::ProgramChange { program, .. } => (0xC0, program, 0),
+            MidiMessage::ChannelPressure { pressure, .. } => (0xD0, pressure, 0),
+            MidiMessage::PitchBend { value, .. } => {
+                if value > 0x3FFF {
+                    return Err(Error::InvalidMidiMessage);
+                }
+                (0xE0, (value & 0x7F) as u8, (value >> 7) as u8)
+            }
+        };
+        let channel = self.channel();
+        if channel > 0x0F || data1 > 0x7F || data2 > 0x7F {
+            return Err(Error::InvalidMidiMessage);
+        }
+        Ok([kind | channel, data1, data2])
+    }
+
+    /// Decode a channel voice message from its status byte and data bytes.
+    ///
+    /// Returns `None` for system messages, running status and truncated or malformed messages.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let status = *bytes.first()?;
+        let channel = status & 0x0F;
+        let data = |i: usize| bytes.get(i).cloned().filter(|&b| b <= 0x7F);
+        let message = match status & 0xF0 {
+            0x80 => MidiMessage::NoteOff {
+                channel,
+                note: data(1)?,
+                velocity: data(2)?,
+            },
+            0x90 => MidiMessage::NoteOn {
+                channel,
+                note: data(1)?,
+                velocity: data(2)?,
+            },
+            0xA0 => MidiMessage::PolyPressure {
+                channel,
+                note: data(1)?,
+                pressure: data(2)?,
+            },
+            0xB0 => MidiMessage::ControlChange {
+                channel,
+                controller: data(1)?,
+                value: data(2)?,
+            },
+            0xC0 => MidiMessage::ProgramChange {
+                channel,
+                program: data(1)?,
+            },
+            0xD0 => MidiMessage::ChannelPressure {
+                channel,
+                pressure: data(1)?,
+            },
+            0xE0 => MidiMessage::PitchBend {
+                channel,
+                value: data(1)? as u16 | (data(2)? as u16) << 7,
+            },
+            _ => return None,
+        };
+        Some(message)
+    }
+}
+
+/// Check that `data` is a single, complete System Exclusive message.
+fn check_sysex(data: &[u8]) -> Result<(), Error> {
+    match data {
+        [0xF0, body @ .., 0xF7] if body.iter().all(|&b| b <= 0x7F) => Ok(()),
+        _ => Err(Error::InvalidMidiMessage),
+    }
+}
+
+/// Identifies a note started with
+/// [**AudioUnit::start_note**](../struct.AudioUnit#method.start_note).
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct NoteInstance {
+    group: u32,
+    id: u32,
+}
+
+impl AudioUnit {
+    /// Sends a MIDI channel message to a **MusicDevice** unit, taking effect `sample_offset`
+    /// frames into the next render cycle.
+    ///
+    /// **Available** in iOS 5.0 and later.
+    pub fn send_midi(&mut self, message: MidiMessage, sample_offset: u32) -> Result<(), Error> {
+        let [status, data1, data2] = message.to_bytes()?;
+        unsafe {
+            try_os_status!(sys::MusicDeviceMIDIEvent(
+                self.instance,
+                status as c_uint,
+                data1 as c_uint,
+                data2 as c_uint,
+                sample_offset,
+            ));
+        }
+        Ok(())
+    }
+
+    /// Sends a System Exclusive message to a **MusicDevice** unit.
+    ///
+    /// `data` must include the leading `0xF0` and trailing `0xF7` bytes. SysEx messages take
+    /// effect at the start of the next render cycle.
+    ///
+    /// **Available** in iOS 5.0 and later.
+    pub fn send_sysex(&mut self, data: &[u8]) -> Result<(), Error> {
+        check_sysex(data)?;
+        unsafe {
+            try_os_status!(sys::MusicDeviceSysEx(
+                self.instance,
+                data.as_ptr(),
+                data.len() as u32,
+            ));
+        }
+        Ok(())
+    }
+
+    /// Starts a note on the given group (MIDI channel), `sample_offset` frames into the next
+    /// render cycle.
+    ///
+    /// Unlike a `NoteOn` message, `pitch` may be fractional and `velocity` is not quantized
+    /// to 7 bits, though both use the MIDI ranges. The note plays until it is passed to
+    /// [**stop_note**](./struct.AudioUnit#method.stop_note).
+    ///
+    /// **Available** in iOS 5.0 and later.
+    pub fn start_note(
+        &mut self,
+        group: u32,
+        pitch: f32,
+        velocity: f32,
+        sample_offset: u32,
+    ) -> Result<NoteInstance, Error> {
+        let params = sys::MusicDeviceNoteParams {
+            // Only the pitch and velocity are given.
+            argCount: 2,
+            mPitch: pitch,
+            mVelocity: velocity,
+            mControls: [sys::NoteParamsControlValue {
+                mID: 0,
+                mValue: 0.0,
+            }],
+        };
+        let mut id = 0;
+        unsafe {
+            try_os_status!(sys::MusicDeviceStartNote(
+                self.instance,
+                sys::kMusicNoteEvent_UseGroupInstrument,
+                group,
+                &mut id,
+                sample_offset,
+                &params,
+            ));
+        }
+        Ok(NoteInstance { group, id })
+    }
+
+    /// Stops a note started with [**start_note**](./struct.AudioUnit#method.start_note),
+    /// `sample_offset` frames into the next render cycle.
+    ///
+    /// **Available** in iOS 5.0 and later.
+    pub fn stop_note(&mut self, note: NoteInstance, sample_offset: u32) -> Result<(), Error> {
+        unsafe {
+            try_os_status!(sys::MusicDeviceStopNote(
+                self.instance,
+                note.group,
+                note.id,
+                sample_offset,
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_midi_message_bytes() {
+        let messages = [
+            (
+                MidiMessage::NoteOn {
+                    channel: 9,
+                    note: 36,
+                    velocity: 127,
+                },
+                [0x99, 36, 127],
+            ),
+            (
+                MidiMessage::ControlChange {
+                    channel: 0,
+                    controller: 64,
+                    value: 0,
+                },
+                [0xB0, 64, 0],
+            ),
+            (
+                MidiMessage::ProgramChange {
+                    channel: 15,
+                    program: 5,
+                },
+                [0xCF, 5, 0],
+            ),
+            (
+                MidiMessage::PitchBend {
+                    channel: 1,
+                    value: PITCH_BEND_CENTER,
+                },
+                [0xE1, 0x00, 0x40],
+            ),
+        ];
+        for &(message, bytes) in &messages {
+            assert_eq!(message.to_bytes().unwrap(), bytes);
+            assert_eq!(MidiMessage::from_bytes(&bytes), Some(message));
+        }
+
+        let bad_channel = MidiMessage::NoteOff {
+            channel: 16,
+            note: 60,
+            velocity: 0,
+        };
+        assert!(bad_channel.to_bytes().is_err());
+        let bad_bend = MidiMessage::PitchBend {
+            channel: 0,
+            value: 0x4000,
+        };
+        assert!(bad_bend.to_bytes().is_err());
+        assert_eq!(MidiMessage::from_bytes(&[0x90, 60]), None);
+        assert_eq!(MidiMessage::from_bytes(&[0xF8]), None);
+    }
+
+    #[test]
+    fn test_check_sysex() {
+        // GM System On.
+        assert!(check_sysex(&[0xF0, 0x7E, 0x7F, 0x09, 0x01, 0xF7]).is_ok());
+        assert!(check_sysex(&[0xF0, 0x7E, 0x7F, 0x09, 0x01]).is_err());
+        assert!(check_sysex(&[0xF0, 0x90, 0xF7]).is_err());
+        assert!(check_sysex(&[0xF0]).is_err());
+    }
+}
@@ -0,0 +1,505 @@
+//! Rendering an **AudioUnit** faster than real time, e.g. to bounce a graph to a file.
+//!
+//! An [**OfflineRenderer**](./struct.OfflineRenderer) pulls a unit, usually an
+//! `IOType::GenericOutput` at the end of a [**Graph**](../graph/struct.Graph) (see
+//! [**render_graph**](./struct.OfflineRenderer#method.render_graph)), in fixed-size slices with
+//! advancing sample times, and hands each slice to a [**Sink**](./trait.Sink):
+//!
+//! - a `Vec` collecting interleaved samples,
+//! - an [**FnSink**](./struct.FnSink) wrapping a closure, or
+//! - a [**WavWriter**](./struct.WavWriter) writing a WAV file.
+//!
+//! ```no_run
+//! # use coreaudio::audio_unit::offline::{OfflineRenderer, WavWriter};
+//! # use coreaudio::audio_unit::{AudioUnit, IOType};
+//! # fn main() -> Result<(), coreaudio::Error> {
+//! let mut unit = AudioUnit::new(IOType::GenericOutput)?;
+//! // ... connect the rest of the graph or set a render callback ...
+//! unit.initialize()?;
+//!
+//! let format = unit.output_stream_format()?;
+//! let mut wav = WavWriter::create("bounce.wav", format.sample_rate as u32, format.channels as u16)?;
+//! OfflineRenderer::new(512).render::<f32, _, _>(&mut unit, 10 * 44_100, &mut wav, |progress| {
+//!     println!("{:.0}%", progress.fraction() * 100.0);
+//! })?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use super::graph::Graph;
+use super::render_callback::ActionFlags;
+use super::{property, AudioUnit, Bus, FrameCounter, Scope};
+use crate::error::{self, Error};
+use crate::{AudioBufferList, Sample};
+
+/// Receives the audio rendered by an [**OfflineRenderer**](./struct.OfflineRenderer).
+pub trait Sink<S: Sample> {
+    /// Called with each rendered slice.
+    fn write(&mut self, buffer: &AudioBufferList<S>) -> Result<(), Error>;
+
+    /// Called once after the last slice has been written.
+    fn finish(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+/// Appends the rendered audio as interleaved samples.
+impl<S: Sample> Sink<S> for Vec<S> {
+    fn write(&mut self, buffer: &AudioBufferList<S>) -> Result<(), Error> {
+        if buffer.is_interleaved() {
+            self.extend_from_slice(buffer.buffer(0));
+        } else {
+            self.reserve(buffer.frames() * buffer.channels());
+            for frame in 0..buffer.frames() {
+                for channel in 0..buffer.channels() {
+                    self.push(buffer.buffer(channel)[frame].clone());
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A [**Sink**](./trait.Sink) calling a closure with each rendered slice.
+pub struct FnSink<F>(pub F);
+
+impl<S, F> Sink<S> for FnSink<F>
+where
+    S: Sample,
+    F: FnMut(&AudioBufferList<S>) -> Result<(), Error>,
+{
+    fn write(&mut self, buffer: &AudioBufferList<S>) -> Result<(), Error> {
+        (self.0)(buffer)
+    }
+}
+
+/// Sample types that may be written to a WAV file.
+pub trait WavSample: Sample {
+    /// The `wFormatTag` of the format chunk.
+    const FORMAT_TAG: u16;
+    /// Append the little-endian bytes of the sample, as stored in a WAV file.
+    fn write_le(&self, bytes: &mut Vec<u8>);
+}
+
+// WAVE_FORMAT_PCM and WAVE_FORMAT_IEEE_FLOAT.
+const WAVE_FORMAT_PCM: u16 = 1;
+const WAVE_FORMAT_IEEE_FLOAT: u16 = 3;
+
+impl WavSample for f32 {
+    const FORMAT_TAG: u16 = WAVE_FORMAT_IEEE_FLOAT;
+    fn write_le(&self, bytes: &mut Vec<u8>) {
+        bytes.extend_from_slice(&self.to_le_bytes());
+    }
+}
+
+impl WavSample for i32 {
+    const FORMAT_TAG: u16 = WAVE_FORMAT_PCM;
+    fn write_le(&self, bytes: &mut Vec<u8>) {
+        bytes.extend_from_slice(&self.to_le_bytes());
+    }
+}
+
+impl WavSample for i16 {
+    const FORMAT_TAG: u16 = WAVE_FORMAT_PCM;
+    fn write_le(&self, bytes: &mut Vec<u8>) {
+        bytes.extend_from_slice(&self.to_le_bytes());
+    }
+}
+
+impl WavSample for i8 {
+    const FORMAT_TAG: u16 = WAVE_FORMAT_PCM;
+    fn write_le(&self, bytes: &mut Vec<u8>) {
+        // 8-bit WAV samples are unsigned.
+        bytes.push((*self as u8) ^ 0x80);
+    }
+}
+
+// The size of the RIFF header, format chunk and data chunk header.
+const WAV_HEADER_SIZE: u32 = 44;
+
+/// A [**Sink**](./trait.Sink) writing a WAV file.
+///
+/// The header is written up front and its sizes are filled in by `finish`, so the writer must be
+/// seekable. The sizes in the header are 32-bit, so `Error::WavTooLarge` is returned by the write
+/// that would take the file past 4 GiB.
+pub struct WavWriter<W: Write + Seek> {
+    writer: W,
+    sample_rate: u32,
+    channels: u16,
+    header_written: bool,
+    data_size: u32,
+    bytes: Vec<u8>,
+}
+
+impl WavWriter<BufWriter<File>> {
+    /// Create or truncate the file at `path`.
+    pub fn create<P: AsRef<Path>>(path: P, sample_rate: u32, channels: u16) -> Result<Self, Error> {
+        let file = File::create(path)?;
+        Ok(WavWriter::new(BufWriter::new(file), sample_rate, channels))
+    }
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    /// Write a WAV file to `writer`, which should be positioned at its start.
+    pub fn new(writer: W, sample_rate: u32, channels: u16) -> Self {
+        WavWriter {
+            writer,
+            sample_rate,
+            channels,
+            header_written: false,
+            data_size: 0,
+            bytes: Vec::new(),
+        }
+    }
+
+    /// Unwrap the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    fn write_header<S: WavSample>(&mut self) -> io::Result<()> {
+        let bits = (std::mem::size_of::<S>() * 8) as u16;
+        let block_align = self.channels * bits / 8;
+        let mut header = Vec::with_capacity(WAV_HEADER_SIZE as usize);
+        header.extend_from_slice(b"RIFF");
+        header.extend_from_slice(&(WAV_HEADER_SIZE - 8 + self.data_size).to_le_bytes());
+        header.extend_from_slice(b"WAVEfmt ");
+        header.extend_from_slice(&16u32.to_le_bytes());
+        header.extend_from_slice(&S::FORMAT_TAG.to_le_bytes());
+        header.extend_from_slice(&self.channels.to_le_bytes());
+        header.extend_from_slice(&self.sample_rate.to_le_bytes());
+        header.extend_from_slice(&(self.sample_rate * block_align as u32).to_le_bytes());
+        header.extend_from_slice(&block_align.to_le_bytes());
+        header.extend_from_slice(&bits.to_le_bytes());
+        header.extend_from_slice(b"data");
+        header.extend_from_slice(&self.data_size.to_le_bytes());
+        self.writer.write_all(&header)
+    }
+}
+
+impl<S: WavSample, W: Write + Seek> Sink<S> for WavWriter<W> {
+    fn write(&mut self, buffer: &AudioBufferList<S>) -> Result<(), Error> {
+        if buffer.channels() != self.channels as usize {
+            return Err(Error::UnsupportedStreamFormat);
+        }
+        if !self.header_written {
+            self.write_header::<S>()?;
+            self.header_written = true;
+        }
+        self.bytes.clear();
+        if buffer.is_interleaved() {
+            for sample in buffer.buffer(0) {
+                sample.write_le(&mut self.bytes);
+            }
+        } else {
+            for frame in 0..buffer.frames() {
+                for channel in 0..buffer.channels() {
+                    buffer.buffer(channel)[frame].write_le(&mut self.bytes);
+                }
+            }
+        }
+        // The RIFF chunk size covers the rest of the header as well as the data.
+        let riff_size =
+            (WAV_HEADER_SIZE - 8) as u64 + self.data_size as u64 + self.bytes.len() as u64;
+        if riff_size > u32::MAX as u64 {
+            return Err(Error::WavTooLarge);
+        }
+        self.writer.write_all(&self.bytes)?;
+        self.data_size += self.bytes.len() as u32;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), Error> {
+        // Rewrite the header with the final sizes.
+        self.writer.seek(SeekFrom::Start(0))?;
+        self.write_header::<S>()?;
+        self.header_written = true;
+        self.writer.seek(SeekFrom::End(0))?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// The progress of an offline render.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Progress {
+    pub frames_rendered: u64,
+    pub total_frames: u64,
+}
+
+impl Progress {
+    /// The rendered portion, from `0.0` to `1.0`.
+    pub fn fraction(&self) -> f64 {
+        if self.total_frames == 0 {
+            1.0
+        } else {
+            self.frames_rendered as f64 / self.total_frames as f64
+        }
+    }
+
+    /// Whether all frames have been rendered.
+    pub fn is_complete(&self) -> bool {
+        self.frames_rendered >= self.total_frames
+    }
+}
+
+/// Pulls an **AudioUnit** in fixed-size slices, faster than real time.
+#[derive(Copy, Clone, Debug)]
+pub struct OfflineRenderer {
+    slice_frames: u32,
+    bus: Bus,
+    preflight: bool,
+}
+
+impl OfflineRenderer {
+    /// A renderer pulling `slice_frames` frames at a time from output bus `0`.
+    ///
+    /// `slice_frames` must not exceed the `MaximumFramesPerSlice` of the unit.
+    pub fn new(slice_frames: u32) -> Self {
+        OfflineRenderer {
+            slice_frames,
+            bus: Bus(0),
+            preflight: false,
+        }
+    }
+
+    /// Pull from the given output bus instead.
+    pub fn bus(mut self, bus: impl Into<Bus>) -> Self {
+        self.bus = bus.into();
+        self
+    }
+
+    /// Run a preflight pass over all frames with `ActionFlags::OFFLINE_PREFLIGHT` before
+    /// rendering, as required by offline effects that analyse their input first.
+    pub fn preflight(mut self, preflight: bool) -> Self {
+        self.preflight = preflight;
+        self
+    }
+
+    /// Render `total_frames` frames from the initialized `unit` into `sink`.
+    ///
+    /// Each slice is rendered with `ActionFlags::OFFLINE_RENDER`, and the last one also with
+    /// `ActionFlags::OFFLINE_COMPLETE`. `progress` is called after each slice, and the final
+    /// progress is returned once the sink has finished.
+    pub fn render<S, K, F>(
+        &self,
+        unit: &mut AudioUnit,
+        total_frames: u64,
+        sink: &mut K,
+        mut progress: F,
+    ) -> Result<Progress, Error>
+    where
+        S: Sample,
+        K: Sink<S>,
+        F: FnMut(Progress),
+    {
+        let max_frames = unit.get::<property::MaximumFramesPerSlice>(Scope::Global, 0)?;
+        if self.slice_frames == 0 || self.slice_frames > max_frames {
+            return Err(Error::AudioUnit(
+                error::audio_unit::Error::TooManyFramesToProcess,
+            ));
+        }
+        let stream_format = unit.bus_stream_format(Scope::Output, self.bus)?;
+        let mut buffer =
+            AudioBufferList::<S>::for_stream_format(&stream_format, self.slice_frames as usize)?;
+
+        if self.preflight {
+            self.pass(
+                unit,
+                total_frames,
+                &mut buffer,
+                ActionFlags::OFFLINE_PREFLIGHT,
+                |_, _| Ok(()),
+            )?;
+        }
+
+        let mut state = Progress {
+            frames_rendered: 0,
+            total_frames,
+        };
+        self.pass(
+            unit,
+            total_frames,
+            &mut buffer,
+            ActionFlags::OFFLINE_RENDER,
+            |buffer, frames| {
+                sink.write(buffer)?;
+                state.frames_rendered += frames as u64;
+                progress(state);
+                Ok(())
+            },
+        )?;
+        sink.finish()?;
+        Ok(state)
+    }
+
+    /// Initialize `graph` if necessary and render `total_frames` frames from its output node into
+    /// `sink`, as [**render**](./struct.OfflineRenderer#method.render) does for a single unit.
+    ///
+    /// The output node is the first sink node of the graph that is an I/O unit (see
+    /// [**Graph::output_node**](../graph/struct.Graph#method.output_node)), usually an
+    /// `IOType::GenericOutput`. The graph must not be started, as the output node would then be
+    /// pulled by the device as well.
+    pub fn render_graph<S, K, F>(
+        &self,
+        graph: &mut Graph,
+        total_frames: u64,
+        sink: &mut K,
+        progress: F,
+    ) -> Result<Progress, Error>
+    where
+        S: Sample,
+        K: Sink<S>,
+        F: FnMut(Progress),
+    {
+        let output = graph.output_node()?;
+        graph.initialize()?;
+        self.render(graph.node_mut(output), total_frames, sink, progress)
+    }
+
+    // Render all slices with the given flags, from sample time zero.
+    fn pass<S, F>(
+        &self,
+        unit: &mut AudioUnit,
+        total_frames: u64,
+        buffer: &mut AudioBufferList<S>,
+        flags: ActionFlags,
+        mut f: F,
+    ) -> Result<(), Error>
+    where
+        S: Sample,
+        F: FnMut(&AudioBufferList<S>, u32) -> Result<(), Error>,
+    {
+        let mut counter = FrameCounter::new();
+        let mut remaining = total_frames;
+        while remaining > 0 {
+            let frames = remaining.min(self.slice_frames as u64) as u32;
+            remaining -= frames as u64;
+            let mut slice_flags = flags;
+            if remaining == 0 && flags.contains(ActionFlags::OFFLINE_RENDER) {
+                slice_flags.insert(ActionFlags::OFFLINE_COMPLETE);
+            }
+            let time_stamp = counter.next(frames);
+            unit.render(&mut slice_flags, &time_stamp, self.bus.0, frames, buffer)?;
+            f(buffer, frames)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::render_callback::{self, data};
+    use super::super::EffectType;
+    use super::*;
+    use std::io::Cursor;
+
+    fn stereo_buffer(interleaved: bool) -> AudioBufferList<i16> {
+        let mut buffer = if interleaved {
+            AudioBufferList::new(2, 4)
+        } else {
+            AudioBufferList::new_non_interleaved(2, 4)
+        };
+        buffer.reset(2);
+        if interleaved {
+            buffer.buffer_mut(0).copy_from_slice(&[1, -1, 2, -2]);
+        } else {
+            buffer.buffer_mut(0).copy_from_slice(&[1, 2]);
+            buffer.buffer_mut(1).copy_from_slice(&[-1, -2]);
+        }
+        buffer
+    }
+
+    #[test]
+    fn test_vec_sink() {
+        for &interleaved in &[true, false] {
+            let mut samples = Vec::new();
+            Sink::write(&mut samples, &stereo_buffer(interleaved)).unwrap();
+            assert_eq!(samples, vec![1, -1, 2, -2]);
+        }
+    }
+
+    #[test]
+    fn test_wav_writer() {
+        let mut wav = WavWriter::new(Cursor::new(Vec::new()), 48_000, 2);
+        Sink::<i16>::write(&mut wav, &stereo_buffer(false)).unwrap();
+        Sink::<i16>::finish(&mut wav).unwrap();
+        let bytes = wav.into_inner().into_inner();
+
+        assert_eq!(bytes.len(), 44 + 8);
+        assert_eq!(&bytes[0..4], b"RIFF");
+        assert_eq!(&bytes[4..8], &44u32.to_le_bytes());
+        assert_eq!(&bytes[8..16], b"WAVEfmt ");
+        assert_eq!(&bytes[20..22], &WAVE_FORMAT_PCM.to_le_bytes());
+        assert_eq!(&bytes[22..24], &2u16.to_le_bytes());
+        assert_eq!(&bytes[24..28], &48_000u32.to_le_bytes());
+        assert_eq!(&bytes[28..32], &192_000u32.to_le_bytes());
+        assert_eq!(&bytes[32..34], &4u16.to_le_bytes());
+        assert_eq!(&bytes[34..36], &16u16.to_le_bytes());
+        assert_eq!(&bytes[36..40], b"data");
+        assert_eq!(&bytes[40..44], &8u32.to_le_bytes());
+        assert_eq!(&bytes[44..46], &1i16.to_le_bytes());
+        assert_eq!(&bytes[46..48], &(-1i16).to_le_bytes());
+    }
+
+    #[test]
+    fn test_wav_writer_too_large() {
+        let mut wav = WavWriter::new(Cursor::new(Vec::new()), 48_000, 2);
+        wav.data_size = u32::MAX - (WAV_HEADER_SIZE - 8) - 8;
+        Sink::<i16>::write(&mut wav, &stereo_buffer(false)).unwrap();
+        assert!(matches!(
+            Sink::<i16>::write(&mut wav, &stereo_buffer(false)),
+            Err(Error::WavTooLarge)
+        ));
+        assert_eq!(wav.data_size, u32::MAX - (WAV_HEADER_SIZE - 8));
+    }
+
+    #[test]
+    fn test_progress() {
+        let progress = Progress {
+            frames_rendered: 250,
+            total_frames: 1000,
+        };
+        assert_eq!(progress.fraction(), 0.25);
+        assert!(!progress.is_complete());
+        let empty = Progress {
+            frames_rendered: 0,
+            total_frames: 0,
+        };
+        assert_eq!(empty.fraction(), 1.0);
+        assert!(empty.is_complete());
+    }
+
+    #[test]
+    fn test_offline_render() {
+        let mut unit = AudioUnit::new(EffectType::Delay).unwrap();
+        type Args = render_callback::Args<data::NonInterleaved<f32>>;
+        unit.set_render_callback(|args: Args| {
+            let Args { mut data, .. } = args;
+            for channel in data.channels_mut() {
+                for sample in channel.iter_mut() {
+                    *sample = 0.25;
+                }
+            }
+            Ok(())
+        })
+        .unwrap();
+        unit.initialize().unwrap();
+
+        let channels = unit.output_stream_format().unwrap().channels as usize;
+        let mut samples = Vec::<f32>::new();
+        let mut updates = Vec::new();
+        let progress = OfflineRenderer::new(256)
+            .render(&mut unit, 1000, &mut samples, |progress| {
+                updates.push(progress.frames_rendered)
+            })
+            .unwrap();
+        assert!(progress.is_complete());
+        assert_eq!(updates, vec![256, 512, 768, 1000]);
+        assert_eq!(samples.len(), 1000 * channels);
+    }
+}
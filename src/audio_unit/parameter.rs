@@ -0,0 +1,679 @@
+//! Getting and setting **AudioUnit** parameters, and describing them with `ParameterInfo`.
+//!
+//! Parameters are the values of an audio unit that may be changed while it is rendering, such as
+//! the delay time of `EffectType::Delay` or the volume of a bus of
+//! `MixerType::MultiChannelMixer`.
+//!
+//! ```no_run
+//! # use coreaudio::audio_unit::{AudioUnit, EffectType, Element, Scope};
+//! # fn main() -> Result<(), coreaudio::Error> {
+//! let mut unit = AudioUnit::new(EffectType::Delay)?;
+//! for info in unit.parameters(Scope::Global, Element::Output)? {
+//!     let value = unit.get_parameter(info.id, Scope::Global, Element::Output)?;
+//!     println!("{}: {}", info.name, info.format(value));
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! See the Audio Unit Parameters Reference
+//! [here](https://developer.apple.com/documentation/audiotoolbox/audio_unit_parameters) for more
+//! info.
+
+use std::mem;
+use std::os::raw::{c_uint, c_void};
+
+use super::{property, AudioUnit, Bus, Scope};
+use crate::cf;
+use crate::error::Error;
+use crate::try_os_status;
+use sys;
+
+/// The unit of a parameter value, used to display it.
+///
+/// Original documentation [here](https://developer.apple.com/documentation/audiotoolbox/audiounitparameterunit).
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AudioUnitParameterUnit {
+    /// Untyped values from `0.0` to `1.0`.
+    Generic = 0,
+    /// Takes an integer value, used for parameters that select from a list.
+    Indexed = 1,
+    /// `0.0` means off, `1.0` means on.
+    Boolean = 2,
+    /// Usually from `0.0` to `100.0`.
+    Percent = 3,
+    Seconds = 4,
+    SampleFrames = 5,
+    /// From `-180.0` to `180.0` degrees.
+    Phase = 6,
+    /// A rate multiplier, where `1.0` is the normal rate.
+    Rate = 7,
+    Hertz = 8,
+    Cents = 9,
+    RelativeSemiTones = 10,
+    /// From `0` to `127`.
+    MIDINoteNumber = 11,
+    /// From `0` to `127`.
+    MIDIController = 12,
+    Decibels = 13,
+    /// From `0.0` to `1.0`.
+    LinearGain = 14,
+    /// From `-180.0` to `180.0`.
+    Degrees = 15,
+    /// From `0.0` to `1.0`, where `0.5` is equal power.
+    EqualPowerCrossfade = 16,
+    /// From `0.0` to `1.0`, with a gain curve of `x^3`.
+    MixerFaderCurve1 = 17,
+    /// From `0.0` (left) to `1.0` (right).
+    Pan = 18,
+    /// Distance in meters.
+    Meters = 19,
+    /// Absolute frequency in cents, where `6900` is A440.
+    AbsoluteCents = 20,
+    Octaves = 21,
+    /// Beats per minute.
+    BPM = 22,
+    Beats = 23,
+    Milliseconds = 24,
+    Ratio = 25,
+    /// The unit is named by the `unit_name` of the `ParameterInfo`.
+    CustomUnit = 26,
+    /// A MIDI 2.0 controller value, from `0` to `0xFFFFFFFF`.
+    MIDI2Controller = 27,
+}
+
+impl AudioUnitParameterUnit {
+    /// Convert from the `AudioUnitParameterUnit` value used by Core Audio.
+    pub fn from_u32(u: u32) -> Option<Self> {
+        use self::AudioUnitParameterUnit::*;
+        let unit = match u {
+            0 => Generic,
+            1 => Indexed,
+            2 => Boolean,
+            3 => Percent,
+            4 => Seconds,
+            5 => SampleFrames,
+            6 => Phase,
+            7 => Rate,
+            8 => Hertz,
+            9 => Cents,
+            10 => RelativeSemiTones,
+            11 => MIDINoteNumber,
+            12 => MIDIController,
+            13 => Decibels,
+            14 => LinearGain,
+            15 => Degrees,
+            16 => EqualPowerCrossfade,
+            17 => MixerFaderCurve1,
+            18 => Pan,
+            19 => Meters,
+            20 => AbsoluteCents,
+            21 => Octaves,
+            22 => BPM,
+            23 => Beats,
+            24 => Milliseconds,
+            25 => Ratio,
+            26 => CustomUnit,
+            27 => MIDI2Controller,
+            _ => return None,
+        };
+        Some(unit)
+    }
+
+    /// Convert to the `AudioUnitParameterUnit` value used by Core Audio.
+    pub fn as_u32(&self) -> u32 {
+        *self as u32
+    }
+
+    /// The symbol displayed after a value in this unit, if any.
+    pub fn symbol(&self) -> Option<&'static str> {
+        use self::AudioUnitParameterUnit::*;
+        let symbol = match *self {
+            Percent => "%",
+            Seconds => "s",
+            SampleFrames => "samples",
+            Phase | Degrees => "°",
+            Rate => "x",
+            Hertz => "Hz",
+            Cents | AbsoluteCents => "cents",
+            RelativeSemiTones => "semitones",
+            Decibels => "dB",
+            Meters => "m",
+            Octaves => "octaves",
+            BPM => "BPM",
+            Beats => "beats",
+            Milliseconds => "ms",
+            _ => return None,
+        };
+        Some(symbol)
+    }
+
+    /// Format a value in this unit for display, e.g. `"-6.0 dB"` or `"On"`.
+    pub fn format(&self, value: f32) -> String {
+        use self::AudioUnitParameterUnit::*;
+        match *self {
+            Boolean => {
+                let on = if value >= 0.5 { "On" } else { "Off" };
+                on.to_string()
+            }
+            Indexed | SampleFrames | MIDINoteNumber | MIDIController | MIDI2Controller => {
+                with_symbol(format!("{}", value.round() as i64), self.symbol())
+            }
+            Hertz if value.abs() >= 1000.0 => format!("{:.2} kHz", value / 1000.0),
+            Ratio => format!("{:.2}:1", value),
+            Cents | AbsoluteCents => with_symbol(format!("{:.0}", value), self.symbol()),
+            Seconds | Rate | RelativeSemiTones | Octaves | Beats | Generic | LinearGain
+            | EqualPowerCrossfade | MixerFaderCurve1 | Pan | CustomUnit => {
+                with_symbol(format!("{:.2}", value), self.symbol())
+            }
+            _ => with_symbol(format!("{:.1}", value), self.symbol()),
+        }
+    }
+}
+
+fn with_symbol(value: String, symbol: Option<&str>) -> String {
+    match symbol {
+        // These symbols are written without a space.
+        Some(symbol @ "%") | Some(symbol @ "°") | Some(symbol @ "x") => {
+            format!("{}{}", value, symbol)
+        }
+        Some(symbol) => format!("{} {}", value, symbol),
+        None => value,
+    }
+}
+
+bitflags! {
+    /// Flags describing a parameter.
+    ///
+    /// Original documentation [here](https://developer.apple.com/documentation/audiotoolbox/audiounitparameteroptions).
+    pub struct ParameterFlags: u32 {
+        /// The host must release the `CFString` name of the parameter. Handled by
+        /// **ParameterInfo**.
+        const CF_NAME_RELEASE = 1 << 4;
+        /// The parameter is not saved in presets.
+        const OMIT_FROM_PRESETS = 1 << 13;
+        /// The parameter should be plotted over time.
+        const PLOT_HISTORY = 1 << 14;
+        /// The parameter is a read-only meter.
+        const METER_READ_ONLY = 1 << 15;
+        /// The bits holding the display curve. See
+        /// [**display_type**](./struct.ParameterFlags#method.display_type).
+        const DISPLAY_MASK = (7 << 16) | (1 << 22);
+        /// The parameter belongs to a clump of related parameters.
+        const HAS_CLUMP = 1 << 20;
+        /// The values of the parameter have names.
+        const VALUES_HAVE_STRINGS = 1 << 21;
+        /// Changes to the parameter are applied with high resolution.
+        const IS_HIGH_RESOLUTION = 1 << 23;
+        /// The parameter must not be changed while rendering.
+        const NON_REAL_TIME = 1 << 24;
+        /// The parameter supports ramped scheduled events.
+        const CAN_RAMP = 1 << 25;
+        /// The parameter is intended for expert users.
+        const EXPERT_MODE = 1 << 26;
+        /// The name of the parameter is a `CFString`.
+        const HAS_CF_NAME_STRING = 1 << 27;
+        /// Changing the parameter changes other parameters of the unit.
+        const IS_GLOBAL_META = 1 << 28;
+        /// Changing the parameter changes other parameters of the same element.
+        const IS_ELEMENT_META = 1 << 29;
+        const IS_READABLE = 1 << 30;
+        const IS_WRITABLE = 1 << 31;
+    }
+}
+
+impl ParameterFlags {
+    /// The curve with which the parameter should be displayed, e.g. on a slider.
+    ///
+    /// Unknown curves are displayed linearly.
+    pub fn display_type(&self) -> ParameterDisplayType {
+        let display = self.bits() & Self::DISPLAY_MASK.bits();
+        if display & (1 << 22) != 0 {
+            return ParameterDisplayType::Logarithmic;
+        }
+        match display >> 16 {
+            1 => ParameterDisplayType::SquareRoot,
+            2 => ParameterDisplayType::Squared,
+            3 => ParameterDisplayType::Cubed,
+            4 => ParameterDisplayType::CubeRoot,
+            5 => ParameterDisplayType::Exponential,
+            _ => ParameterDisplayType::Linear,
+        }
+    }
+}
+
+/// The curve with which a parameter should be displayed, as stored in its
+/// `ParameterFlags::DISPLAY_MASK` bits.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ParameterDisplayType {
+    Linear,
+    SquareRoot,
+    Squared,
+    Cubed,
+    CubeRoot,
+    Exponential,
+    Logarithmic,
+}
+
+/// A description of a parameter, as returned by `kAudioUnitProperty_ParameterInfo`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParameterInfo {
+    /// The ID used to get and set the parameter.
+    pub id: u32,
+    pub name: String,
+    pub unit: AudioUnitParameterUnit,
+    /// The name of the unit, for parameters with `AudioUnitParameterUnit::CustomUnit`.
+    pub unit_name: Option<String>,
+    pub min_value: f32,
+    pub max_value: f32,
+    pub default_value: f32,
+    pub flags: ParameterFlags,
+    /// The clump of related parameters, if `ParameterFlags::HAS_CLUMP` is set.
+    pub clump_id: Option<u32>,
+}
+
+impl ParameterInfo {
+    /// Convert the raw description of the parameter with the given ID, releasing the strings it
+    /// holds as required by its flags.
+    ///
+    /// # Safety
+    ///
+    /// `info` must have been returned by an audio unit for `kAudioUnitProperty_ParameterInfo`.
+    unsafe fn from_sys(id: u32, info: &sys::AudioUnitParameterInfo) -> Self {
+        let flags = ParameterFlags::from_bits_truncate(info.flags);
+        let release = flags.contains(ParameterFlags::CF_NAME_RELEASE);
+        let take = |string| {
+            if release {
+                cf::take_cfstring(string)
+            } else {
+                cf::cfstring_to_string(string)
+            }
+        };
+
+        let name = if flags.contains(ParameterFlags::HAS_CF_NAME_STRING) {
+            take(info.cfNameString as _)
+        } else {
+            let bytes = &*(&info.name[..] as *const [_] as *const [u8]);
+            let len = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+            String::from_utf8_lossy(&bytes[..len]).into_owned()
+        };
+        let unit =
+            AudioUnitParameterUnit::from_u32(info.unit).unwrap_or(AudioUnitParameterUnit::Generic);
+        let unit_name = if unit == AudioUnitParameterUnit::CustomUnit && !info.unitName.is_null() {
+            Some(take(info.unitName as _))
+        } else {
+            None
+        };
+        let clump_id = if flags.contains(ParameterFlags::HAS_CLUMP) {
+            Some(info.clumpID)
+        } else {
+            None
+        };
+
+        ParameterInfo {
+            id,
+            name,
+            unit,
+            unit_name,
+            min_value: info.minValue,
+            max_value: info.maxValue,
+            default_value: info.defaultValue,
+            flags,
+            clump_id,
+        }
+    }
+
+    /// Format a value of the parameter for display, using its unit.
+    pub fn format(&self, value: f32) -> String {
+        match self.unit_name {
+            Some(ref unit_name) => format!("{:.2} {}", value, unit_name),
+            None => self.unit.format(value),
+        }
+    }
+
+    /// Whether the value lies within the range of the parameter.
+    pub fn contains(&self, value: f32) -> bool {
+        value >= self.min_value && value <= self.max_value
+    }
+}
+
+/// The range and default value of a parameter, known without querying the audio unit.
+///
+/// The typed effect wrappers in [**effects**](../effects/index.html) expose one of these for each
+/// of their parameters.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ParameterRange {
+    pub min: f32,
+    pub max: f32,
+    pub default: f32,
+}
+
+impl ParameterRange {
+    /// Whether the value lies within the range.
+    pub fn contains(&self, value: f32) -> bool {
+        value >= self.min && value <= self.max
+    }
+
+    /// Returns the value if it lies within the range, or `Error::ParameterOutOfRange`.
+    pub fn check(&self, value: f32) -> Result<f32, Error> {
+        if self.contains(value) {
+            Ok(value)
+        } else {
+            Err(Error::ParameterOutOfRange)
+        }
+    }
+
+    /// Clamp the value to the range.
+    pub fn clamp(&self, value: f32) -> f32 {
+        value.max(self.min).min(self.max)
+    }
+}
+
+/// A change to a parameter, scheduled with
+/// [**AudioUnit::schedule_parameters**](../struct.AudioUnit#method.schedule_parameters).
+///
+/// Offsets are in frames, relative to the start of the next render.
+#[derive(Copy, Clone, Debug)]
+pub struct ParameterEvent {
+    pub id: u32,
+    pub scope: Scope,
+    pub elem: Bus,
+    pub change: ParameterChange,
+}
+
+/// How a [**ParameterEvent**](./struct.ParameterEvent) changes the value of a parameter.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ParameterChange {
+    /// Jump to `value` at `buffer_offset`.
+    Immediate { buffer_offset: u32, value: f32 },
+    /// Move linearly from `start_value` to `end_value` over `duration` frames, starting at
+    /// `start_offset`.
+    ///
+    /// A negative `start_offset` continues a ramp that started in an earlier render. Only
+    /// parameters with `ParameterFlags::CAN_RAMP` support ramps.
+    Ramp {
+        start_offset: i32,
+        duration: u32,
+        start_value: f32,
+        end_value: f32,
+    },
+}
+
+impl ParameterEvent {
+    /// An event setting the parameter to `value` at `buffer_offset`.
+    pub fn immediate(
+        id: u32,
+        scope: Scope,
+        elem: impl Into<Bus>,
+        buffer_offset: u32,
+        value: f32,
+    ) -> Self {
+        ParameterEvent {
+            id,
+            scope,
+            elem: elem.into(),
+            change: ParameterChange::Immediate {
+                buffer_offset,
+                value,
+            },
+        }
+    }
+
+    /// An event ramping the parameter from `start_value` to `end_value` over `duration` frames.
+    pub fn ramp(
+        id: u32,
+        scope: Scope,
+        elem: impl Into<Bus>,
+        start_offset: i32,
+        duration: u32,
+        start_value: f32,
+        end_value: f32,
+    ) -> Self {
+        ParameterEvent {
+            id,
+            scope,
+            elem: elem.into(),
+            change: ParameterChange::Ramp {
+                start_offset,
+                duration,
+                start_value,
+                end_value,
+            },
+        }
+    }
+
+    fn to_sys(self) -> sys::AudioUnitParameterEvent {
+        let (event_type, event_values) = match self.change {
+            ParameterChange::Immediate {
+                buffer_offset,
+                value,
+            } => (
+                // kParameterEvent_Immediate
+                1,
+                sys::AudioUnitParameterEvent__bindgen_ty_1 {
+                    immediate: sys::AudioUnitParameterEvent__bindgen_ty_1__bindgen_ty_2 {
+                        bufferOffset: buffer_offset,
+                        value,
+                    },
+                },
+            ),
+            ParameterChange::Ramp {
+                start_offset,
+                duration,
+                start_value,
+                end_value,
+            } => (
+                // kParameterEvent_Ramped
+                2,
+                sys::AudioUnitParameterEvent__bindgen_ty_1 {
+                    ramp: sys::AudioUnitParameterEvent__bindgen_ty_1__bindgen_ty_1 {
+                        startBufferOffset: start_offset,
+                        durationInFrames: duration,
+                        startValue: start_value,
+                        endValue: end_value,
+                    },
+                },
+            ),
+        };
+        sys::AudioUnitParameterEvent {
+            scope: self.scope as c_uint,
+            element: self.elem.0 as c_uint,
+            parameter: self.id,
+            eventType: event_type,
+            eventValues: event_values,
+        }
+    }
+}
+
+impl AudioUnit {
+    /// Describe all parameters of the **AudioUnit** in the given scope and element.
+    ///
+    /// **Available** in iOS 2.0 and later.
+    pub fn parameters(
+        &self,
+        scope: Scope,
+        elem: impl Into<Bus>,
+    ) -> Result<Vec<ParameterInfo>, Error> {
+        let ids = self.get::<property::ParameterList>(scope, elem)?;
+        ids.into_iter()
+            .map(|id| self.parameter_info(id, scope))
+            .collect()
+    }
+
+    /// Describe the parameter with the given ID in the given scope.
+    ///
+    /// **Available** in iOS 2.0 and later.
+    pub fn parameter_info(&self, id: u32, scope: Scope) -> Result<ParameterInfo, Error> {
+        let mut size = mem::size_of::<sys::AudioUnitParameterInfo>() as u32;
+        unsafe {
+            let mut info = mem::MaybeUninit::<sys::AudioUnitParameterInfo>::zeroed();
+            // The element of the property is the ID of the parameter.
+            try_os_status!(sys::AudioUnitGetProperty(
+                self.instance,
+                sys::kAudioUnitProperty_ParameterInfo,
+                scope as c_uint,
+                id,
+                info.as_mut_ptr() as *mut c_void,
+                &mut size,
+            ));
+            Ok(ParameterInfo::from_sys(id, &info.assume_init()))
+        }
+    }
+
+    /// Gets the current value of a parameter.
+    ///
+    /// **Available** in iOS 2.0 and later.
+    pub fn get_parameter(&self, id: u32, scope: Scope, elem: impl Into<Bus>) -> Result<f32, Error> {
+        let mut value = 0.0;
+        unsafe {
+            try_os_status!(sys::AudioUnitGetParameter(
+                self.instance,
+                id,
+                scope as c_uint,
+                elem.into().0 as c_uint,
+                &mut value,
+            ));
+        }
+        Ok(value)
+    }
+
+    /// Sets the value of a parameter.
+    ///
+    /// The change is applied `buffer_offset` frames into the next render, or immediately when
+    /// `0`. Use [**schedule_parameters**](./struct.AudioUnit#method.schedule_parameters) for
+    /// ramped changes.
+    ///
+    /// **Available** in iOS 2.0 and later.
+    pub fn set_parameter(
+        &mut self,
+        id: u32,
+        scope: Scope,
+        elem: impl Into<Bus>,
+        value: f32,
+        buffer_offset: u32,
+    ) -> Result<(), Error> {
+        unsafe {
+            try_os_status!(sys::AudioUnitSetParameter(
+                self.instance,
+                id,
+                scope as c_uint,
+                elem.into().0 as c_uint,
+                value,
+                buffer_offset,
+            ));
+        }
+        Ok(())
+    }
+
+    /// Schedules changes to parameters for the next render.
+    ///
+    /// **Available** in iOS 2.0 and later.
+    pub fn schedule_parameters(&mut self, events: &[ParameterEvent]) -> Result<(), Error> {
+        // Convert the events in chunks on the stack rather than allocating.
+        const CHUNK: usize = 16;
+        for chunk in events.chunks(CHUNK) {
+            let mut sys_events =
+                [mem::MaybeUninit::<sys::AudioUnitParameterEvent>::uninit(); CHUNK];
+            for (sys_event, event) in sys_events.iter_mut().zip(chunk) {
+                sys_event.write(event.to_sys());
+            }
+            unsafe {
+                try_os_status!(sys::AudioUnitScheduleParameters(
+                    self.instance,
+                    sys_events.as_ptr() as *const sys::AudioUnitParameterEvent,
+                    chunk.len() as u32,
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::audio_unit::Element;
+
+    #[test]
+    fn test_unit_round_trip() {
+        for u in 0..28 {
+            let unit = AudioUnitParameterUnit::from_u32(u).unwrap();
+            assert_eq!(unit.as_u32(), u);
+        }
+        assert_eq!(AudioUnitParameterUnit::from_u32(28), None);
+    }
+
+    #[test]
+    fn test_format() {
+        use super::AudioUnitParameterUnit::*;
+        assert_eq!(Decibels.format(-6.0), "-6.0 dB");
+        assert_eq!(Hertz.format(440.0), "440.0 Hz");
+        assert_eq!(Hertz.format(12_500.0), "12.50 kHz");
+        assert_eq!(Percent.format(50.0), "50.0%");
+        assert_eq!(Boolean.format(1.0), "On");
+        assert_eq!(Boolean.format(0.0), "Off");
+        assert_eq!(MIDINoteNumber.format(60.4), "60");
+        assert_eq!(Milliseconds.format(12.5), "12.5 ms");
+        assert_eq!(Ratio.format(4.0), "4.00:1");
+        assert_eq!(Generic.format(0.5), "0.50");
+    }
+
+    #[test]
+    fn test_display_type() {
+        let flags = |bits| ParameterFlags::from_bits_truncate(bits);
+        let readable = ParameterFlags::IS_READABLE.bits();
+        assert_eq!(flags(readable).display_type(), ParameterDisplayType::Linear);
+        assert_eq!(
+            flags(readable | 1 << 16).display_type(),
+            ParameterDisplayType::SquareRoot
+        );
+        assert_eq!(flags(3 << 16).display_type(), ParameterDisplayType::Cubed);
+        assert_eq!(
+            flags(5 << 16).display_type(),
+            ParameterDisplayType::Exponential
+        );
+        assert_eq!(
+            flags(readable | 1 << 22).display_type(),
+            ParameterDisplayType::Logarithmic
+        );
+        assert_eq!(flags(7 << 16).display_type(), ParameterDisplayType::Linear);
+    }
+
+    #[test]
+    fn test_event_conversion() {
+        let event = ParameterEvent::ramp(3, Scope::Input, Element::Input, -64, 512, 0.0, 1.0);
+        let sys_event = event.to_sys();
+        assert_eq!(sys_event.parameter, 3);
+        assert_eq!(sys_event.scope, 1);
+        assert_eq!(sys_event.element, 1);
+        assert_eq!(sys_event.eventType, 2);
+        let ramp = unsafe { sys_event.eventValues.ramp };
+        assert_eq!(ramp.startBufferOffset, -64);
+        assert_eq!(ramp.durationInFrames, 512);
+        assert_eq!((ramp.startValue, ramp.endValue), (0.0, 1.0));
+
+        let event = ParameterEvent::immediate(7, Scope::Input, 5, 128, 0.5);
+        let sys_event = event.to_sys();
+        assert_eq!(sys_event.element, 5);
+        assert_eq!(sys_event.eventType, 1);
+        let immediate = unsafe { sys_event.eventValues.immediate };
+        assert_eq!((immediate.bufferOffset, immediate.value), (128, 0.5));
+    }
+
+    #[test]
+    fn test_delay_parameters() {
+        use super::super::EffectType;
+
+        let mut unit = AudioUnit::new(EffectType::Delay).unwrap();
+        let parameters = unit.parameters(Scope::Global, Element::Output).unwrap();
+        assert!(!parameters.is_empty());
+        let info = &parameters[0];
+        assert!(info.contains(info.default_value));
+        unit.set_parameter(info.id, Scope::Global, Element::Output, info.min_value, 0)
+            .unwrap();
+        let value = unit
+            .get_parameter(info.id, Scope::Global, Element::Output)
+            .unwrap();
+        assert_eq!(value, info.min_value);
+    }
+}
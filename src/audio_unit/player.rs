@@ -0,0 +1,539 @@
+//! Scheduling audio on the `ScheduledSoundPlayer` and `AudioFilePlayer` generator units.
+//!
+//! Both units play audio that is scheduled ahead of time on their own sample timeline, which
+//! starts running once a start time is set with `schedule_start`. The scheduled buffers and
+//! file regions are read by the unit on the render thread, so the wrappers here own them until
+//! the unit reports that they have completed. Completed buffers are handed back by
+//! [**ScheduledSoundPlayer::reclaim_completed**](./struct.ScheduledSoundPlayer#method.reclaim_completed)
+//! so they may be refilled and scheduled again.
+//!
+//! ```no_run
+//! # use coreaudio::audio_unit::player::{ScheduledSoundPlayer, SliceFlags};
+//! # use coreaudio::AudioBufferList;
+//! # fn main() -> Result<(), coreaudio::Error> {
+//! let mut player = ScheduledSoundPlayer::<f32>::new()?;
+//! player.initialize()?;
+//! let mut buffer = AudioBufferList::new_non_interleaved(2, 44_100);
+//! buffer.iter_mut().for_each(|sample| *sample = 0.1);
+//! player.schedule_slice(buffer, 0.0, SliceFlags::empty())?;
+//! player.schedule_start_now()?;
+//! // ... once the slice has played:
+//! let buffers = player.reclaim_completed();
+//! # Ok(())
+//! # }
+//! ```
+
+use std::ops::{Deref, DerefMut};
+use std::os::raw::{c_uint, c_void};
+use std::ptr;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+#[cfg(feature = "audio_toolbox")]
+use std::{mem, path::Path};
+
+use super::{AudioUnit, Element, GeneratorType, Scope};
+use crate::error::Error;
+use crate::try_os_status;
+use crate::{AudioBufferList, Sample};
+use sys;
+
+#[cfg(feature = "audio_toolbox")]
+use crate::cf;
+#[cfg(feature = "audio_toolbox")]
+use crate::error::AudioUnitError;
+
+bitflags! {
+    /// Flags of a scheduled slice.
+    ///
+    /// `LOOP`, `INTERRUPT` and `INTERRUPT_AT_LOOP` are set when scheduling, the others are set by
+    /// the unit.
+    ///
+    /// Original documentation [here](https://developer.apple.com/documentation/audiotoolbox/auscheduledaudiosliceflags).
+    pub struct SliceFlags: u32 {
+        /// The slice has been played, or was skipped because it was scheduled too late.
+        const COMPLETE = sys::kScheduledAudioSliceFlag_Complete;
+        /// The slice has started playing.
+        const BEGAN_TO_RENDER = sys::kScheduledAudioSliceFlag_BeganToRender;
+        /// The slice started playing after its time stamp, so its beginning was skipped.
+        const BEGAN_TO_RENDER_LATE = sys::kScheduledAudioSliceFlag_BeganToRenderLate;
+        /// Play the slice repeatedly until another slice interrupts it.
+        const LOOP = sys::kScheduledAudioSliceFlag_Loop;
+        /// Stop the slice that is playing and play this one instead.
+        const INTERRUPT = sys::kScheduledAudioSliceFlag_Interrupt;
+        /// Play this slice once the looping slice that is playing reaches its end.
+        const INTERRUPT_AT_LOOP = sys::kScheduledAudioSliceFlag_InterruptAtLoop;
+    }
+}
+
+/// A scheduled event of type `T` that the unit reads until its completion proc is called,
+/// along with the data it refers to.
+///
+/// Boxed so that the event keeps its address while it is scheduled.
+struct Pending<T, D> {
+    event: T,
+    done: AtomicBool,
+    data: D,
+}
+
+impl<T, D> Pending<T, D> {
+    fn is_done(&self) -> bool {
+        self.done.load(Ordering::Acquire)
+    }
+
+    /// The user data passed to the completion proc.
+    fn user_data(&self) -> *mut c_void {
+        &self.done as *const AtomicBool as *mut c_void
+    }
+}
+
+unsafe fn mark_done(user_data: *mut c_void) {
+    (*(user_data as *const AtomicBool)).store(true, Ordering::Release);
+}
+
+unsafe extern "C" fn slice_complete(user_data: *mut c_void, _slice: *mut sys::ScheduledAudioSlice) {
+    mark_done(user_data);
+}
+
+/// Remove the completed events from `pending`, in the order they were scheduled.
+fn take_completed<T, D>(pending: &mut Vec<Box<Pending<T, D>>>) -> Vec<D> {
+    let mut completed = Vec::new();
+    let mut i = 0;
+    while i < pending.len() {
+        if pending[i].is_done() {
+            completed.push(pending.remove(i).data);
+        } else {
+            i += 1;
+        }
+    }
+    completed
+}
+
+fn sample_time_stamp(sample_time: f64) -> sys::AudioTimeStamp {
+    sys::AudioTimeStamp {
+        mSampleTime: sample_time,
+        mFlags: sys::kAudioTimeStampSampleTimeValid,
+        ..Default::default()
+    }
+}
+
+/// Start the player's timeline at `sample_time` of the unit's render time stamps, or on the
+/// next render cycle if it is `-1`.
+fn schedule_start(unit: &mut AudioUnit, sample_time: f64) -> Result<(), Error> {
+    let time_stamp = sample_time_stamp(sample_time);
+    unit.set_property(
+        sys::kAudioUnitProperty_ScheduleStartTimeStamp,
+        Scope::Global,
+        Element::Output,
+        Some(&time_stamp),
+    )
+}
+
+fn current_play_time(unit: &AudioUnit) -> Result<Option<f64>, Error> {
+    let time_stamp: sys::AudioTimeStamp = unit.get_property(
+        sys::kAudioUnitProperty_CurrentPlayTime,
+        Scope::Global,
+        Element::Output,
+    )?;
+    // The sample time is -1 until the player has started.
+    if time_stamp.mSampleTime < 0.0 {
+        Ok(None)
+    } else {
+        Ok(Some(time_stamp.mSampleTime))
+    }
+}
+
+/// Clear everything the unit has scheduled, after which it no longer reads the pending events.
+fn reset(unit: &mut AudioUnit) -> Result<(), Error> {
+    unsafe {
+        try_os_status!(sys::AudioUnitReset(
+            unit.instance,
+            Scope::Global as c_uint,
+            0
+        ));
+    }
+    Ok(())
+}
+
+/// Plays buffers of audio scheduled at sample times, wrapping
+/// `GeneratorType::ScheduledSoundPlayer`.
+///
+/// The unit must be initialized before slices are scheduled.
+///
+/// **Available** in OS X v10.4 and later and iOS 5.0 and later.
+pub struct ScheduledSoundPlayer<S: Sample> {
+    // Declared first so that the unit is disposed before the slices it reads are freed.
+    unit: AudioUnit,
+    // Each slice is boxed, as the unit holds a pointer to it.
+    #[allow(clippy::vec_box)]
+    slices: Vec<Box<Pending<sys::ScheduledAudioSlice, AudioBufferList<S>>>>,
+}
+
+impl<S: Sample> ScheduledSoundPlayer<S> {
+    /// Create a new instance of the scheduled sound player unit.
+    pub fn new() -> Result<Self, Error> {
+        let unit = AudioUnit::new(GeneratorType::ScheduledSoundPlayer)?;
+        Ok(ScheduledSoundPlayer {
+            unit,
+            slices: Vec::new(),
+        })
+    }
+
+    /// Schedule all frames of `buffer` to play at `sample_time` of the player's timeline.
+    ///
+    /// The buffer must match the output stream format of the unit. It is owned by the player
+    /// until the slice completes.
+    pub fn schedule_slice(
+        &mut self,
+        mut buffer: AudioBufferList<S>,
+        sample_time: f64,
+        flags: SliceFlags,
+    ) -> Result<(), Error> {
+        let frames = buffer.frames();
+        buffer.reset(frames);
+        let buffer_list = buffer.as_mut_ptr();
+        let mut slice = Box::new(Pending {
+            event: sys::ScheduledAudioSlice {
+                mTimeStamp: sample_time_stamp(sample_time),
+                mCompletionProc: Some(slice_complete),
+                mCompletionProcUserData: ptr::null_mut(),
+                mFlags: flags.bits(),
+                mReserved: 0,
+                mReserved2: ptr::null_mut(),
+                mNumberFrames: frames as u32,
+                mBufferList: buffer_list,
+            },
+            done: AtomicBool::new(false),
+            data: buffer,
+        });
+        slice.event.mCompletionProcUserData = slice.user_data();
+        self.unit.set_property(
+            sys::kAudioUnitProperty_ScheduleAudioSlice,
+            Scope::Global,
+            Element::Output,
+            Some(&slice.event),
+        )?;
+        self.slices.push(slice);
+        Ok(())
+    }
+
+    /// The number of scheduled slices that have not completed.
+    pub fn pending_slices(&self) -> usize {
+        self.slices.iter().filter(|slice| !slice.is_done()).count()
+    }
+
+    /// Take back the buffers of all completed slices, in the order they were scheduled.
+    pub fn reclaim_completed(&mut self) -> Vec<AudioBufferList<S>> {
+        take_completed(&mut self.slices)
+    }
+
+    /// Start the player's timeline at `sample_time` of the unit's render time stamps.
+    ///
+    /// Must be called after the unit is initialized.
+    pub fn schedule_start(&mut self, sample_time: f64) -> Result<(), Error> {
+        schedule_start(&mut self.unit, sample_time)
+    }
+
+    /// Start the player's timeline on the next render cycle.
+    pub fn schedule_start_now(&mut self) -> Result<(), Error> {
+        schedule_start(&mut self.unit, -1.0)
+    }
+
+    /// The current sample time of the player's timeline, or `None` if it has not started.
+    pub fn current_play_time(&self) -> Result<Option<f64>, Error> {
+        current_play_time(&self.unit)
+    }
+
+    /// Clear all scheduled slices and the start time, returning the buffers of every slice.
+    pub fn reset(&mut self) -> Result<Vec<AudioBufferList<S>>, Error> {
+        reset(&mut self.unit)?;
+        Ok(self.slices.drain(..).map(|slice| slice.data).collect())
+    }
+}
+
+impl<S: Sample> Deref for ScheduledSoundPlayer<S> {
+    type Target = AudioUnit;
+    fn deref(&self) -> &AudioUnit {
+        &self.unit
+    }
+}
+
+impl<S: Sample> DerefMut for ScheduledSoundPlayer<S> {
+    fn deref_mut(&mut self) -> &mut AudioUnit {
+        &mut self.unit
+    }
+}
+
+/// An audio file opened for reading, to be played by an
+/// [**AudioFilePlayer**](./struct.AudioFilePlayer).
+///
+/// The file is closed when dropped.
+#[cfg(feature = "audio_toolbox")]
+pub struct AudioFile {
+    id: sys::AudioFileID,
+}
+
+// The file is only read through the `AudioFile` API, which may be used from any thread.
+#[cfg(feature = "audio_toolbox")]
+unsafe impl Send for AudioFile {}
+
+#[cfg(feature = "audio_toolbox")]
+impl AudioFile {
+    /// Open the audio file at `path` for reading.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        // `kAudioFileReadPermission`
+        const READ_PERMISSION: sys::AudioFilePermissions = 1;
+        let path = path.as_ref();
+        // Report a missing file as an I/O error rather than an `OSStatus`.
+        std::fs::metadata(path)?;
+        let mut id = ptr::null_mut();
+        cf::with_cfurl(path, |url| unsafe {
+            try_os_status!(sys::AudioFileOpenURL(
+                url as sys::CFURLRef,
+                READ_PERMISSION,
+                0,
+                &mut id
+            ));
+            Ok(AudioFile { id })
+        })
+    }
+}
+
+#[cfg(feature = "audio_toolbox")]
+impl Drop for AudioFile {
+    fn drop(&mut self) {
+        unsafe {
+            sys::AudioFileClose(self.id);
+        }
+    }
+}
+
+/// A region of one of the files of an [**AudioFilePlayer**](./struct.AudioFilePlayer).
+#[cfg(feature = "audio_toolbox")]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct FileRegion {
+    /// The index of the file, in the order given to `set_files`.
+    pub file: usize,
+    /// The first frame of the file to play.
+    pub start_frame: i64,
+    /// The number of frames to play.
+    pub frames: u32,
+    /// The number of times the region is repeated after it is first played.
+    pub loop_count: u32,
+    /// The sample time of the player's timeline at which the region starts.
+    pub sample_time: f64,
+}
+
+#[cfg(feature = "audio_toolbox")]
+impl FileRegion {
+    /// A region of `frames` frames starting at `start_frame`, played once at the start of the
+    /// player's timeline.
+    pub fn new(file: usize, start_frame: i64, frames: u32) -> Self {
+        FileRegion {
+            file,
+            start_frame,
+            frames,
+            loop_count: 0,
+            sample_time: 0.0,
+        }
+    }
+}
+
+#[cfg(feature = "audio_toolbox")]
+unsafe extern "C" fn region_complete(
+    user_data: *mut c_void,
+    _region: *mut sys::ScheduledAudioFileRegion,
+    _result: sys::OSStatus,
+) {
+    mark_done(user_data);
+}
+
+/// Plays regions of audio files, wrapping `GeneratorType::AudioFilePlayer`.
+///
+/// The files are set with [**set_files**](./struct.AudioFilePlayer#method.set_files) and kept
+/// open by the player. The unit must be initialized before regions are scheduled.
+///
+/// **Available** in OS X v10.4 and later and iOS 5.0 and later.
+#[cfg(feature = "audio_toolbox")]
+pub struct AudioFilePlayer {
+    // Declared first so that the unit is disposed before the regions and files it reads are
+    // freed.
+    unit: AudioUnit,
+    #[allow(clippy::vec_box)]
+    regions: Vec<Box<Pending<sys::ScheduledAudioFileRegion, ()>>>,
+    files: Vec<AudioFile>,
+}
+
+#[cfg(feature = "audio_toolbox")]
+impl AudioFilePlayer {
+    /// Create a new instance of the audio file player unit.
+    pub fn new() -> Result<Self, Error> {
+        let unit = AudioUnit::new(GeneratorType::AudioFilePlayer)?;
+        Ok(AudioFilePlayer {
+            unit,
+            regions: Vec::new(),
+            files: Vec::new(),
+        })
+    }
+
+    /// Replace the files the player may read from.
+    ///
+    /// The player is reset first, as the scheduled regions may refer to the previous files.
+    pub fn set_files(&mut self, files: Vec<AudioFile>) -> Result<(), Error> {
+        self.reset()?;
+        let ids: Vec<sys::AudioFileID> = files.iter().map(|file| file.id).collect();
+        unsafe {
+            try_os_status!(sys::AudioUnitSetProperty(
+                self.unit.instance,
+                sys::kAudioUnitProperty_ScheduledFileIDs,
+                Scope::Global as c_uint,
+                0,
+                ids.as_ptr() as *const c_void,
+                (ids.len() * mem::size_of::<sys::AudioFileID>()) as u32,
+            ));
+        }
+        self.files = files;
+        Ok(())
+    }
+
+    /// The files the player may read from.
+    pub fn files(&self) -> &[AudioFile] {
+        &self.files
+    }
+
+    /// Schedule a region of one of the player's files.
+    ///
+    /// Returns `Error::AudioUnit(AudioUnitError::InvalidPropertyValue)` if the file index is out
+    /// of range.
+    pub fn schedule_region(&mut self, region: FileRegion) -> Result<(), Error> {
+        let file = self
+            .files
+            .get(region.file)
+            .ok_or(Error::AudioUnit(AudioUnitError::InvalidPropertyValue))?;
+        let mut pending = Box::new(Pending {
+            event: sys::ScheduledAudioFileRegion {
+                mTimeStamp: sample_time_stamp(region.sample_time),
+                mCompletionProc: Some(region_complete),
+                mCompletionProcUserData: ptr::null_mut(),
+                mAudioFile: file.id,
+                mLoopCount: region.loop_count,
+                mStartFrame: region.start_frame,
+                mFramesToPlay: region.frames,
+            },
+            done: AtomicBool::new(false),
+            data: (),
+        });
+        pending.event.mCompletionProcUserData = pending.user_data();
+        self.unit.set_property(
+            sys::kAudioUnitProperty_ScheduledFileRegion,
+            Scope::Global,
+            Element::Output,
+            Some(&pending.event),
+        )?;
+        self.regions.push(pending);
+        Ok(())
+    }
+
+    /// Read the first `frames` frames of the scheduled regions ahead of playback, or the
+    /// unit's default amount if `frames` is `0`.
+    ///
+    /// Should be called after the regions are scheduled and before the start time is set.
+    pub fn prime(&mut self, frames: u32) -> Result<(), Error> {
+        self.unit.set_property(
+            sys::kAudioUnitProperty_ScheduledFilePrime,
+            Scope::Global,
+            Element::Output,
+            Some(&frames),
+        )
+    }
+
+    /// The number of scheduled regions that have not completed.
+    pub fn pending_regions(&self) -> usize {
+        self.regions
+            .iter()
+            .filter(|region| !region.is_done())
+            .count()
+    }
+
+    /// Free the completed regions, returning how many there were.
+    pub fn reclaim_completed(&mut self) -> usize {
+        take_completed(&mut self.regions).len()
+    }
+
+    /// Start the player's timeline at `sample_time` of the unit's render time stamps.
+    ///
+    /// Must be called after the unit is initialized.
+    pub fn schedule_start(&mut self, sample_time: f64) -> Result<(), Error> {
+        schedule_start(&mut self.unit, sample_time)
+    }
+
+    /// Start the player's timeline on the next render cycle.
+    pub fn schedule_start_now(&mut self) -> Result<(), Error> {
+        schedule_start(&mut self.unit, -1.0)
+    }
+
+    /// The current sample time of the player's timeline, or `None` if it has not started.
+    pub fn current_play_time(&self) -> Result<Option<f64>, Error> {
+        current_play_time(&self.unit)
+    }
+
+    /// Clear all scheduled regions and the start time. The files are kept.
+    pub fn reset(&mut self) -> Result<(), Error> {
+        reset(&mut self.unit)?;
+        self.regions.clear();
+        Ok(())
+    }
+}
+
+#[cfg(feature = "audio_toolbox")]
+impl Deref for AudioFilePlayer {
+    type Target = AudioUnit;
+    fn deref(&self) -> &AudioUnit {
+        &self.unit
+    }
+}
+
+#[cfg(feature = "audio_toolbox")]
+impl DerefMut for AudioFilePlayer {
+    fn deref_mut(&mut self) -> &mut AudioUnit {
+        &mut self.unit
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_take_completed() {
+        let mut pending: Vec<_> = (0..4)
+            .map(|i| {
+                Box::new(Pending {
+                    event: (),
+                    done: AtomicBool::new(false),
+                    data: i,
+                })
+            })
+            .collect();
+        for i in [3, 1].iter() {
+            unsafe { mark_done(pending[*i].user_data()) };
+        }
+        assert_eq!(take_completed(&mut pending), vec![1, 3]);
+        assert_eq!(pending.len(), 2);
+        assert!(take_completed(&mut pending).is_empty());
+    }
+
+    #[test]
+    fn test_schedule_slice() {
+        let mut player = ScheduledSoundPlayer::<f32>::new().unwrap();
+        player.initialize().unwrap();
+        let buffer = AudioBufferList::new_non_interleaved(2, 512);
+        player
+            .schedule_slice(buffer, 0.0, SliceFlags::empty())
+            .unwrap();
+        assert_eq!(player.pending_slices(), 1);
+        assert_eq!(player.current_play_time().unwrap(), None);
+        let buffers = player.reset().unwrap();
+        assert_eq!(buffers.len(), 1);
+        assert_eq!(buffers[0].frames(), 512);
+    }
+}
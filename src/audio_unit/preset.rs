@@ -0,0 +1,184 @@
+//! Factory presets and saving and restoring the state of an **AudioUnit**.
+//!
+//! [**AudioUnit::factory_presets**](../struct.AudioUnit#method.factory_presets) lists the
+//! presets a unit ships with, and
+//! [**AudioUnit::set_preset**](../struct.AudioUnit#method.set_preset) applies one.
+//! [**AudioUnit::save_state**](../struct.AudioUnit#method.save_state) returns the complete state
+//! of a unit, i.e. its `kAudioUnitProperty_ClassInfo`, as a [**Plist**](../../plist/enum.Plist)
+//! that can be written to a file in its XML form and restored later.
+//!
+//! ```no_run
+//! # use coreaudio::audio_unit::{AudioUnit, EffectType};
+//! # use coreaudio::plist::Plist;
+//! # fn main() -> Result<(), coreaudio::Error> {
+//! let mut unit = AudioUnit::new(EffectType::Distortion)?;
+//! if let Some(preset) = unit.factory_presets()?.first() {
+//!     unit.set_preset(preset)?;
+//! }
+//! let xml = unit.save_state()?.to_xml();
+//!
+//! let mut restored = AudioUnit::new(EffectType::Distortion)?;
+//! restored.restore_state(&Plist::from_xml(&xml)?)?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::str;
+
+use core_foundation_sys::array::{CFArrayGetCount, CFArrayGetValueAtIndex, CFArrayRef};
+use core_foundation_sys::base::CFRelease;
+use core_foundation_sys::propertylist::CFPropertyListRef;
+
+use super::{AudioUnit, Element, Scope};
+use crate::cf;
+use crate::error::{AudioUnitError, Error};
+use crate::plist::Plist;
+use sys;
+
+/// A preset of an **AudioUnit**.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Preset {
+    /// The number of a factory preset, or a negative number for a preset saved by the user.
+    pub number: i32,
+    pub name: String,
+}
+
+impl Preset {
+    /// Whether this is one of the presets the unit ships with.
+    pub fn is_factory(&self) -> bool {
+        self.number >= 0
+    }
+}
+
+impl AudioUnit {
+    /// The presets the unit ships with, which is empty for units without any.
+    ///
+    /// **Available** in iOS 2.0 and later.
+    pub fn factory_presets(&self) -> Result<Vec<Preset>, Error> {
+        let array: CFArrayRef = match self.get_property(
+            sys::kAudioUnitProperty_FactoryPresets,
+            Scope::Global,
+            Element::Output,
+        ) {
+            Ok(array) => array,
+            Err(Error::AudioUnit(AudioUnitError::InvalidProperty)) => return Ok(Vec::new()),
+            Err(err) => return Err(err),
+        };
+        if array.is_null() {
+            return Ok(Vec::new());
+        }
+        unsafe {
+            let presets = (0..CFArrayGetCount(array))
+                .map(|i| {
+                    let preset = &*(CFArrayGetValueAtIndex(array, i) as *const sys::AUPreset);
+                    Preset {
+                        number: preset.presetNumber,
+                        name: cf::cfstring_to_string(preset.presetName as _),
+                    }
+                })
+                .collect();
+            CFRelease(array as *const _);
+            Ok(presets)
+        }
+    }
+
+    /// The preset that was last applied or saved.
+    ///
+    /// **Available** in iOS 2.0 and later.
+    pub fn present_preset(&self) -> Result<Preset, Error> {
+        let preset: sys::AUPreset = self.get_property(
+            sys::kAudioUnitProperty_PresentPreset,
+            Scope::Global,
+            Element::Output,
+        )?;
+        Ok(Preset {
+            number: preset.presetNumber,
+            name: unsafe { cf::take_cfstring(preset.presetName as _) },
+        })
+    }
+
+    /// Apply a factory preset, or set the name and number reported for the present state.
+    ///
+    /// **Available** in iOS 2.0 and later.
+    pub fn set_preset(&mut self, preset: &Preset) -> Result<(), Error> {
+        cf::with_cfstring(&preset.name, |name| {
+            let preset = sys::AUPreset {
+                presetNumber: preset.number,
+                presetName: name as _,
+            };
+            self.set_property(
+                sys::kAudioUnitProperty_PresentPreset,
+                Scope::Global,
+                Element::Output,
+                Some(&preset),
+            )
+        })
+    }
+
+    /// The complete state of the unit, including its parameters and present preset.
+    ///
+    /// **Available** in iOS 2.0 and later.
+    pub fn save_state(&self) -> Result<Plist, Error> {
+        let list: CFPropertyListRef = self.get_property(
+            sys::kAudioUnitProperty_ClassInfo,
+            Scope::Global,
+            Element::Output,
+        )?;
+        let xml = unsafe { cf::take_property_list_xml(list) }.ok_or(Error::InvalidPropertyList)?;
+        let xml = str::from_utf8(&xml).map_err(|_| Error::InvalidPropertyList)?;
+        Plist::from_xml(xml)
+    }
+
+    /// Restore a state returned by [**save_state**](./struct.AudioUnit#method.save_state).
+    ///
+    /// The state must have been saved by a unit of the same type, subtype and manufacturer.
+    ///
+    /// **Available** in iOS 2.0 and later.
+    pub fn restore_state(&mut self, state: &Plist) -> Result<(), Error> {
+        let xml = state.to_xml();
+        cf::with_property_list_from_xml(xml.as_bytes(), |list| {
+            self.set_property(
+                sys::kAudioUnitProperty_ClassInfo,
+                Scope::Global,
+                Element::Output,
+                Some(&list),
+            )
+        })
+        .ok_or(Error::InvalidPropertyList)?
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::audio_unit::EffectType;
+
+    #[test]
+    fn test_factory_presets() {
+        let mut unit = AudioUnit::new(EffectType::Distortion).unwrap();
+        let presets = unit.factory_presets().unwrap();
+        assert!(presets.iter().all(Preset::is_factory));
+        let preset = presets.last().unwrap();
+        unit.set_preset(preset).unwrap();
+        assert_eq!(&unit.present_preset().unwrap(), preset);
+    }
+
+    #[test]
+    fn test_save_and_restore_state() {
+        let mut unit = AudioUnit::new(EffectType::Distortion).unwrap();
+        let presets = unit.factory_presets().unwrap();
+        unit.set_preset(&presets[0]).unwrap();
+        let state = unit.save_state().unwrap();
+        assert_eq!(
+            state.get("name").and_then(Plist::as_str),
+            Some(&presets[0].name[..])
+        );
+
+        let mut restored = AudioUnit::new(EffectType::Distortion).unwrap();
+        let xml = state.to_xml();
+        restored
+            .restore_state(&Plist::from_xml(&xml).unwrap())
+            .unwrap();
+        assert_eq!(restored.save_state().unwrap(), state);
+    }
+}
@@ -0,0 +1,634 @@
+//! Typed access to **AudioUnit** properties.
+//!
+//! Each property is described by a marker type implementing [**Property**](./trait.Property),
+//! which ties the property ID to the type of its value, the scopes it may be used in and whether
+//! it can be written. This allows [**AudioUnit::get**](../struct.AudioUnit#method.get) and
+//! [**AudioUnit::set**](../struct.AudioUnit#method.set) to check the size of the property data
+//! against the value type rather than trusting the caller.
+//!
+//! ```no_run
+//! # use coreaudio::audio_unit::{AudioUnit, Element, IOType, Scope};
+//! use coreaudio::audio_unit::property;
+//!
+//! # fn main() -> Result<(), coreaudio::Error> {
+//! let mut unit = AudioUnit::new(IOType::DefaultOutput)?;
+//! unit.set::<property::MaximumFramesPerSlice>(Scope::Global, Element::Output, &4096)?;
+//! let latency = unit.get::<property::Latency>(Scope::Global, Element::Output)?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! For more on "properties" see [the reference](https://developer.apple.com/library/ios/documentation/AudioUnit/Reference/AudioUnitPropertiesReference/index.html#//apple_ref/doc/uid/TP40007288).
+
+use std::mem;
+use std::os::raw::{c_uint, c_void};
+use std::ptr;
+use std::slice;
+
+use core_foundation_sys::string::CFStringRef;
+
+use super::{AudioUnit, Bus, Scope};
+use crate::cf;
+use crate::error::{self, Error};
+use crate::try_os_status;
+use sys;
+
+/// Whether a property may be written.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Access {
+    ReadOnly,
+    ReadWrite,
+}
+
+/// Describes an **AudioUnit** property.
+///
+/// Implemented by the marker types in this module. The `ID`, `SCOPES` and `ACCESS` must match
+/// the documentation of the property, and `Value` must have the same layout as its data.
+pub trait Property {
+    /// The property ID, e.g. `kAudioUnitProperty_StreamFormat`.
+    const ID: u32;
+    /// The scopes in which the property may be accessed.
+    const SCOPES: &'static [Scope];
+    /// Whether the property may be written.
+    const ACCESS: Access;
+    /// The type of the property value.
+    type Value: PropertyValue;
+}
+
+/// A type that can be decoded from and encoded to property data.
+pub trait PropertyValue: Sized {
+    /// The size of the property data in bytes, or `None` if it varies from value to value.
+    const SIZE: Option<usize>;
+
+    /// Decode a value from the property data returned by the audio unit.
+    fn from_data(data: &[u8]) -> Result<Self, Error>;
+
+    /// Call `f` with the property data encoding the value.
+    fn with_data<R, F>(&self, f: F) -> R
+    where
+        F: FnOnce(&[u8]) -> R;
+}
+
+/// A plain C type that is valid for any bit pattern and has no padding bytes.
+///
+/// Plain types can be read from and written to property data directly, alone or as arrays.
+///
+/// # Safety
+///
+/// Implementors must be `repr(C)` types without padding, for which any bit pattern is valid.
+pub unsafe trait Plain: Copy {}
+
+/// Read a plain C value from property data, checking its size.
+pub(crate) fn read_plain<T: Plain>(data: &[u8]) -> Result<T, Error> {
+    if data.len() != mem::size_of::<T>() {
+        return Err(Error::UnexpectedPropertySize);
+    }
+    Ok(unsafe { ptr::read_unaligned(data.as_ptr() as *const T) })
+}
+
+/// Read an array of plain C values from property data, checking that the size is a multiple of
+/// the size of `T`.
+pub(crate) fn read_plain_array<T: Plain>(data: &[u8]) -> Result<Vec<T>, Error> {
+    let size = mem::size_of::<T>();
+    // `usize::is_multiple_of` is too recent for the versions of Rust this crate supports.
+    #[allow(clippy::manual_is_multiple_of)]
+    if size == 0 || data.len() % size != 0 {
+        return Err(Error::UnexpectedPropertySize);
+    }
+    let values = data
+        .chunks_exact(size)
+        .map(|chunk| unsafe { ptr::read_unaligned(chunk.as_ptr() as *const T) })
+        .collect();
+    Ok(values)
+}
+
+/// The bytes of a plain C value.
+pub(crate) fn plain_bytes<T: Plain>(value: &T) -> &[u8] {
+    plain_array_bytes(slice::from_ref(value))
+}
+
+/// The bytes of an array of plain C values.
+pub(crate) fn plain_array_bytes<T: Plain>(values: &[T]) -> &[u8] {
+    unsafe { slice::from_raw_parts(values.as_ptr() as *const u8, mem::size_of_val(values)) }
+}
+
+macro_rules! impl_plain_value {
+    ($($T:ty),*) => {
+        $(
+            unsafe impl Plain for $T {}
+
+            impl PropertyValue for $T {
+                const SIZE: Option<usize> = Some(mem::size_of::<$T>());
+
+                fn from_data(data: &[u8]) -> Result<Self, Error> {
+                    read_plain(data)
+                }
+
+                fn with_data<R, F>(&self, f: F) -> R
+                where
+                    F: FnOnce(&[u8]) -> R,
+                {
+                    f(plain_bytes(self))
+                }
+            }
+        )*
+    };
+}
+
+impl_plain_value!(
+    u8,
+    u32,
+    i32,
+    f32,
+    f64,
+    sys::AudioStreamBasicDescription,
+    sys::AudioStreamRangedDescription,
+    sys::AudioValueRange,
+    sys::AUChannelInfo,
+    sys::AudioChannelDescription,
+    sys::AudioChannelLayout,
+    DuckingConfiguration
+);
+
+// Used to read `CFStringRef`s and other pointers.
+unsafe impl Plain for usize {}
+
+/// Variable-length properties holding an array of plain values, such as
+/// `kAudioUnitProperty_SupportedNumChannels`.
+impl<T: Plain> PropertyValue for Vec<T> {
+    const SIZE: Option<usize> = None;
+
+    fn from_data(data: &[u8]) -> Result<Self, Error> {
+        read_plain_array(data)
+    }
+
+    fn with_data<R, F>(&self, f: F) -> R
+    where
+        F: FnOnce(&[u8]) -> R,
+    {
+        f(plain_array_bytes(self))
+    }
+}
+
+/// String properties are stored as a `CFStringRef`.
+///
+/// Decoding releases the `CFString` returned by the audio unit, as the caller owns it.
+impl PropertyValue for String {
+    const SIZE: Option<usize> = Some(mem::size_of::<CFStringRef>());
+
+    fn from_data(data: &[u8]) -> Result<Self, Error> {
+        let string = read_plain::<usize>(data)? as CFStringRef;
+        Ok(unsafe { cf::take_cfstring(string) })
+    }
+
+    fn with_data<R, F>(&self, f: F) -> R
+    where
+        F: FnOnce(&[u8]) -> R,
+    {
+        cf::with_cfstring(self, |string| f(plain_bytes(&(string as usize))))
+    }
+}
+
+/// A C struct ending in a variable-length array, such as `AudioChannelLayout`.
+///
+/// The struct is declared with a single array element, and the property data holds as many
+/// elements as its count field says.
+///
+/// # Safety
+///
+/// `ITEMS_OFFSET` must be the offset of the array field, and `item_count` must return the value
+/// of the count field.
+pub unsafe trait VariableLengthStruct: Plain {
+    /// The type of the array elements.
+    type Item: Plain;
+
+    /// The offset of the array in bytes. By default, the array is the last field.
+    const ITEMS_OFFSET: usize = mem::size_of::<Self>() - mem::size_of::<Self::Item>();
+
+    /// The number of elements according to the count field.
+    fn item_count(&self) -> usize;
+
+    /// Set the count field.
+    fn set_item_count(&mut self, count: usize);
+}
+
+unsafe impl VariableLengthStruct for sys::AudioChannelLayout {
+    type Item = sys::AudioChannelDescription;
+
+    fn item_count(&self) -> usize {
+        self.mNumberChannelDescriptions as usize
+    }
+
+    fn set_item_count(&mut self, count: usize) {
+        self.mNumberChannelDescriptions = count as u32;
+    }
+}
+
+/// The value of a property holding a [**VariableLengthStruct**](./trait.VariableLengthStruct),
+/// with the array elements moved into `items`.
+#[derive(Clone, Debug)]
+pub struct VariableLength<H: VariableLengthStruct> {
+    /// The fixed-size part of the struct. Its array field should be ignored.
+    pub header: H,
+    pub items: Vec<H::Item>,
+}
+
+impl<H: VariableLengthStruct> PropertyValue for VariableLength<H> {
+    const SIZE: Option<usize> = None;
+
+    fn from_data(data: &[u8]) -> Result<Self, Error> {
+        if data.len() < H::ITEMS_OFFSET {
+            return Err(Error::UnexpectedPropertySize);
+        }
+        let mut header_data = vec![0u8; mem::size_of::<H>()];
+        let header_len = data.len().min(header_data.len());
+        header_data[..header_len].copy_from_slice(&data[..header_len]);
+        let header: H = read_plain(&header_data)?;
+
+        let items_len = header.item_count() * mem::size_of::<H::Item>();
+        let items_data = data
+            .get(H::ITEMS_OFFSET..H::ITEMS_OFFSET + items_len)
+            .ok_or(Error::UnexpectedPropertySize)?;
+        let items = read_plain_array(items_data)?;
+        Ok(VariableLength { header, items })
+    }
+
+    fn with_data<R, F>(&self, f: F) -> R
+    where
+        F: FnOnce(&[u8]) -> R,
+    {
+        let mut header = self.header;
+        header.set_item_count(self.items.len());
+        let mut data = plain_bytes(&header)[..H::ITEMS_OFFSET].to_vec();
+        data.extend_from_slice(plain_array_bytes(&self.items));
+        // The declared struct is the smallest valid size, even without any elements.
+        if data.len() < mem::size_of::<H>() {
+            data.resize(mem::size_of::<H>(), 0);
+        }
+        f(&data)
+    }
+}
+
+/// Boolean properties are stored as a `UInt32`.
+impl PropertyValue for bool {
+    const SIZE: Option<usize> = Some(mem::size_of::<u32>());
+
+    fn from_data(data: &[u8]) -> Result<Self, Error> {
+        read_plain::<u32>(data).map(|value| value != 0)
+    }
+
+    fn with_data<R, F>(&self, f: F) -> R
+    where
+        F: FnOnce(&[u8]) -> R,
+    {
+        f(plain_bytes(&(*self as u32)))
+    }
+}
+
+macro_rules! properties {
+    ($(
+        $(#[$attr:meta])*
+        $Name:ident: $id:expr, $Value:ty, [$($scope:ident),*], $access:ident;
+    )*) => {
+        $(
+            $(#[$attr])*
+            #[derive(Copy, Clone, Debug)]
+            pub struct $Name;
+
+            impl Property for $Name {
+                const ID: u32 = $id;
+                const SCOPES: &'static [Scope] = &[$(Scope::$scope),*];
+                const ACCESS: Access = Access::$access;
+                type Value = $Value;
+            }
+        )*
+    };
+}
+
+properties! {
+    /// The stream format of an input or output bus.
+    StreamFormat: sys::kAudioUnitProperty_StreamFormat, sys::AudioStreamBasicDescription,
+        [Input, Output], ReadWrite;
+    /// The sample rate of an input or output bus.
+    SampleRate: sys::kAudioUnitProperty_SampleRate, f64, [Input, Output], ReadWrite;
+    /// The maximum number of frames the unit is asked to render at once.
+    MaximumFramesPerSlice: sys::kAudioUnitProperty_MaximumFramesPerSlice, u32, [Global],
+        ReadWrite;
+    /// The processing latency of the unit in seconds.
+    Latency: sys::kAudioUnitProperty_Latency, f64, [Global], ReadOnly;
+    /// The time in seconds for the output of the unit to decay to silence after its input stops.
+    TailTime: sys::kAudioUnitProperty_TailTime, f64, [Global], ReadOnly;
+    /// The maximum portion of CPU time the unit may use, from `0.0` to `1.0`.
+    CpuLoad: sys::kAudioUnitProperty_CPULoad, f64, [Global], ReadWrite;
+    /// The number of buses in a scope.
+    ElementCount: sys::kAudioUnitProperty_ElementCount, u32, [Global, Input, Output], ReadWrite;
+    /// Whether an effect unit passes its input through unprocessed.
+    BypassEffect: sys::kAudioUnitProperty_BypassEffect, bool, [Global], ReadWrite;
+    /// The error returned by the most recent render, or `0`.
+    LastRenderError: sys::kAudioUnitProperty_LastRenderError, i32, [Global], ReadOnly;
+    /// The quality of the rendering algorithm, from `0` to `127`.
+    RenderQuality: sys::kAudioUnitProperty_RenderQuality, u32, [Global], ReadWrite;
+    /// Whether the unit renders faster than real time.
+    OfflineRender: sys::kAudioUnitProperty_OfflineRender, bool, [Global], ReadWrite;
+    /// Whether the unit processes its input buffers in place.
+    InPlaceProcessing: sys::kAudioUnitProperty_InPlaceProcessing, bool, [Global], ReadWrite;
+    /// Whether the unit allocates its own buffers for a bus.
+    ShouldAllocateBuffer: sys::kAudioUnitProperty_ShouldAllocateBuffer, bool, [Input, Output],
+        ReadWrite;
+    /// Whether input or output is enabled on an I/O unit.
+    EnableIO: sys::kAudioOutputUnitProperty_EnableIO, bool, [Input, Output], ReadWrite;
+    /// Whether an I/O unit is running.
+    IsRunning: sys::kAudioOutputUnitProperty_IsRunning, bool, [Global], ReadOnly;
+    /// The IDs of the parameters of the unit in a scope.
+    ParameterList: sys::kAudioUnitProperty_ParameterList, Vec<u32>, [Global, Input, Output],
+        ReadOnly;
+    /// The supported combinations of input and output channel counts, where `-1` means any count.
+    SupportedNumChannels: sys::kAudioUnitProperty_SupportedNumChannels, Vec<sys::AUChannelInfo>,
+        [Global], ReadOnly;
+    /// The channel layout of an input or output bus.
+    AudioChannelLayout: sys::kAudioUnitProperty_AudioChannelLayout,
+        VariableLength<sys::AudioChannelLayout>, [Input, Output], ReadWrite;
+    /// The channel layout tags supported by an input or output bus.
+    SupportedChannelLayoutTags: sys::kAudioUnitProperty_SupportedChannelLayoutTags, Vec<u32>,
+        [Input, Output], ReadOnly;
+    /// The name of a bus.
+    ElementName: sys::kAudioUnitProperty_ElementName, String, [Input, Output], ReadWrite;
+    /// Whether a voice-processing I/O unit passes the microphone signal through unprocessed.
+    BypassVoiceProcessing: sys::kAUVoiceIOProperty_BypassVoiceProcessing, bool, [Global],
+        ReadWrite;
+    /// Whether a voice-processing I/O unit applies automatic gain control to the microphone
+    /// signal.
+    VoiceProcessingEnableAgc: sys::kAUVoiceIOProperty_VoiceProcessingEnableAGC, bool, [Global],
+        ReadWrite;
+    /// Whether a voice-processing I/O unit silences the processed microphone signal.
+    MuteOutput: sys::kAUVoiceIOProperty_MuteOutput, bool, [Global], ReadWrite;
+    /// How a voice-processing I/O unit lowers the volume of other audio while it runs.
+    ///
+    /// **Available** in macOS 14.0 and iOS 17.0 and later.
+    OtherAudioDuckingConfiguration: 2108, DuckingConfiguration, [Global], ReadWrite;
+}
+
+/// The value of [**OtherAudioDuckingConfiguration**](./struct.OtherAudioDuckingConfiguration),
+/// laid out as `AUVoiceIOOtherAudioDuckingConfiguration`.
+///
+/// [**voice_processing::Ducking**](../voice_processing/struct.Ducking) is the typed form.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct DuckingConfiguration {
+    /// Whether ducking adapts to the presence of voice, as a `Boolean`.
+    pub enable_advanced_ducking: u8,
+    // Spelled out so that the struct has no padding bytes.
+    _padding: [u8; 3],
+    /// The `AUVoiceIOOtherAudioDuckingLevel`.
+    pub ducking_level: u32,
+}
+
+impl DuckingConfiguration {
+    pub fn new(enable_advanced_ducking: bool, ducking_level: u32) -> Self {
+        DuckingConfiguration {
+            enable_advanced_ducking: enable_advanced_ducking as u8,
+            _padding: [0; 3],
+            ducking_level,
+        }
+    }
+}
+
+/// The device used by an I/O unit.
+///
+/// **Available** in macOS only.
+#[cfg(target_os = "macos")]
+#[derive(Copy, Clone, Debug)]
+pub struct CurrentDevice;
+
+#[cfg(target_os = "macos")]
+impl Property for CurrentDevice {
+    const ID: u32 = sys::kAudioOutputUnitProperty_CurrentDevice;
+    const SCOPES: &'static [Scope] = &[Scope::Global];
+    const ACCESS: Access = Access::ReadWrite;
+    type Value = sys::AudioDeviceID;
+}
+
+pub(crate) fn check_scope<P: Property>(scope: Scope) -> Result<(), Error> {
+    if P::SCOPES.iter().any(|&s| s as c_uint == scope as c_uint) {
+        Ok(())
+    } else {
+        Err(Error::AudioUnit(error::audio_unit::Error::InvalidScope))
+    }
+}
+
+/// The size in bytes of the property data and whether the property is writable.
+///
+/// **Available** in iOS 2.0 and later.
+pub fn property_info(
+    au: sys::AudioUnit,
+    id: u32,
+    scope: Scope,
+    elem: impl Into<Bus>,
+) -> Result<(usize, bool), Error> {
+    let mut size: u32 = 0;
+    let mut writable: sys::Boolean = 0;
+    unsafe {
+        try_os_status!(sys::AudioUnitGetPropertyInfo(
+            au,
+            id,
+            scope as c_uint,
+            elem.into().0 as c_uint,
+            &mut size,
+            &mut writable,
+        ));
+    }
+    Ok((size as usize, writable != 0))
+}
+
+/// Gets the value of the property `P`.
+///
+/// The size of the property data is queried first and checked against the value type.
+///
+/// **Available** in iOS 2.0 and later.
+pub fn get<P: Property>(
+    au: sys::AudioUnit,
+    scope: Scope,
+    elem: impl Into<Bus>,
+) -> Result<P::Value, Error> {
+    check_scope::<P>(scope)?;
+    let elem = elem.into();
+    let (size, _) = property_info(au, P::ID, scope, elem)?;
+    if let Some(expected) = <P::Value as PropertyValue>::SIZE {
+        if size != expected {
+            return Err(Error::UnexpectedPropertySize);
+        }
+    }
+    let mut data = vec![0u8; size];
+    let mut size = size as u32;
+    unsafe {
+        try_os_status!(sys::AudioUnitGetProperty(
+            au,
+            P::ID,
+            scope as c_uint,
+            elem.0 as c_uint,
+            data.as_mut_ptr() as *mut c_void,
+            &mut size,
+        ));
+    }
+    data.truncate(size as usize);
+    P::Value::from_data(&data)
+}
+
+/// Sets the value of the property `P`.
+///
+/// Returns `PropertyNotWritable` for read-only properties without calling the audio unit.
+///
+/// **Available** in iOS 2.0 and later.
+pub fn set<P: Property>(
+    au: sys::AudioUnit,
+    scope: Scope,
+    elem: impl Into<Bus>,
+    value: &P::Value,
+) -> Result<(), Error> {
+    check_scope::<P>(scope)?;
+    if P::ACCESS != Access::ReadWrite {
+        return Err(Error::AudioUnit(
+            error::audio_unit::Error::PropertyNotWritable,
+        ));
+    }
+    value.with_data(|data| {
+        unsafe {
+            try_os_status!(sys::AudioUnitSetProperty(
+                au,
+                P::ID,
+                scope as c_uint,
+                elem.into().0 as c_uint,
+                data.as_ptr() as *const c_void,
+                data.len() as u32,
+            ));
+        }
+        Ok(())
+    })
+}
+
+impl AudioUnit {
+    /// Gets the value of the property `P`.
+    ///
+    /// Unlike [**get_property**](./struct.AudioUnit#method.get_property), the size of the
+    /// property data is checked against the value type, and the scope against the scopes allowed
+    /// for the property.
+    ///
+    /// **Available** in iOS 2.0 and later.
+    pub fn get<P: Property>(&self, scope: Scope, elem: impl Into<Bus>) -> Result<P::Value, Error> {
+        get::<P>(self.instance, scope, elem)
+    }
+
+    /// Sets the value of the property `P`.
+    ///
+    /// **Available** in iOS 2.0 and later.
+    pub fn set<P: Property>(
+        &mut self,
+        scope: Scope,
+        elem: impl Into<Bus>,
+        value: &P::Value,
+    ) -> Result<(), Error> {
+        set::<P>(self.instance, scope, elem, value)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::audio_unit::Element;
+
+    #[test]
+    fn test_plain_values() {
+        let data = 48_000.0f64.to_ne_bytes();
+        assert_eq!(f64::from_data(&data).unwrap(), 48_000.0);
+        assert!(u32::from_data(&data).is_err());
+        assert!(bool::from_data(&1u32.to_ne_bytes()).unwrap());
+        true.with_data(|data| assert_eq!(data, 1u32.to_ne_bytes()));
+    }
+
+    #[test]
+    fn test_plain_arrays() {
+        let channels = vec![
+            sys::AUChannelInfo {
+                inChannels: -1,
+                outChannels: -1,
+            },
+            sys::AUChannelInfo {
+                inChannels: 1,
+                outChannels: 2,
+            },
+        ];
+        let decoded = channels
+            .with_data(Vec::<sys::AUChannelInfo>::from_data)
+            .unwrap();
+        assert_eq!(decoded.len(), 2);
+        assert_eq!((decoded[1].inChannels, decoded[1].outChannels), (1, 2));
+        assert!(Vec::<u32>::from_data(&[0u8; 6]).is_err());
+        assert!(Vec::<u32>::from_data(&[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_variable_length_struct() {
+        let description = |label| sys::AudioChannelDescription {
+            mChannelLabel: label,
+            ..Default::default()
+        };
+        let layout = VariableLength {
+            header: sys::AudioChannelLayout::default(),
+            items: vec![description(1), description(2), description(3)],
+        };
+        layout.with_data(|data| {
+            let size = mem::size_of::<sys::AudioChannelLayout>()
+                + 2 * mem::size_of::<sys::AudioChannelDescription>();
+            assert_eq!(data.len(), size);
+            let decoded = VariableLength::<sys::AudioChannelLayout>::from_data(data).unwrap();
+            assert_eq!(decoded.header.mNumberChannelDescriptions, 3);
+            let labels: Vec<_> = decoded.items.iter().map(|d| d.mChannelLabel).collect();
+            assert_eq!(labels, vec![1, 2, 3]);
+            // Data that is shorter than its count field claims is rejected.
+            assert!(
+                VariableLength::<sys::AudioChannelLayout>::from_data(&data[..size - 4]).is_err()
+            );
+        });
+
+        // Without elements, the data is still the size of the declared struct.
+        let empty = VariableLength {
+            header: sys::AudioChannelLayout::default(),
+            items: vec![],
+        };
+        empty.with_data(|data| {
+            assert_eq!(data.len(), mem::size_of::<sys::AudioChannelLayout>());
+            let decoded = VariableLength::<sys::AudioChannelLayout>::from_data(data).unwrap();
+            assert!(decoded.items.is_empty());
+        });
+    }
+
+    #[test]
+    fn test_catalogue() {
+        assert_eq!(
+            <StreamFormat as Property>::ID,
+            sys::kAudioUnitProperty_StreamFormat
+        );
+        assert_eq!(
+            <<StreamFormat as Property>::Value as PropertyValue>::SIZE,
+            Some(mem::size_of::<sys::AudioStreamBasicDescription>())
+        );
+        assert!(check_scope::<StreamFormat>(Scope::Global).is_err());
+        assert!(check_scope::<StreamFormat>(Scope::Input).is_ok());
+    }
+
+    #[test]
+    fn test_get_and_set() {
+        use super::super::EffectType;
+
+        let mut unit = AudioUnit::new(EffectType::Delay).unwrap();
+        unit.set::<MaximumFramesPerSlice>(Scope::Global, Element::Output, &2048)
+            .unwrap();
+        let frames = unit
+            .get::<MaximumFramesPerSlice>(Scope::Global, Element::Output)
+            .unwrap();
+        assert_eq!(frames, 2048);
+        assert!(unit
+            .set::<Latency>(Scope::Global, Element::Output, &0.0)
+            .is_err());
+    }
+}
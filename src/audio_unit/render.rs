@@ -0,0 +1,174 @@
+//! Pulling audio from an **AudioUnit** manually, for example when bouncing offline or when
+//! driving effect and converter units directly.
+
+use super::render_callback::ActionFlags;
+use super::{AudioUnit, Scope};
+use crate::error::{self, Error};
+use crate::try_os_status;
+use crate::{AudioBufferList, LinearPcmFlags, Sample};
+use sys;
+
+impl AudioUnit {
+    /// Renders `num_frames` frames of audio from the given output `bus` into `buffer`.
+    ///
+    /// This pulls the **AudioUnit** synchronously, which in turn pulls any render callbacks or
+    /// units connected to its inputs. The unit must be initialized and the layout and sample type
+    /// of `buffer` must match the output stream format of the bus (see
+    /// [**AudioBufferList::for_stream_format**](../struct.AudioBufferList#method.for_stream_format)),
+    /// or `Error::RenderCallbackBufferFormatDoesNotMatchAudioUnitStreamFormat` is returned.
+    ///
+    /// The `flags` are passed to the render operation and updated with the flags set by the unit,
+    /// for example `OUTPUT_IS_SILENCE`.
+    ///
+    /// Use a [**FrameCounter**](./struct.FrameCounter) to produce time stamps with an advancing
+    /// sample time.
+    pub fn render<S>(
+        &mut self,
+        flags: &mut ActionFlags,
+        time_stamp: &sys::AudioTimeStamp,
+        bus: u32,
+        num_frames: u32,
+        buffer: &mut AudioBufferList<S>,
+    ) -> Result<(), Error>
+    where
+        S: Sample,
+    {
+        let stream_format = self.bus_stream_format(Scope::Output, bus)?;
+        let interleaved = !stream_format
+            .flags
+            .contains(LinearPcmFlags::IS_NON_INTERLEAVED);
+        if S::sample_format() != stream_format.sample_format
+            || buffer.channels() != stream_format.channels as usize
+            || buffer.is_interleaved() != interleaved
+        {
+            return Err(Error::RenderCallbackBufferFormatDoesNotMatchAudioUnitStreamFormat);
+        }
+        if num_frames as usize > buffer.capacity() {
+            return Err(Error::AudioUnit(
+                error::audio_unit::Error::TooManyFramesToProcess,
+            ));
+        }
+        buffer.reset(num_frames as usize);
+        let mut raw_flags = flags.bits();
+        unsafe {
+            try_os_status!(sys::AudioUnitRender(
+                self.instance,
+                &mut raw_flags,
+                time_stamp,
+                bus,
+                num_frames,
+                buffer.as_mut_ptr(),
+            ));
+        }
+        *flags = ActionFlags::from_bits_truncate(raw_flags);
+        Ok(())
+    }
+}
+
+/// Produces `AudioTimeStamp`s with an advancing sample time for successive render calls.
+///
+/// ```no_run
+/// # use coreaudio::audio_unit::{AudioUnit, FrameCounter, EffectType};
+/// # use coreaudio::audio_unit::render_callback::ActionFlags;
+/// # use coreaudio::AudioBufferList;
+/// # fn main() -> Result<(), coreaudio::Error> {
+/// # let mut unit = AudioUnit::new(EffectType::Delay)?;
+/// let mut counter = FrameCounter::new();
+/// let mut buffer = AudioBufferList::<f32>::new_non_interleaved(2, 512);
+/// for _ in 0..100 {
+///     let time_stamp = counter.next(512);
+///     unit.render(&mut ActionFlags::empty(), &time_stamp, 0, 512, &mut buffer)?;
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct FrameCounter {
+    sample_time: f64,
+}
+
+impl FrameCounter {
+    /// A counter starting at sample time zero.
+    pub fn new() -> Self {
+        Self::starting_at(0.0)
+    }
+
+    /// A counter starting at the given sample time.
+    pub fn starting_at(sample_time: f64) -> Self {
+        FrameCounter { sample_time }
+    }
+
+    /// The sample time of the next render.
+    pub fn sample_time(&self) -> f64 {
+        self.sample_time
+    }
+
+    /// A time stamp for the next render, with a valid sample time.
+    pub fn time_stamp(&self) -> sys::AudioTimeStamp {
+        sys::AudioTimeStamp {
+            mSampleTime: self.sample_time,
+            mFlags: sys::kAudioTimeStampSampleTimeValid,
+            ..Default::default()
+        }
+    }
+
+    /// Advance the sample time by the given number of frames.
+    pub fn advance(&mut self, num_frames: u32) {
+        self.sample_time += num_frames as f64;
+    }
+
+    /// Returns the time stamp for the next render and advances the counter by `num_frames`.
+    pub fn next(&mut self, num_frames: u32) -> sys::AudioTimeStamp {
+        let time_stamp = self.time_stamp();
+        self.advance(num_frames);
+        time_stamp
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::render_callback::{self, data};
+    use super::super::EffectType;
+    use super::*;
+
+    #[test]
+    fn test_frame_counter() {
+        let mut counter = FrameCounter::starting_at(64.0);
+        assert_eq!(counter.next(128).mSampleTime, 64.0);
+        assert_eq!(counter.next(128).mSampleTime, 192.0);
+        assert_eq!(counter.sample_time(), 320.0);
+        assert_eq!(
+            counter.time_stamp().mFlags,
+            sys::kAudioTimeStampSampleTimeValid
+        );
+    }
+
+    #[test]
+    fn test_render_effect() {
+        let mut unit = AudioUnit::new(EffectType::Delay).unwrap();
+        let stream_format = unit.output_stream_format().unwrap();
+
+        type Args = render_callback::Args<data::NonInterleaved<f32>>;
+        unit.set_render_callback(|args: Args| {
+            let Args { mut data, .. } = args;
+            for channel in data.channels_mut() {
+                for sample in channel.iter_mut() {
+                    *sample = 0.5;
+                }
+            }
+            Ok(())
+        })
+        .unwrap();
+        unit.initialize().unwrap();
+
+        let mut buffer = AudioBufferList::<f32>::for_stream_format(&stream_format, 512).unwrap();
+        let mut counter = FrameCounter::new();
+        for _ in 0..4 {
+            let time_stamp = counter.next(512);
+            let mut flags = ActionFlags::empty();
+            unit.render(&mut flags, &time_stamp, 0, 512, &mut buffer)
+                .unwrap();
+            assert_eq!(buffer.frames(), 512);
+        }
+    }
+}
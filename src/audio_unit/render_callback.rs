@@ -47,6 +47,31 @@ pub struct Args<D> {
     pub flags: action_flags::Handle,
 }
 
+/// Arguments given to a render notify callback.
+///
+/// The audio unit invokes the notify callback twice per render cycle: once before it renders and
+/// once after. The buffers are only guaranteed to hold valid audio after rendering, so the data is
+/// only provided with the `PostRender` variant.
+#[derive(Debug)]
+pub enum NotifyArgs<D> {
+    /// The audio unit is about to render `num_frames` frames.
+    PreRender {
+        /// Timing information for the upcoming render.
+        time_stamp: sys::AudioTimeStamp,
+        /// The bus that is about to be rendered.
+        bus_number: u32,
+        /// The number of frames that are about to be rendered.
+        num_frames: usize,
+        /// The flags passed to the render operation.
+        flags: action_flags::Handle,
+    },
+    /// The audio unit has finished rendering.
+    ///
+    /// If `flags` contains `POST_RENDER_ERROR`, the unit's render operation failed and the data
+    /// does not contain valid audio.
+    PostRender(Args<D>),
+}
+
 /// Format specific render callback data.
 pub mod data {
     use crate::{LinearPcmFlags, Sample};
@@ -712,6 +737,115 @@ impl AudioUnit {
         Ok(())
     }
 
+    /// Add a render notify callback to the **AudioUnit**.
+    ///
+    /// The callback is invoked before and after each render operation of the unit, which makes it
+    /// useful for metering, tapping the output of an effect unit, or measuring render timing. See
+    /// [**NotifyArgs**](./enum.NotifyArgs) for the arguments passed in each case.
+    ///
+    /// Multiple notify callbacks may be added. They are removed and dropped by
+    /// [**AudioUnit::free_render_notifies**](./struct.AudioUnit#method.free_render_notifies), or
+    /// when the **AudioUnit** is dropped.
+    pub fn add_render_notify<F, D>(&mut self, mut f: F) -> Result<(), Error>
+    where
+        F: FnMut(NotifyArgs<D>) -> Result<(), ()> + 'static,
+        D: Data,
+    {
+        // The notify callback observes the data produced by the audio unit, so the callback
+        // format must match the output stream format.
+        let id = sys::kAudioUnitProperty_StreamFormat;
+        let asbd = self.get_property(id, Scope::Output, Element::Output)?;
+        let stream_format = super::StreamFormat::from_asbd(asbd)?;
+
+        // If the stream format does not match, return an error indicating this.
+        if !D::does_stream_format_match(&stream_format) {
+            return Err(Error::RenderCallbackBufferFormatDoesNotMatchAudioUnitStreamFormat);
+        }
+
+        let notify_proc_fn = move |io_action_flags: *mut sys::AudioUnitRenderActionFlags,
+                                   in_time_stamp: *const sys::AudioTimeStamp,
+                                   in_bus_number: sys::UInt32,
+                                   in_number_frames: sys::UInt32,
+                                   io_data: *mut sys::AudioBufferList|
+              -> sys::OSStatus {
+            let args = unsafe {
+                let flags = action_flags::Handle::from_ptr(io_action_flags);
+                if flags.contains(ActionFlags::PRE_RENDER) {
+                    NotifyArgs::PreRender {
+                        time_stamp: *in_time_stamp,
+                        bus_number: in_bus_number,
+                        num_frames: in_number_frames as usize,
+                        flags,
+                    }
+                } else {
+                    let data = D::from_input_proc_args(in_number_frames, io_data);
+                    NotifyArgs::PostRender(Args {
+                        data,
+                        time_stamp: *in_time_stamp,
+                        flags,
+                        bus_number: in_bus_number,
+                        num_frames: in_number_frames as usize,
+                    })
+                }
+            };
+
+            match f(args) {
+                Ok(()) => 0,
+                Err(()) => error::Error::Unspecified.as_os_status(),
+            }
+        };
+
+        let notify_proc_fn_wrapper = Box::new(InputProcFnWrapper {
+            callback: Box::new(notify_proc_fn),
+        });
+
+        // As with the render callback, we relinquish ownership of the callback here and keep the
+        // raw pointer so that it can be removed and freed in `free_render_notifies`.
+        let notify_proc_fn_wrapper_ptr = Box::into_raw(notify_proc_fn_wrapper) as *mut c_void;
+
+        unsafe {
+            let status = sys::AudioUnitAddRenderNotify(
+                self.instance,
+                Some(input_proc),
+                notify_proc_fn_wrapper_ptr,
+            );
+            if let Err(err) = Error::from_os_status(status) {
+                // The audio unit never received the callback, so we can safely free it here.
+                let _ = Box::from_raw(notify_proc_fn_wrapper_ptr as *mut InputProcFnWrapper);
+                return Err(err);
+            }
+        }
+
+        self.render_notifies
+            .push(notify_proc_fn_wrapper_ptr as *mut InputProcFnWrapper);
+        Ok(())
+    }
+
+    /// Removes all render notify callbacks from the **AudioUnit** and returns them where they can
+    /// be re-used or safely dropped.
+    ///
+    /// A callback that the audio unit fails to remove is leaked rather than returned, as the unit
+    /// may still call it.
+    pub fn free_render_notifies(&mut self) -> Vec<Box<InputProcFnWrapper>> {
+        let instance = self.instance;
+        self.render_notifies
+            .drain(..)
+            .filter_map(|callback| unsafe {
+                // We can't report errors here, as we may be called from `drop`. The callback is
+                // only freed once the audio unit no longer refers to it.
+                let status = sys::AudioUnitRemoveRenderNotify(
+                    instance,
+                    Some(input_proc),
+                    callback as *mut c_void,
+                );
+                match Error::from_os_status(status) {
+                    Ok(()) => Some(Box::from_raw(callback)),
+                    Err(_) => None,
+                }
+            })
+            .collect()
+    }
+
     /// Retrieves ownership over the render callback and returns it where it can be re-used or
     /// safely dropped.
     pub fn free_render_callback(&mut self) -> Option<Box<InputProcFnWrapper>> {
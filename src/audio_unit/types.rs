@@ -229,6 +229,11 @@ pub enum EffectType {
     ///
     /// **Available** in OS X v10.2 and later.
     MatrixReverb = 1836213622,
+    /// An audio unit that provides a reverberation effect with controllable decay times and
+    /// reflections.
+    ///
+    /// **Available** in OS X v10.7 and later and iOS 5.0 and later.
+    Reverb2 = 1920361010,
     /// An audio unit for modifying the pitch of a signal.
     ///
     /// **Available** in OS X v10.4 and later.
@@ -455,3 +460,28 @@ pub enum IOType {
     /// **Available** in iOS.
     RemoteIO = 1919512419,
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn four_cc(code: &[u8; 4]) -> u32 {
+        u32::from_be_bytes(*code)
+    }
+
+    #[test]
+    fn test_effect_sub_types() {
+        let sub_types = [
+            (EffectType::Delay as u32, b"dely"),
+            (EffectType::Reverb2 as u32, b"rvb2"),
+            (EffectType::DynamicsProcessor as u32, b"dcmp"),
+            (EffectType::PeakLimiter as u32, b"lmtr"),
+            (EffectType::NBandEQ as u32, b"nbeq"),
+            (EffectType::Distortion as u32, b"dist"),
+            (EffectType::MatrixReverb as u32, b"mrev"),
+        ];
+        for &(sub_type, code) in &sub_types {
+            assert_eq!(sub_type, four_cc(code), "{}", String::from_utf8_lossy(code));
+        }
+    }
+}
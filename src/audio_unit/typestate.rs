@@ -0,0 +1,324 @@
+//! An **AudioUnit** whose lifecycle is tracked in its type.
+//!
+//! The dynamic [**AudioUnit**](../struct.AudioUnit) tracks whether it is initialized and started
+//! at runtime, so calling a method in the wrong state only fails when the program runs (e.g.
+//! `get_formats` after `initialize` returns `Error::AlreadyInitialized`). The
+//! [**AudioUnit**](./struct.AudioUnit) in this module instead moves through the states
+//! [**Uninitialized**](./struct.Uninitialized) → [**Initialized**](./struct.Initialized) →
+//! [**Running**](./struct.Running), and only has the methods that are valid in its current state.
+//! Methods that change the stream format exist only before initialization, and `render` only
+//! after.
+//!
+//! ```no_run
+//! # use coreaudio::audio_unit::typestate::AudioUnit;
+//! # use coreaudio::audio_unit::{IOType, Scope};
+//! # fn main() -> Result<(), coreaudio::Error> {
+//! let mut unit = AudioUnit::new(IOType::DefaultOutput)?;
+//! unit.set_sample_rate(48_000.0)?;
+//! let unit = unit.initialize()?;
+//! let running = unit.start()?;
+//! // ...
+//! let unit = running.stop()?.uninitialize()?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Every state derefs to the dynamic **AudioUnit** for read-only access, and
+//! [**into_dynamic**](./struct.AudioUnit#method.into_dynamic) converts back to it for code that
+//! needs it.
+
+use std::fmt;
+use std::marker::PhantomData;
+use std::ops::Deref;
+
+use super::parameter::ParameterEvent;
+use super::property::Property;
+use super::render_callback::{ActionFlags, Args, Data, NotifyArgs};
+use super::{AudioUnit as DynamicAudioUnit, Bus, Formats, Scope, Type};
+use crate::error::Error;
+use crate::{AudioBufferList, Sample, StreamFormat};
+use sys;
+
+/// A lifecycle state of an [**AudioUnit**](./struct.AudioUnit).
+pub trait State: private::Sealed {}
+
+/// A state in which the unit is initialized, and may render.
+pub trait Ready: State {}
+
+/// The unit may be configured, but may not render.
+#[derive(Copy, Clone, Debug)]
+pub struct Uninitialized;
+
+/// The unit has allocated its resources and may render, but its formats are fixed.
+#[derive(Copy, Clone, Debug)]
+pub struct Initialized;
+
+/// The I/O unit has been started.
+#[derive(Copy, Clone, Debug)]
+pub struct Running;
+
+impl State for Uninitialized {}
+impl State for Initialized {}
+impl State for Running {}
+impl Ready for Initialized {}
+impl Ready for Running {}
+
+mod private {
+    pub trait Sealed {}
+    impl Sealed for super::Uninitialized {}
+    impl Sealed for super::Initialized {}
+    impl Sealed for super::Running {}
+}
+
+/// An **AudioUnit** in the lifecycle state `S`.
+pub struct AudioUnit<S: State> {
+    unit: DynamicAudioUnit,
+    state: PhantomData<S>,
+}
+
+/// A failed state transition, returning the unit in its original state.
+///
+/// Converts into an `Error`, so it may be propagated with `?` when the unit is not needed.
+pub struct TransitionError<S: State> {
+    pub error: Error,
+    pub unit: AudioUnit<S>,
+}
+
+impl<S: State> AudioUnit<S> {
+    fn wrap<T: State>(unit: DynamicAudioUnit) -> AudioUnit<T> {
+        AudioUnit {
+            unit,
+            state: PhantomData,
+        }
+    }
+
+    /// Convert to the dynamic **AudioUnit**, keeping its current state.
+    pub fn into_dynamic(self) -> DynamicAudioUnit {
+        self.unit
+    }
+
+    /// Sets the value of a parameter. See
+    /// [**AudioUnit::set_parameter**](../struct.AudioUnit#method.set_parameter).
+    pub fn set_parameter(
+        &mut self,
+        id: u32,
+        scope: Scope,
+        elem: impl Into<Bus>,
+        value: f32,
+        buffer_offset: u32,
+    ) -> Result<(), Error> {
+        self.unit
+            .set_parameter(id, scope, elem, value, buffer_offset)
+    }
+
+    /// Schedules changes to parameters for the next render. See
+    /// [**AudioUnit::schedule_parameters**](../struct.AudioUnit#method.schedule_parameters).
+    pub fn schedule_parameters(&mut self, events: &[ParameterEvent]) -> Result<(), Error> {
+        self.unit.schedule_parameters(events)
+    }
+}
+
+impl AudioUnit<Uninitialized> {
+    /// Construct a new, uninitialized **AudioUnit** with any type that may be automatically
+    /// converted into [**Type**](../enum.Type).
+    pub fn new<T>(ty: T) -> Result<Self, Error>
+    where
+        T: Into<Type>,
+    {
+        DynamicAudioUnit::new(ty).map(Self::wrap)
+    }
+
+    /// Take over a dynamic **AudioUnit**, stopping and uninitializing it if necessary.
+    pub fn from_dynamic(mut unit: DynamicAudioUnit) -> Result<Self, Error> {
+        unit.uninitialize()?;
+        Ok(Self::wrap(unit))
+    }
+
+    /// Allocate the unit's resources, fixing its formats. See
+    /// [**AudioUnit::initialize**](../struct.AudioUnit#method.initialize).
+    pub fn initialize(mut self) -> Result<AudioUnit<Initialized>, TransitionError<Uninitialized>> {
+        match self.unit.initialize() {
+            Ok(()) => Ok(Self::wrap(self.unit)),
+            Err(error) => Err(TransitionError { error, unit: self }),
+        }
+    }
+
+    /// Sets the value for some property of the **AudioUnit**. See
+    /// [**AudioUnit::set_property**](../struct.AudioUnit#method.set_property).
+    pub fn set_property<T>(
+        &mut self,
+        id: u32,
+        scope: Scope,
+        elem: impl Into<Bus>,
+        maybe_data: Option<&T>,
+    ) -> Result<(), Error> {
+        self.unit.set_property(id, scope, elem, maybe_data)
+    }
+
+    /// Sets the value of the property `P`. See
+    /// [**AudioUnit::set**](../struct.AudioUnit#method.set).
+    pub fn set<P: Property>(
+        &mut self,
+        scope: Scope,
+        elem: impl Into<Bus>,
+        value: &P::Value,
+    ) -> Result<(), Error> {
+        self.unit.set::<P>(scope, elem, value)
+    }
+
+    /// List all possible sample rates and sample formats.
+    pub fn get_formats(&mut self) -> Result<Formats, Error> {
+        self.unit.get_formats()
+    }
+
+    /// Set the **AudioUnit**'s sample rate.
+    pub fn set_sample_rate(&mut self, sample_rate: f64) -> Result<(), Error> {
+        self.unit.set_sample_rate(sample_rate)
+    }
+
+    /// Sets the current **StreamFormat** for the first bus of the given scope.
+    pub fn set_stream_format(
+        &mut self,
+        stream_format: StreamFormat,
+        scope: Scope,
+    ) -> Result<(), Error> {
+        self.unit.set_stream_format(stream_format, scope)
+    }
+
+    /// Sets the **StreamFormat** of a single bus within the given scope.
+    pub fn set_bus_stream_format(
+        &mut self,
+        stream_format: StreamFormat,
+        scope: Scope,
+        bus: impl Into<Bus>,
+    ) -> Result<(), Error> {
+        self.unit.set_bus_stream_format(stream_format, scope, bus)
+    }
+
+    /// Sets the number of buses within the given scope.
+    pub fn set_element_count(&mut self, scope: Scope, count: u32) -> Result<(), Error> {
+        self.unit.set_element_count(scope, count)
+    }
+
+    /// Pass a render callback (aka "Input Procedure") to the **AudioUnit**.
+    pub fn set_render_callback<F, D>(&mut self, f: F) -> Result<(), Error>
+    where
+        F: FnMut(Args<D>) -> Result<(), ()> + 'static,
+        D: Data,
+    {
+        self.unit.set_render_callback(f)
+    }
+
+    /// Pass an input callback (aka "Input Procedure") to the **AudioUnit**.
+    pub fn set_input_callback<F, D>(&mut self, f: F) -> Result<(), Error>
+    where
+        F: FnMut(Args<D>) -> Result<(), ()> + 'static,
+        D: Data,
+    {
+        self.unit.set_input_callback(f)
+    }
+
+    /// Add a render notify callback to the **AudioUnit**.
+    pub fn add_render_notify<F, D>(&mut self, f: F) -> Result<(), Error>
+    where
+        F: FnMut(NotifyArgs<D>) -> Result<(), ()> + 'static,
+        D: Data,
+    {
+        self.unit.add_render_notify(f)
+    }
+}
+
+impl<S: Ready> AudioUnit<S> {
+    /// Renders `num_frames` frames of audio from the given output `bus` into `buffer`. See
+    /// [**AudioUnit::render**](../struct.AudioUnit#method.render).
+    pub fn render<T>(
+        &mut self,
+        flags: &mut ActionFlags,
+        time_stamp: &sys::AudioTimeStamp,
+        bus: u32,
+        num_frames: u32,
+        buffer: &mut AudioBufferList<T>,
+    ) -> Result<(), Error>
+    where
+        T: Sample,
+    {
+        self.unit.render(flags, time_stamp, bus, num_frames, buffer)
+    }
+}
+
+impl AudioUnit<Initialized> {
+    /// Start the I/O unit. See [**AudioUnit::start**](../struct.AudioUnit#method.start).
+    pub fn start(mut self) -> Result<AudioUnit<Running>, TransitionError<Initialized>> {
+        match self.unit.start() {
+            Ok(()) => Ok(Self::wrap(self.unit)),
+            Err(error) => Err(TransitionError { error, unit: self }),
+        }
+    }
+
+    /// Release the unit's resources so that it may be reconfigured. See
+    /// [**AudioUnit::uninitialize**](../struct.AudioUnit#method.uninitialize).
+    pub fn uninitialize(
+        mut self,
+    ) -> Result<AudioUnit<Uninitialized>, TransitionError<Initialized>> {
+        match self.unit.uninitialize() {
+            Ok(()) => Ok(Self::wrap(self.unit)),
+            Err(error) => Err(TransitionError { error, unit: self }),
+        }
+    }
+}
+
+impl AudioUnit<Running> {
+    /// Stop the I/O unit. See [**AudioUnit::stop**](../struct.AudioUnit#method.stop).
+    pub fn stop(mut self) -> Result<AudioUnit<Initialized>, TransitionError<Running>> {
+        match self.unit.stop() {
+            Ok(()) => Ok(Self::wrap(self.unit)),
+            Err(error) => Err(TransitionError { error, unit: self }),
+        }
+    }
+}
+
+impl<S: State> Deref for AudioUnit<S> {
+    type Target = DynamicAudioUnit;
+    fn deref(&self) -> &DynamicAudioUnit {
+        &self.unit
+    }
+}
+
+impl<S: State> From<TransitionError<S>> for Error {
+    fn from(err: TransitionError<S>) -> Self {
+        err.error
+    }
+}
+
+impl<S: State> fmt::Debug for TransitionError<S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TransitionError")
+            .field("error", &self.error)
+            .finish()
+    }
+}
+
+impl<S: State> fmt::Display for TransitionError<S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.error)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::audio_unit::EffectType;
+
+    #[test]
+    fn test_lifecycle() {
+        let mut unit = AudioUnit::new(EffectType::Delay).unwrap();
+        assert!(unit.get_formats().is_ok());
+        let unit = unit.initialize().unwrap();
+        assert!(unit.stream_format(Scope::Output).is_ok());
+        let unit = unit.uninitialize().unwrap();
+        let mut dynamic = unit.into_dynamic();
+        dynamic.initialize().unwrap();
+        let mut unit = AudioUnit::from_dynamic(dynamic).unwrap();
+        assert!(unit.get_formats().is_ok());
+    }
+}
@@ -0,0 +1,359 @@
+//! Configuration of the voice-processing I/O unit.
+//!
+//! `IOType::VoiceProcessingIO` cancels the echo of its output from the microphone signal and
+//! applies further processing suited to voice chat. A
+//! [**VoiceProcessing**](./struct.VoiceProcessing) configuration sets its properties in one go,
+//! and [**AudioUnit::voice_processing**](../struct.AudioUnit#method.voice_processing) reports
+//! the active settings.
+//!
+//! ```no_run
+//! # use coreaudio::audio_unit::{AudioUnit, IOType};
+//! # use coreaudio::audio_unit::voice_processing::{Ducking, DuckingLevel, VoiceProcessing};
+//! # fn main() -> Result<(), coreaudio::Error> {
+//! let mut unit = AudioUnit::new(IOType::VoiceProcessingIO)?;
+//! unit.apply_voice_processing(&VoiceProcessing {
+//!     agc: false,
+//!     ducking: Some(Ducking { advanced: true, level: DuckingLevel::Min }),
+//!     ..VoiceProcessing::default()
+//! })?;
+//! let _listener = unit.set_speech_activity_listener(|activity| {
+//!     println!("speech while muted: {:?}", activity);
+//! })?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::os::raw::{c_int, c_ulong, c_void};
+use std::ptr;
+use std::sync::{Arc, Mutex};
+
+use super::listener::{Detach, SharedInstance};
+use super::property::{
+    BypassVoiceProcessing, DuckingConfiguration, MuteOutput, OtherAudioDuckingConfiguration,
+    VoiceProcessingEnableAgc,
+};
+use super::{AudioUnit, Element, Scope};
+use crate::error::{AudioUnitError, Error};
+
+/// The element the voice-processing properties are set on, which is the microphone input.
+pub const ELEMENT: Element = Element::Input;
+
+/// `kAUVoiceIOProperty_MutedSpeechActivityEventListener`
+const MUTED_SPEECH_ACTIVITY_EVENT_LISTENER: u32 = 2106;
+
+/// How much other audio is lowered while the voice-processing unit runs.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DuckingLevel {
+    /// The level used by default, equivalent to `Max`.
+    Default = 0,
+    Min = 10,
+    Mid = 20,
+    Max = 30,
+}
+
+impl DuckingLevel {
+    /// Convert from an `AUVoiceIOOtherAudioDuckingLevel`.
+    pub fn from_u32(level: u32) -> Option<Self> {
+        match level {
+            0 => Some(DuckingLevel::Default),
+            10 => Some(DuckingLevel::Min),
+            20 => Some(DuckingLevel::Mid),
+            30 => Some(DuckingLevel::Max),
+            _ => None,
+        }
+    }
+}
+
+/// The ducking of other audio by the voice-processing unit.
+///
+/// **Available** in macOS 14.0 and iOS 17.0 and later.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Ducking {
+    /// Only duck other audio while someone is speaking, rather than at all times.
+    pub advanced: bool,
+    pub level: DuckingLevel,
+}
+
+impl From<Ducking> for DuckingConfiguration {
+    fn from(ducking: Ducking) -> Self {
+        DuckingConfiguration::new(ducking.advanced, ducking.level as u32)
+    }
+}
+
+/// The settings of a voice-processing I/O unit.
+///
+/// The default matches the settings of a newly created unit, except for `ducking`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct VoiceProcessing {
+    /// Pass the microphone signal through without echo cancellation or other processing.
+    pub bypass: bool,
+    /// Apply automatic gain control to the microphone signal.
+    pub agc: bool,
+    /// Silence the processed microphone signal, e.g. for a mute button. Speech is still
+    /// detected, see
+    /// [**set_speech_activity_listener**](../struct.AudioUnit#method.set_speech_activity_listener).
+    pub mute_output: bool,
+    /// The ducking of other audio, or `None` to leave it unchanged. Reported as `None` on
+    /// systems without ducking control.
+    pub ducking: Option<Ducking>,
+}
+
+impl Default for VoiceProcessing {
+    fn default() -> Self {
+        VoiceProcessing {
+            bypass: false,
+            agc: true,
+            mute_output: false,
+            ducking: None,
+        }
+    }
+}
+
+/// A change in speech detected while the output of the voice-processing unit is muted.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SpeechActivity {
+    Started = 0,
+    Ended = 1,
+}
+
+impl SpeechActivity {
+    /// Convert from an `AUVoiceIOSpeechActivityEvent`.
+    pub fn from_u32(event: u32) -> Option<Self> {
+        match event {
+            0 => Some(SpeechActivity::Started),
+            1 => Some(SpeechActivity::Ended),
+            _ => None,
+        }
+    }
+}
+
+// The listener property takes an Objective-C block. The block is built on the stack following the
+// block ABI, as the compiler would for a block literal, and Core Audio copies it to the heap to
+// keep it. The copy and dispose helpers then keep the state it captures alive for as long as any
+// copy of the block exists.
+const BLOCK_HAS_COPY_DISPOSE: c_int = 1 << 25;
+
+extern "C" {
+    static _NSConcreteStackBlock: [*const c_void; 32];
+}
+
+#[repr(C)]
+struct BlockDescriptor {
+    reserved: c_ulong,
+    size: c_ulong,
+    copy: unsafe extern "C" fn(*mut SpeechActivityBlock, *const SpeechActivityBlock),
+    dispose: unsafe extern "C" fn(*const SpeechActivityBlock),
+}
+
+/// A block literal of type `void (^)(AUVoiceIOSpeechActivityEvent)`, capturing the state of the
+/// listener.
+#[repr(C)]
+struct SpeechActivityBlock {
+    isa: *const c_void,
+    flags: c_int,
+    reserved: c_int,
+    invoke: unsafe extern "C" fn(*const SpeechActivityBlock, u32),
+    descriptor: *const BlockDescriptor,
+    state: *const SpeechActivityState,
+}
+
+static SPEECH_ACTIVITY_BLOCK_DESCRIPTOR: BlockDescriptor = BlockDescriptor {
+    reserved: 0,
+    size: std::mem::size_of::<SpeechActivityBlock>() as c_ulong,
+    copy: speech_activity_copy,
+    dispose: speech_activity_dispose,
+};
+
+type Callback = Box<dyn FnMut(SpeechActivity) + Send>;
+
+struct SpeechActivityState {
+    // `None` once the listener is removed or the unit is disposed.
+    callback: Mutex<Option<Callback>>,
+}
+
+impl Detach for SpeechActivityState {
+    fn detach(&self) {
+        if let Ok(mut callback) = self.callback.lock() {
+            *callback = None;
+        }
+    }
+}
+
+unsafe extern "C" fn speech_activity_invoke(block: *const SpeechActivityBlock, event: u32) {
+    let activity = match SpeechActivity::from_u32(event) {
+        Some(activity) => activity,
+        None => return,
+    };
+    if let Ok(mut callback) = (*(*block).state).callback.lock() {
+        if let Some(callback) = callback.as_mut() {
+            callback(activity);
+        }
+    }
+}
+
+// Called by the runtime after copying the block, which has already copied the state pointer.
+unsafe extern "C" fn speech_activity_copy(
+    _dst: *mut SpeechActivityBlock,
+    src: *const SpeechActivityBlock,
+) {
+    Arc::increment_strong_count((*src).state);
+}
+
+// Called by the runtime before freeing a copy of the block.
+unsafe extern "C" fn speech_activity_dispose(block: *const SpeechActivityBlock) {
+    Arc::decrement_strong_count((*block).state);
+}
+
+/// Removes the speech activity listener when dropped, unless it has been replaced since.
+///
+/// The closure is dropped with the handle, or when the **AudioUnit** is disposed if the handle
+/// outlives it.
+pub struct SpeechActivityListener {
+    instance: SharedInstance,
+    state: Arc<SpeechActivityState>,
+}
+
+impl Drop for SpeechActivityListener {
+    fn drop(&mut self) {
+        self.state.detach();
+        let user_data = Arc::as_ptr(&self.state) as usize;
+        self.instance.uninstall(
+            MUTED_SPEECH_ACTIVITY_EVENT_LISTENER,
+            user_data,
+            |instance| {
+                let none: *const c_void = ptr::null();
+                super::set_property(
+                    instance,
+                    MUTED_SPEECH_ACTIVITY_EVENT_LISTENER,
+                    Scope::Global,
+                    ELEMENT,
+                    Some(&none),
+                )
+                .ok();
+            },
+        );
+    }
+}
+
+impl AudioUnit {
+    /// Apply the voice-processing settings to an `IOType::VoiceProcessingIO` unit.
+    ///
+    /// **Available** in iOS 3.0 and later.
+    pub fn apply_voice_processing(&mut self, config: &VoiceProcessing) -> Result<(), Error> {
+        self.set::<BypassVoiceProcessing>(Scope::Global, ELEMENT, &config.bypass)?;
+        self.set::<VoiceProcessingEnableAgc>(Scope::Global, ELEMENT, &config.agc)?;
+        self.set::<MuteOutput>(Scope::Global, ELEMENT, &config.mute_output)?;
+        if let Some(ducking) = config.ducking {
+            self.set::<OtherAudioDuckingConfiguration>(Scope::Global, ELEMENT, &ducking.into())?;
+        }
+        Ok(())
+    }
+
+    /// The active settings of an `IOType::VoiceProcessingIO` unit.
+    ///
+    /// **Available** in iOS 3.0 and later.
+    pub fn voice_processing(&self) -> Result<VoiceProcessing, Error> {
+        let ducking = match self.get::<OtherAudioDuckingConfiguration>(Scope::Global, ELEMENT) {
+            Ok(config) => DuckingLevel::from_u32(config.ducking_level).map(|level| Ducking {
+                advanced: config.enable_advanced_ducking != 0,
+                level,
+            }),
+            Err(Error::AudioUnit(AudioUnitError::InvalidProperty)) => None,
+            Err(err) => return Err(err),
+        };
+        Ok(VoiceProcessing {
+            bypass: self.get::<BypassVoiceProcessing>(Scope::Global, ELEMENT)?,
+            agc: self.get::<VoiceProcessingEnableAgc>(Scope::Global, ELEMENT)?,
+            mute_output: self.get::<MuteOutput>(Scope::Global, ELEMENT)?,
+            ducking,
+        })
+    }
+
+    /// Calls `f` when speech starts or ends while the output of an `IOType::VoiceProcessingIO`
+    /// unit is muted, e.g. to remind the user that they are muted.
+    ///
+    /// Replaces any previous listener. The listener is removed when the returned handle is
+    /// dropped, while dropping the handle of a replaced listener leaves the new one in place.
+    ///
+    /// **Available** in macOS 14.0 and iOS 17.0 and later.
+    pub fn set_speech_activity_listener<F>(&mut self, f: F) -> Result<SpeechActivityListener, Error>
+    where
+        F: FnMut(SpeechActivity) + Send + 'static,
+    {
+        let state = Arc::new(SpeechActivityState {
+            callback: Mutex::new(Some(Box::new(f))),
+        });
+        let block = SpeechActivityBlock {
+            isa: unsafe { _NSConcreteStackBlock.as_ptr() as *const c_void },
+            flags: BLOCK_HAS_COPY_DISPOSE,
+            reserved: 0,
+            invoke: speech_activity_invoke,
+            descriptor: &SPEECH_ACTIVITY_BLOCK_DESCRIPTOR,
+            state: Arc::as_ptr(&state),
+        };
+        let block_ptr = &block as *const SpeechActivityBlock as *const c_void;
+        let user_data = Arc::as_ptr(&state) as usize;
+        self.listener_instance.install(
+            MUTED_SPEECH_ACTIVITY_EVENT_LISTENER,
+            user_data,
+            |instance| {
+                super::set_property(
+                    instance,
+                    MUTED_SPEECH_ACTIVITY_EVENT_LISTENER,
+                    Scope::Global,
+                    ELEMENT,
+                    Some(&block_ptr),
+                )
+            },
+        )?;
+        self.listener_instance.keep(state.clone());
+        Ok(SpeechActivityListener {
+            instance: self.listener_instance.clone(),
+            state,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::audio_unit::property::{Property, PropertyValue};
+    use std::mem;
+
+    fn mapping<P: Property>() -> (u32, Vec<u32>) {
+        (P::ID, P::SCOPES.iter().map(|&scope| scope as u32).collect())
+    }
+
+    #[test]
+    fn test_property_mapping() {
+        let global = vec![Scope::Global as u32];
+        assert_eq!(mapping::<BypassVoiceProcessing>(), (2100, global.clone()));
+        assert_eq!(
+            mapping::<VoiceProcessingEnableAgc>(),
+            (2101, global.clone())
+        );
+        assert_eq!(mapping::<MuteOutput>(), (2104, global.clone()));
+        assert_eq!(mapping::<OtherAudioDuckingConfiguration>(), (2108, global));
+        assert_eq!(ELEMENT as u32, 1);
+    }
+
+    #[test]
+    fn test_ducking_configuration() {
+        assert_eq!(mem::size_of::<DuckingConfiguration>(), 8);
+        let ducking = Ducking {
+            advanced: true,
+            level: DuckingLevel::Mid,
+        };
+        let config = DuckingConfiguration::from(ducking);
+        config.with_data(|data| {
+            assert_eq!(data[0], 1);
+            assert_eq!(data[4..], 20u32.to_ne_bytes());
+            assert_eq!(DuckingConfiguration::from_data(data).unwrap(), config);
+        });
+        for &level in &[0, 10, 20, 30] {
+            assert_eq!(DuckingLevel::from_u32(level).unwrap() as u32, level);
+        }
+        assert_eq!(DuckingLevel::from_u32(15), None);
+        assert_eq!(SpeechActivity::from_u32(1), Some(SpeechActivity::Ended));
+    }
+}
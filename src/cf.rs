@@ -0,0 +1,187 @@
+//! Helpers for converting between Core Foundation and Rust types.
+
+use std::ffi::CStr;
+use std::os::raw::{c_char, c_void};
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+use std::ptr;
+use std::slice;
+
+use core_foundation_sys::array::{kCFTypeArrayCallBacks, CFArrayCreate, CFArrayRef};
+use core_foundation_sys::base::{kCFAllocatorDefault, CFRelease};
+use core_foundation_sys::data::{CFDataCreate, CFDataGetBytePtr, CFDataGetLength};
+use core_foundation_sys::propertylist::{
+    kCFPropertyListImmutable, kCFPropertyListXMLFormat_v1_0, CFPropertyListCreateData,
+    CFPropertyListCreateWithData, CFPropertyListRef,
+};
+use core_foundation_sys::string::{
+    kCFStringEncodingUTF8, CFStringCreateWithBytes, CFStringGetCString, CFStringGetLength,
+    CFStringGetMaximumSizeForEncoding, CFStringRef,
+};
+use core_foundation_sys::url::{CFURLCreateFromFileSystemRepresentation, CFURLRef};
+
+/// Copy the contents of a `CFString` into a `String`.
+///
+/// Returns an empty `String` for a null reference. The reference is not released.
+pub(crate) unsafe fn cfstring_to_string(string: CFStringRef) -> String {
+    if string.is_null() {
+        return String::new();
+    }
+    let len = CFStringGetLength(string);
+    let size = CFStringGetMaximumSizeForEncoding(len, kCFStringEncodingUTF8) + 1;
+    let mut bytes = vec![0 as c_char; size as usize];
+    if CFStringGetCString(string, bytes.as_mut_ptr(), size, kCFStringEncodingUTF8) == 0 {
+        return String::new();
+    }
+    CStr::from_ptr(bytes.as_ptr())
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Copy the contents of a `CFString` that the caller owns into a `String`, and release it.
+///
+/// Use this for strings obtained through functions following the "Create" or "Copy" rule, such as
+/// `AudioComponentCopyName` or string-valued properties.
+pub(crate) unsafe fn take_cfstring(string: CFStringRef) -> String {
+    let result = cfstring_to_string(string);
+    if !string.is_null() {
+        CFRelease(string as *const _);
+    }
+    result
+}
+
+/// Call `f` with a temporary `CFString` holding a copy of `string`.
+///
+/// The `CFString` is released once `f` returns, so `f` must retain it if it is kept.
+pub(crate) fn with_cfstring<R, F>(string: &str, f: F) -> R
+where
+    F: FnOnce(CFStringRef) -> R,
+{
+    unsafe {
+        let cf_string = CFStringCreateWithBytes(
+            kCFAllocatorDefault,
+            string.as_ptr(),
+            string.len() as _,
+            kCFStringEncodingUTF8,
+            0,
+        );
+        let result = f(cf_string);
+        if !cf_string.is_null() {
+            CFRelease(cf_string as *const _);
+        }
+        result
+    }
+}
+
+unsafe fn create_cfurl(path: &Path) -> CFURLRef {
+    let bytes = path.as_os_str().as_bytes();
+    CFURLCreateFromFileSystemRepresentation(
+        kCFAllocatorDefault,
+        bytes.as_ptr(),
+        bytes.len() as _,
+        path.is_dir() as _,
+    )
+}
+
+/// Call `f` with a temporary file `CFURL` for `path`.
+///
+/// The `CFURL` is released once `f` returns, so `f` must retain it if it is kept.
+pub(crate) fn with_cfurl<R, F>(path: &Path, f: F) -> R
+where
+    F: FnOnce(CFURLRef) -> R,
+{
+    unsafe {
+        let url = create_cfurl(path);
+        let result = f(url);
+        if !url.is_null() {
+            CFRelease(url as *const _);
+        }
+        result
+    }
+}
+
+/// Call `f` with a temporary `CFArray` of file `CFURL`s, one for each of `paths`.
+///
+/// The array and its URLs are released once `f` returns.
+pub(crate) fn with_cfurl_array<P, R, F>(paths: &[P], f: F) -> R
+where
+    P: AsRef<Path>,
+    F: FnOnce(CFArrayRef) -> R,
+{
+    unsafe {
+        let urls: Vec<*const c_void> = paths
+            .iter()
+            .map(|path| create_cfurl(path.as_ref()) as *const c_void)
+            .filter(|url| !url.is_null())
+            .collect();
+        // The array retains the URLs.
+        let array = CFArrayCreate(
+            kCFAllocatorDefault,
+            urls.as_ptr(),
+            urls.len() as _,
+            &kCFTypeArrayCallBacks,
+        );
+        for &url in &urls {
+            CFRelease(url);
+        }
+        let result = f(array);
+        if !array.is_null() {
+            CFRelease(array as *const _);
+        }
+        result
+    }
+}
+
+/// Serialize a property list that the caller owns to XML, and release it.
+///
+/// Returns `None` for a null reference or if the property list can't be serialized.
+pub(crate) unsafe fn take_property_list_xml(list: CFPropertyListRef) -> Option<Vec<u8>> {
+    if list.is_null() {
+        return None;
+    }
+    let data = CFPropertyListCreateData(
+        kCFAllocatorDefault,
+        list,
+        kCFPropertyListXMLFormat_v1_0,
+        0,
+        ptr::null_mut(),
+    );
+    CFRelease(list);
+    if data.is_null() {
+        return None;
+    }
+    let bytes = slice::from_raw_parts(CFDataGetBytePtr(data), CFDataGetLength(data) as usize);
+    let xml = bytes.to_vec();
+    CFRelease(data as *const _);
+    Some(xml)
+}
+
+/// Call `f` with a temporary property list parsed from an XML document.
+///
+/// Returns `None` without calling `f` if Core Foundation can't parse the document. The property
+/// list is released once `f` returns.
+pub(crate) fn with_property_list_from_xml<R, F>(xml: &[u8], f: F) -> Option<R>
+where
+    F: FnOnce(CFPropertyListRef) -> R,
+{
+    unsafe {
+        let data = CFDataCreate(kCFAllocatorDefault, xml.as_ptr(), xml.len() as _);
+        if data.is_null() {
+            return None;
+        }
+        let list = CFPropertyListCreateWithData(
+            kCFAllocatorDefault,
+            data,
+            kCFPropertyListImmutable,
+            ptr::null_mut(),
+            ptr::null_mut(),
+        );
+        CFRelease(data as *const _);
+        if list.is_null() {
+            return None;
+        }
+        let result = f(list);
+        CFRelease(list);
+        Some(result)
+    }
+}
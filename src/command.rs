@@ -0,0 +1,323 @@
+//! A wait-free channel for sending commands into a render or input callback.
+//!
+//! Changing a gain, swapping a sound or toggling mute from the UI thread would otherwise require
+//! shared mutable state captured by the callback closure. Instead, the [**Sender**](./struct.Sender)
+//! stays on the control thread and the [**Receiver**](./struct.Receiver) is moved into the
+//! callback, which drains pending commands at the start of each render.
+//!
+//! Neither half allocates or frees when sending or receiving. Commands that own large objects
+//! (e.g. a new sample buffer) should not be dropped on the realtime thread either, so the receiver
+//! can hand them back through [**Receiver::retire**](./struct.Receiver#method.retire). Retired
+//! values are dropped on the control thread the next time it sends a command or calls
+//! [**Sender::collect_garbage**](./struct.Sender#method.collect_garbage).
+//!
+//! ```
+//! use coreaudio::command;
+//!
+//! enum Command {
+//!     SetGain(f32),
+//!     SetSound(Vec<f32>),
+//! }
+//!
+//! let (mut sender, mut receiver) = command::channel::<Command, Command>(16);
+//! sender.send(Command::SetSound(vec![0.5; 4096])).ok();
+//! sender.send(Command::SetGain(0.25)).ok();
+//!
+//! // Inside the render callback.
+//! let mut gain = 1.0;
+//! let mut sound = Vec::new();
+//! while let Some(command) = receiver.try_recv() {
+//!     match command {
+//!         Command::SetGain(g) => gain = g,
+//!         Command::SetSound(s) => {
+//!             // Hand the previous sound back so it is freed on the control thread.
+//!             let old = std::mem::replace(&mut sound, s);
+//!             receiver.retire(Command::SetSound(old)).ok();
+//!         }
+//!     }
+//! }
+//! assert_eq!(gain, 0.25);
+//! assert_eq!(sound.len(), 4096);
+//!
+//! // Back on the control thread.
+//! assert_eq!(sender.collect_garbage(), 1);
+//! ```
+
+use std::cell::UnsafeCell;
+use std::mem::MaybeUninit;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// The control side of a command channel, created by [**channel**](./fn.channel).
+///
+/// Sends commands of type `T` and drops the garbage of type `G` returned by the receiver.
+pub struct Sender<T, G = T> {
+    commands: Arc<Queue<T>>,
+    garbage: Arc<Queue<G>>,
+}
+
+/// The callback side of a command channel, created by [**channel**](./fn.channel).
+pub struct Receiver<T, G = T> {
+    commands: Arc<Queue<T>>,
+    garbage: Arc<Queue<G>>,
+}
+
+/// Create a command channel holding up to `capacity` pending commands.
+///
+/// The garbage queue returning values from the receiver has the same capacity.
+pub fn channel<T: Send, G: Send>(capacity: usize) -> (Sender<T, G>, Receiver<T, G>) {
+    assert!(
+        capacity > 0,
+        "the command channel capacity must be non-zero"
+    );
+    let commands = Arc::new(Queue::new(capacity));
+    let garbage = Arc::new(Queue::new(capacity));
+    let sender = Sender {
+        commands: commands.clone(),
+        garbage: garbage.clone(),
+    };
+    let receiver = Receiver { commands, garbage };
+    (sender, receiver)
+}
+
+impl<T, G> Sender<T, G> {
+    /// Send a command to the receiver.
+    ///
+    /// Any garbage returned by the receiver is dropped first. Returns the command back if the
+    /// channel is full.
+    pub fn send(&mut self, command: T) -> Result<(), T> {
+        self.collect_garbage();
+        self.commands.push(command)
+    }
+
+    /// Drop all values retired by the receiver.
+    ///
+    /// Returns the number of values dropped.
+    pub fn collect_garbage(&mut self) -> usize {
+        let mut count = 0;
+        while self.garbage.pop().is_some() {
+            count += 1;
+        }
+        count
+    }
+
+    /// The number of commands that have not been received yet.
+    pub fn pending(&self) -> usize {
+        self.commands.len()
+    }
+
+    /// The maximum number of pending commands.
+    pub fn capacity(&self) -> usize {
+        self.commands.capacity
+    }
+}
+
+impl<T, G> Receiver<T, G> {
+    /// Take the next pending command, if any.
+    pub fn try_recv(&mut self) -> Option<T> {
+        self.commands.pop()
+    }
+
+    /// An iterator that takes pending commands until the channel is empty.
+    pub fn try_iter(&mut self) -> TryIter<'_, T, G> {
+        TryIter { receiver: self }
+    }
+
+    /// Hand a value back to the sender so that it is dropped on the control thread.
+    ///
+    /// Returns the value back if the garbage queue is full, in which case it may be kept and
+    /// retired again during a later callback.
+    pub fn retire(&mut self, garbage: G) -> Result<(), G> {
+        self.garbage.push(garbage)
+    }
+}
+
+/// An iterator over the pending commands of a [**Receiver**](./struct.Receiver).
+pub struct TryIter<'a, T, G> {
+    receiver: &'a mut Receiver<T, G>,
+}
+
+impl<T, G> Iterator for TryIter<'_, T, G> {
+    type Item = T;
+    fn next(&mut self) -> Option<T> {
+        self.receiver.try_recv()
+    }
+}
+
+unsafe impl<T: Send, G: Send> Send for Sender<T, G> {}
+unsafe impl<T: Send, G: Send> Send for Receiver<T, G> {}
+
+// A fixed-capacity single-producer single-consumer queue.
+struct Queue<T> {
+    slots: Box<[UnsafeCell<MaybeUninit<T>>]>,
+    capacity: usize,
+    // The total number of values pushed and popped. Both wrap around, only their difference is
+    // meaningful.
+    head: AtomicUsize,
+    tail: AtomicUsize,
+}
+
+// Only one thread pushes and only one thread pops, and a slot is never accessed by both at the
+// same time, which is ensured by the acquire/release ordering of `head` and `tail`.
+unsafe impl<T: Send> Sync for Queue<T> {}
+
+impl<T> Queue<T> {
+    fn new(capacity: usize) -> Self {
+        let slots = (0..capacity)
+            .map(|_| UnsafeCell::new(MaybeUninit::uninit()))
+            .collect();
+        Queue {
+            slots,
+            capacity,
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+        }
+    }
+
+    fn len(&self) -> usize {
+        let tail = self.tail.load(Ordering::Acquire);
+        let head = self.head.load(Ordering::Acquire);
+        tail.wrapping_sub(head)
+    }
+
+    fn push(&self, value: T) -> Result<(), T> {
+        let tail = self.tail.load(Ordering::Relaxed);
+        let head = self.head.load(Ordering::Acquire);
+        if tail.wrapping_sub(head) == self.capacity {
+            return Err(value);
+        }
+        unsafe { (*self.slots[tail % self.capacity].get()).write(value) };
+        self.tail.store(tail.wrapping_add(1), Ordering::Release);
+        Ok(())
+    }
+
+    fn pop(&self) -> Option<T> {
+        let head = self.head.load(Ordering::Relaxed);
+        let tail = self.tail.load(Ordering::Acquire);
+        if head == tail {
+            return None;
+        }
+        let value = unsafe { (*self.slots[head % self.capacity].get()).assume_init_read() };
+        self.head.store(head.wrapping_add(1), Ordering::Release);
+        Some(value)
+    }
+}
+
+impl<T> Drop for Queue<T> {
+    fn drop(&mut self) {
+        while self.pop().is_some() {}
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::thread;
+
+    struct Tracked(Arc<AtomicUsize>);
+
+    impl Drop for Tracked {
+        fn drop(&mut self) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn test_full_and_empty() {
+        let (mut sender, mut receiver) = channel::<u32, u32>(2);
+        assert_eq!(receiver.try_recv(), None);
+        assert_eq!(sender.send(1), Ok(()));
+        assert_eq!(sender.send(2), Ok(()));
+        assert_eq!(sender.send(3), Err(3));
+        assert_eq!(sender.pending(), 2);
+        assert_eq!(receiver.try_iter().collect::<Vec<_>>(), vec![1, 2]);
+        assert_eq!(sender.pending(), 0);
+    }
+
+    #[test]
+    fn test_garbage_dropped_by_sender() {
+        let drops = Arc::new(AtomicUsize::new(0));
+        let (mut sender, mut receiver) = channel::<Tracked, Tracked>(4);
+        for _ in 0..4 {
+            sender.send(Tracked(drops.clone())).ok().unwrap();
+        }
+        while let Some(command) = receiver.try_recv() {
+            receiver.retire(command).ok().unwrap();
+        }
+        assert_eq!(drops.load(Ordering::SeqCst), 0);
+        assert_eq!(sender.collect_garbage(), 4);
+        assert_eq!(drops.load(Ordering::SeqCst), 4);
+    }
+
+    #[test]
+    fn test_pending_values_dropped_with_channel() {
+        let drops = Arc::new(AtomicUsize::new(0));
+        let (mut sender, mut receiver) = channel::<Tracked, Tracked>(4);
+        sender.send(Tracked(drops.clone())).ok().unwrap();
+        sender.send(Tracked(drops.clone())).ok().unwrap();
+        let command = receiver.try_recv().unwrap();
+        receiver.retire(command).ok().unwrap();
+        drop(sender);
+        drop(receiver);
+        assert_eq!(drops.load(Ordering::SeqCst), 2);
+    }
+
+    // A command that must be dropped on the thread that created it.
+    struct Owned {
+        value: usize,
+        thread: thread::ThreadId,
+        drops: Arc<AtomicUsize>,
+    }
+
+    impl Drop for Owned {
+        fn drop(&mut self) {
+            assert_eq!(thread::current().id(), self.thread);
+            self.drops.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn test_threaded() {
+        const COMMANDS: usize = 20_000;
+        let (mut sender, mut receiver) = channel::<Owned, Owned>(8);
+
+        let receiving = thread::spawn(move || {
+            let mut expected = 0;
+            while expected < COMMANDS {
+                match receiver.try_recv() {
+                    Some(mut command) => {
+                        assert_eq!(command.value, expected);
+                        expected += 1;
+                        while let Err(returned) = receiver.retire(command) {
+                            command = returned;
+                            thread::yield_now();
+                        }
+                    }
+                    None => thread::yield_now(),
+                }
+            }
+        });
+
+        let drops = Arc::new(AtomicUsize::new(0));
+        let mut next = 0;
+        let mut rejected = 0;
+        while next < COMMANDS {
+            let command = Owned {
+                value: next,
+                thread: thread::current().id(),
+                drops: drops.clone(),
+            };
+            match sender.send(command) {
+                Ok(()) => next += 1,
+                Err(_) => {
+                    rejected += 1;
+                    thread::yield_now();
+                }
+            }
+        }
+        receiving.join().unwrap();
+        sender.collect_garbage();
+        assert_eq!(drops.load(Ordering::SeqCst), COMMANDS + rejected);
+        assert_eq!(sender.pending(), 0);
+    }
+}
@@ -254,6 +254,20 @@ pub enum Error {
     Unknown(OSStatus),
     AlreadyInitialized,
     SampleFormatDoesntMatchQueueType,
+    UnexpectedPropertySize,
+    ParameterOutOfRange,
+    GraphCycle,
+    BusAlreadyConnected,
+    UnconnectedInput,
+    NoOutputUnit,
+    StreamFormatMismatch,
+    Io(::std::io::ErrorKind),
+    WavTooLarge,
+    InvalidMidiMessage,
+    UnsupportedInstrumentFile,
+    ChannelCountMismatch,
+    InvalidPropertyList,
+    AudioUnitDisposed,
 }
 
 impl Error {
@@ -323,6 +337,26 @@ impl ::std::fmt::Display for Error {
             Error::Unknown(_) => write!(f, "An unknown error unknown to the coreaudio-rs API occurred"),
             Error::AlreadyInitialized => write!(f, "Operation must be done before unit is initialized"),
             Error::SampleFormatDoesntMatchQueueType => write!(f, "The SampleFormat doesn't match generic type S of the queue"),
+            Error::UnexpectedPropertySize => write!(f, "The size of the property data doesn't match the property value type"),
+            Error::ParameterOutOfRange => write!(f, "The parameter value is outside the range of the parameter"),
+            Error::GraphCycle => write!(f, "The connection would create a cycle in the graph"),
+            Error::BusAlreadyConnected => write!(f, "The input bus is already connected"),
+            Error::UnconnectedInput => write!(f, "An input bus of a node in the graph is not connected"),
+            Error::NoOutputUnit => write!(f, "None of the sink nodes of the graph is an I/O unit"),
+            Error::StreamFormatMismatch => write!(f, "The stream formats of the connected buses don't match"),
+            Error::Io(kind) => write!(f, "An I/O error occurred: {:?}", kind),
+            Error::WavTooLarge => write!(f, "The audio data exceeds the maximum size of a WAV file"),
+            Error::InvalidMidiMessage => write!(f, "The MIDI message contains an out of range channel or data byte"),
+            Error::UnsupportedInstrumentFile => write!(f, "The file is not of a kind the instrument can load"),
+            Error::ChannelCountMismatch => write!(f, "The buffers don't match the channel counts of the mixing matrix"),
+            Error::InvalidPropertyList => write!(f, "The property list is malformed"),
+            Error::AudioUnitDisposed => write!(f, "The audio unit has been disposed"),
         }
     }
 }
+
+impl From<::std::io::Error> for Error {
+    fn from(err: ::std::io::Error) -> Self {
+        Error::Io(err.kind())
+    }
+}
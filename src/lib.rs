@@ -21,7 +21,15 @@ pub mod audio_unit;
 #[cfg(feature = "audio_queue")]
 pub mod audio_queue;
 
+pub mod command;
 pub mod error;
+pub mod plist;
+pub mod ring_buffer;
+pub mod schedule;
+pub mod smoother;
+
+#[cfg(feature = "audio_unit")]
+mod cf;
 
 mod audio_format;
 pub use audio_format::*;
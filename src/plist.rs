@@ -0,0 +1,577 @@
+//! A Rust representation of property lists, with an encoder and decoder for their XML form.
+//!
+//! Audio units save their state as a property list, see
+//! [**AudioUnit::save_state**](../audio_unit/struct.AudioUnit#method.save_state). The XML form
+//! written by [**Plist::to_xml**](./enum.Plist#method.to_xml) matches the one written by Core
+//! Foundation, with dictionary keys sorted, so saved states can be stored in text files and
+//! diffed.
+//!
+//! ```
+//! use coreaudio::plist::Plist;
+//!
+//! let mut state = Plist::dictionary();
+//! state.insert("name", Plist::String("Untitled".into()));
+//! state.insert("version", Plist::Integer(0));
+//!
+//! let xml = state.to_xml();
+//! assert!(xml.contains("<key>name</key>"));
+//! assert_eq!(Plist::from_xml(&xml).unwrap(), state);
+//! ```
+//!
+//! Only the XML format is supported. Dates are kept as their ISO 8601 text.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use crate::error::Error;
+
+const HEADER: &str = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+<!DOCTYPE plist PUBLIC \"-//Apple//DTD PLIST 1.0//EN\" \
+\"http://www.apple.com/DTDs/PropertyList-1.0.dtd\">\n\
+<plist version=\"1.0\">\n";
+const FOOTER: &str = "</plist>\n";
+
+/// A property list value.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Plist {
+    Dictionary(BTreeMap<String, Plist>),
+    Array(Vec<Plist>),
+    String(String),
+    Integer(i64),
+    Real(f64),
+    Boolean(bool),
+    Data(Vec<u8>),
+    /// A date in ISO 8601 format, e.g. `2024-01-31T12:00:00Z`.
+    Date(String),
+}
+
+impl Plist {
+    /// An empty dictionary.
+    pub fn dictionary() -> Self {
+        Plist::Dictionary(BTreeMap::new())
+    }
+
+    /// Insert a value into a dictionary, returning the value previously stored under `key`.
+    ///
+    /// Panics if `self` is not a dictionary.
+    pub fn insert<K: Into<String>>(&mut self, key: K, value: Plist) -> Option<Plist> {
+        match self {
+            Plist::Dictionary(dict) => dict.insert(key.into(), value),
+            _ => panic!("`Plist::insert` called on a value that is not a dictionary"),
+        }
+    }
+
+    /// The value stored under `key`, if `self` is a dictionary.
+    pub fn get(&self, key: &str) -> Option<&Plist> {
+        self.as_dictionary().and_then(|dict| dict.get(key))
+    }
+
+    pub fn as_dictionary(&self) -> Option<&BTreeMap<String, Plist>> {
+        match self {
+            Plist::Dictionary(dict) => Some(dict),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Plist]> {
+        match self {
+            Plist::Array(array) => Some(array),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Plist::String(string) => Some(string),
+            _ => None,
+        }
+    }
+
+    pub fn as_integer(&self) -> Option<i64> {
+        match *self {
+            Plist::Integer(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// The value of a real, or of an integer converted to a real.
+    pub fn as_real(&self) -> Option<f64> {
+        match *self {
+            Plist::Real(value) => Some(value),
+            Plist::Integer(value) => Some(value as f64),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match *self {
+            Plist::Boolean(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_data(&self) -> Option<&[u8]> {
+        match self {
+            Plist::Data(data) => Some(data),
+            _ => None,
+        }
+    }
+
+    /// Encode as an XML property list document.
+    pub fn to_xml(&self) -> String {
+        let mut xml = String::from(HEADER);
+        self.write_xml(&mut xml, 0);
+        xml.push_str(FOOTER);
+        xml
+    }
+
+    /// Decode an XML property list document.
+    ///
+    /// Returns `Error::InvalidPropertyList` if the document is malformed.
+    pub fn from_xml(xml: &str) -> Result<Self, Error> {
+        let mut parser = Parser { rest: xml };
+        parser.skip_prolog();
+        let plist = match parser.next_tag()? {
+            Tag::Open("plist") => {
+                let value = parser.parse_value()?;
+                parser.expect_close("plist")?;
+                value
+            }
+            tag => parser.parse_value_from(tag)?,
+        };
+        parser.skip_misc();
+        if !parser.rest.is_empty() {
+            return Err(Error::InvalidPropertyList);
+        }
+        Ok(plist)
+    }
+
+    fn write_xml(&self, xml: &mut String, depth: usize) {
+        let indent = |xml: &mut String, depth| {
+            for _ in 0..depth {
+                xml.push('\t');
+            }
+        };
+        indent(xml, depth);
+        match self {
+            Plist::Dictionary(dict) if dict.is_empty() => xml.push_str("<dict/>"),
+            Plist::Dictionary(dict) => {
+                xml.push_str("<dict>\n");
+                for (key, value) in dict {
+                    indent(xml, depth + 1);
+                    xml.push_str("<key>");
+                    escape(xml, key);
+                    xml.push_str("</key>\n");
+                    value.write_xml(xml, depth + 1);
+                }
+                indent(xml, depth);
+                xml.push_str("</dict>");
+            }
+            Plist::Array(array) if array.is_empty() => xml.push_str("<array/>"),
+            Plist::Array(array) => {
+                xml.push_str("<array>\n");
+                for value in array {
+                    value.write_xml(xml, depth + 1);
+                }
+                indent(xml, depth);
+                xml.push_str("</array>");
+            }
+            Plist::String(string) => {
+                xml.push_str("<string>");
+                escape(xml, string);
+                xml.push_str("</string>");
+            }
+            Plist::Integer(value) => {
+                write!(xml, "<integer>{}</integer>", value).unwrap();
+            }
+            Plist::Real(value) => {
+                xml.push_str("<real>");
+                if value.is_nan() {
+                    xml.push_str("nan");
+                } else if value.is_infinite() {
+                    xml.push_str(if *value > 0.0 {
+                        "+infinity"
+                    } else {
+                        "-infinity"
+                    });
+                } else {
+                    write!(xml, "{}", value).unwrap();
+                }
+                xml.push_str("</real>");
+            }
+            Plist::Boolean(true) => xml.push_str("<true/>"),
+            Plist::Boolean(false) => xml.push_str("<false/>"),
+            Plist::Data(data) => {
+                xml.push_str("<data>\n");
+                // Like Core Foundation, wrap the base64 lines at 76 columns, less a tab of 8 columns
+                // for each of up to 8 levels of indentation.
+                let width = 76 - 8 * depth.min(8);
+                let encoded = base64_encode(data);
+                for line in encoded.as_bytes().chunks(width) {
+                    indent(xml, depth);
+                    xml.push_str(std::str::from_utf8(line).unwrap());
+                    xml.push('\n');
+                }
+                indent(xml, depth);
+                xml.push_str("</data>");
+            }
+            Plist::Date(date) => {
+                xml.push_str("<date>");
+                escape(xml, date);
+                xml.push_str("</date>");
+            }
+        }
+        xml.push('\n');
+    }
+}
+
+fn escape(xml: &mut String, text: &str) {
+    for c in text.chars() {
+        match c {
+            '&' => xml.push_str("&amp;"),
+            '<' => xml.push_str("&lt;"),
+            '>' => xml.push_str("&gt;"),
+            c => xml.push(c),
+        }
+    }
+}
+
+fn unescape(text: &str) -> Result<String, Error> {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        result.push_str(&rest[..start]);
+        let end = rest[start..].find(';').ok_or(Error::InvalidPropertyList)? + start;
+        let entity = &rest[start + 1..end];
+        let c = match entity {
+            "amp" => '&',
+            "lt" => '<',
+            "gt" => '>',
+            "quot" => '"',
+            "apos" => '\'',
+            _ => {
+                let code = if let Some(hex) = entity.strip_prefix("#x") {
+                    u32::from_str_radix(hex, 16)
+                } else if let Some(decimal) = entity.strip_prefix('#') {
+                    decimal.parse()
+                } else {
+                    return Err(Error::InvalidPropertyList);
+                };
+                code.ok()
+                    .and_then(std::char::from_u32)
+                    .ok_or(Error::InvalidPropertyList)?
+            }
+        };
+        result.push(c);
+        rest = &rest[end + 1..];
+    }
+    result.push_str(rest);
+    Ok(result)
+}
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn base64_encode(data: &[u8]) -> String {
+    // `usize::div_ceil` is too recent for the versions of Rust this crate supports.
+    #[allow(clippy::manual_div_ceil)]
+    let mut encoded = String::with_capacity((data.len() + 2) / 3 * 4);
+    for chunk in data.chunks(3) {
+        let bytes = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let bits = ((bytes[0] as u32) << 16) | ((bytes[1] as u32) << 8) | bytes[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(BASE64[((bits >> (18 - 6 * i)) & 0x3F) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+fn base64_decode(text: &str) -> Result<Vec<u8>, Error> {
+    let mut data = Vec::with_capacity(text.len() / 4 * 3);
+    let mut bits = 0u32;
+    let mut count = 0;
+    for c in text.bytes().filter(|c| !c.is_ascii_whitespace()) {
+        if c == b'=' {
+            break;
+        }
+        let value = BASE64
+            .iter()
+            .position(|&b| b == c)
+            .ok_or(Error::InvalidPropertyList)?;
+        bits = (bits << 6) | value as u32;
+        count += 1;
+        if count == 4 {
+            data.extend_from_slice(&bits.to_be_bytes()[1..]);
+            bits = 0;
+            count = 0;
+        }
+    }
+    match count {
+        0 => (),
+        2 => data.push((bits >> 4) as u8),
+        3 => data.extend_from_slice(&((bits >> 2) as u16).to_be_bytes()),
+        _ => return Err(Error::InvalidPropertyList),
+    }
+    Ok(data)
+}
+
+#[derive(Debug, PartialEq)]
+enum Tag<'a> {
+    Open(&'a str),
+    Close(&'a str),
+    Empty(&'a str),
+}
+
+struct Parser<'a> {
+    rest: &'a str,
+}
+
+impl<'a> Parser<'a> {
+    /// Skip whitespace and comments.
+    fn skip_misc(&mut self) {
+        loop {
+            self.rest = self.rest.trim_start();
+            match self.rest.strip_prefix("<!--") {
+                Some(comment) => {
+                    let end = comment.find("-->").map_or(comment.len(), |end| end + 3);
+                    self.rest = &comment[end..];
+                }
+                None => return,
+            }
+        }
+    }
+
+    /// Skip the XML declaration and document type, along with whitespace and comments.
+    fn skip_prolog(&mut self) {
+        loop {
+            self.skip_misc();
+            if self.rest.starts_with("<?") || self.rest.starts_with("<!DOCTYPE") {
+                let end = self.rest.find('>').map_or(self.rest.len(), |end| end + 1);
+                self.rest = &self.rest[end..];
+            } else {
+                return;
+            }
+        }
+    }
+
+    fn next_tag(&mut self) -> Result<Tag<'a>, Error> {
+        self.skip_misc();
+        let rest = self
+            .rest
+            .strip_prefix('<')
+            .ok_or(Error::InvalidPropertyList)?;
+        let end = rest.find('>').ok_or(Error::InvalidPropertyList)?;
+        let content = &rest[..end];
+        self.rest = &rest[end + 1..];
+        // Attributes, such as the version of the `plist` element, are ignored.
+        let name = |content: &'a str| content.split_whitespace().next().unwrap_or("");
+        let tag = if let Some(name) = content.strip_prefix('/') {
+            Tag::Close(name.trim())
+        } else if let Some(content) = content.strip_suffix('/') {
+            Tag::Empty(name(content))
+        } else {
+            Tag::Open(name(content))
+        };
+        Ok(tag)
+    }
+
+    fn expect_close(&mut self, name: &str) -> Result<(), Error> {
+        match self.next_tag()? {
+            Tag::Close(n) if n == name => Ok(()),
+            _ => Err(Error::InvalidPropertyList),
+        }
+    }
+
+    /// The unescaped text up to the closing tag of the element `name`.
+    fn text(&mut self, name: &str) -> Result<String, Error> {
+        let end = self.rest.find('<').ok_or(Error::InvalidPropertyList)?;
+        let text = &self.rest[..end];
+        self.rest = &self.rest[end..];
+        self.expect_close(name)?;
+        unescape(text)
+    }
+
+    fn parse_value(&mut self) -> Result<Plist, Error> {
+        let tag = self.next_tag()?;
+        self.parse_value_from(tag)
+    }
+
+    fn parse_value_from(&mut self, tag: Tag<'a>) -> Result<Plist, Error> {
+        let value = match tag {
+            Tag::Empty("dict") => Plist::dictionary(),
+            Tag::Empty("array") => Plist::Array(Vec::new()),
+            Tag::Empty("string") => Plist::String(String::new()),
+            Tag::Empty("data") => Plist::Data(Vec::new()),
+            Tag::Empty("true") => Plist::Boolean(true),
+            Tag::Empty("false") => Plist::Boolean(false),
+            Tag::Open("dict") => {
+                let mut dict = BTreeMap::new();
+                loop {
+                    match self.next_tag()? {
+                        Tag::Close("dict") => break,
+                        Tag::Open("key") => {
+                            let key = self.text("key")?;
+                            dict.insert(key, self.parse_value()?);
+                        }
+                        Tag::Empty("key") => {
+                            dict.insert(String::new(), self.parse_value()?);
+                        }
+                        _ => return Err(Error::InvalidPropertyList),
+                    }
+                }
+                Plist::Dictionary(dict)
+            }
+            Tag::Open("array") => {
+                let mut array = Vec::new();
+                loop {
+                    match self.next_tag()? {
+                        Tag::Close("array") => break,
+                        tag => array.push(self.parse_value_from(tag)?),
+                    }
+                }
+                Plist::Array(array)
+            }
+            Tag::Open("string") => Plist::String(self.text("string")?),
+            Tag::Open("integer") => {
+                let text = self.text("integer")?;
+                let text = text.trim();
+                let value = match text.strip_prefix("0x") {
+                    Some(hex) => i64::from_str_radix(hex, 16),
+                    None => text.parse(),
+                };
+                Plist::Integer(value.map_err(|_| Error::InvalidPropertyList)?)
+            }
+            Tag::Open("real") => {
+                let text = self.text("real")?;
+                let value = match text.trim() {
+                    "nan" => f64::NAN,
+                    "+infinity" | "infinity" => f64::INFINITY,
+                    "-infinity" => f64::NEG_INFINITY,
+                    text => text.parse().map_err(|_| Error::InvalidPropertyList)?,
+                };
+                Plist::Real(value)
+            }
+            Tag::Open("data") => Plist::Data(base64_decode(&self.text("data")?)?),
+            Tag::Open("date") => Plist::Date(self.text("date")?.trim().to_string()),
+            _ => return Err(Error::InvalidPropertyList),
+        };
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_base64() {
+        for (data, encoded) in &[
+            (&b""[..], ""),
+            (b"f", "Zg=="),
+            (b"fo", "Zm8="),
+            (b"foo", "Zm9v"),
+            (b"foob", "Zm9vYg=="),
+            (b"\x00\xff\x10\x80", "AP8QgA=="),
+        ] {
+            assert_eq!(base64_encode(data), *encoded);
+            assert_eq!(base64_decode(encoded).unwrap(), *data);
+        }
+        assert_eq!(base64_decode("Zm9v\n\tYg==").unwrap(), b"foob");
+        assert!(base64_decode("Z").is_err());
+        assert!(base64_decode("Zm9*").is_err());
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut state = Plist::dictionary();
+        state.insert("name", Plist::String("Bass & <Drums>".into()));
+        state.insert("type", Plist::Integer(1635083896));
+        state.insert("version", Plist::Integer(-1));
+        state.insert("gain", Plist::Real(0.25));
+        state.insert("bypass", Plist::Boolean(false));
+        state.insert("data", Plist::Data((0..200).collect()));
+        state.insert("empty", Plist::Array(Vec::new()));
+        state.insert("created", Plist::Date("2024-01-31T12:00:00Z".into()));
+        state.insert(
+            "parameters",
+            Plist::Array(vec![
+                Plist::Real(1.5),
+                Plist::dictionary(),
+                Plist::Boolean(true),
+            ]),
+        );
+
+        let xml = state.to_xml();
+        assert!(xml.starts_with(HEADER));
+        assert!(xml.contains("\t<key>name</key>\n\t<string>Bass &amp; &lt;Drums&gt;</string>\n"));
+        // Keys are sorted, so the output doesn't depend on insertion order.
+        assert!(xml.find("<key>bypass</key>") < xml.find("<key>created</key>"));
+        assert_eq!(Plist::from_xml(&xml).unwrap(), state);
+        assert_eq!(state.get("gain").and_then(Plist::as_real), Some(0.25));
+        assert_eq!(
+            state.get("type").and_then(Plist::as_integer),
+            Some(1635083896)
+        );
+    }
+
+    #[test]
+    fn test_data_width() {
+        let data = Plist::Data(vec![0; 300]);
+        let line_lengths = |plist: &Plist| {
+            let xml = plist.to_xml();
+            let start = xml.find("<data>\n").unwrap() + "<data>\n".len();
+            let end = xml.find("</data>").unwrap();
+            xml[start..end]
+                .trim_end_matches('\t')
+                .lines()
+                .map(|line| line.trim_start_matches('\t').len())
+                .collect::<Vec<_>>()
+        };
+        // 300 bytes encode to 400 characters.
+        assert_eq!(line_lengths(&data), vec![76, 76, 76, 76, 76, 20]);
+        let nested = Plist::Array(vec![Plist::Array(vec![data])]);
+        assert_eq!(line_lengths(&nested), vec![60, 60, 60, 60, 60, 60, 40]);
+    }
+
+    #[test]
+    fn test_decode() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<!-- A comment -->
+<plist version="1.0">
+<dict>
+	<key>a</key>
+	<string>x &#60; &#x3E; &quot;y&apos;</string>
+	<key>b</key>
+	<real>-infinity</real>
+	<key>c</key>
+	<data>
+	Zm9v
+	</data>
+	<key>d</key>
+	<string/>
+</dict>
+</plist>
+"#;
+        let plist = Plist::from_xml(xml).unwrap();
+        assert_eq!(plist.get("a").and_then(Plist::as_str), Some("x < > \"y'"));
+        assert_eq!(
+            plist.get("b").and_then(Plist::as_real),
+            Some(f64::NEG_INFINITY)
+        );
+        assert_eq!(plist.get("c").and_then(Plist::as_data), Some(&b"foo"[..]));
+        assert_eq!(plist.get("d").and_then(Plist::as_str), Some(""));
+
+        assert!(Plist::from_xml("<plist><dict><key>a</key></dict></plist>").is_err());
+        assert!(Plist::from_xml("<plist><integer>1.5</integer></plist>").is_err());
+        assert!(Plist::from_xml("<plist><true/></plist><true/>").is_err());
+        assert!(Plist::from_xml("<plist><string>&bogus;</string></plist>").is_err());
+    }
+}
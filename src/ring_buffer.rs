@@ -0,0 +1,390 @@
+//! A wait-free single-producer single-consumer ring buffer of audio frames.
+//!
+//! The ring buffer is intended for moving audio between a render or input callback and the rest
+//! of the application (or between two callbacks, as in the `feedback` examples) without taking
+//! locks or allocating on the realtime thread.
+//!
+//! Samples are stored interleaved, but may be written and read either as interleaved data (e.g.
+//! `data::Interleaved`) or as one slice per channel (e.g. `data::NonInterleaved`).
+//!
+//! ```
+//! use coreaudio::ring_buffer::RingBuffer;
+//!
+//! let (mut producer, mut consumer) = RingBuffer::<f32>::new(1024, 2).split();
+//! producer.write_interleaved(&[0.1, 0.2, 0.3, 0.4]);
+//!
+//! let mut left = [0.0; 2];
+//! let mut right = [0.0; 2];
+//! consumer.read_non_interleaved([&mut left[..], &mut right[..]]);
+//! assert_eq!(left, [0.1, 0.3]);
+//! assert_eq!(right, [0.2, 0.4]);
+//! ```
+
+use std::cell::UnsafeCell;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use crate::Sample;
+
+/// A fixed-capacity ring buffer of interleaved audio frames.
+///
+/// Use [**RingBuffer::split**](./struct.RingBuffer#method.split) to obtain the producer and
+/// consumer halves, which may be moved to different threads.
+pub struct RingBuffer<S> {
+    shared: Arc<Shared<S>>,
+}
+
+/// The writing half of a [**RingBuffer**](./struct.RingBuffer).
+pub struct Producer<S> {
+    shared: Arc<Shared<S>>,
+}
+
+/// The reading half of a [**RingBuffer**](./struct.RingBuffer).
+pub struct Consumer<S> {
+    shared: Arc<Shared<S>>,
+}
+
+struct Shared<S> {
+    data: Box<[UnsafeCell<S>]>,
+    channels: usize,
+    capacity: usize,
+    // The total number of frames written and read. Both wrap around, only their difference is
+    // meaningful.
+    write_pos: AtomicUsize,
+    read_pos: AtomicUsize,
+    overruns: AtomicUsize,
+    underruns: AtomicUsize,
+}
+
+// The producer and consumer never access the same slots at the same time, which is ensured by the
+// acquire/release ordering of `write_pos` and `read_pos`.
+unsafe impl<S: Send> Sync for Shared<S> {}
+
+impl<S> Shared<S> {
+    fn available(&self) -> usize {
+        let write_pos = self.write_pos.load(Ordering::Acquire);
+        let read_pos = self.read_pos.load(Ordering::Acquire);
+        write_pos.wrapping_sub(read_pos)
+    }
+
+    // Pointer to the sample for the given channel of the given absolute frame position.
+    fn slot(&self, frame: usize, channel: usize) -> *mut S {
+        let index = (frame % self.capacity) * self.channels + channel;
+        self.data[index].get()
+    }
+}
+
+impl<S: Sample> RingBuffer<S> {
+    /// Create a ring buffer that can hold `capacity` frames of `channels` channels.
+    ///
+    /// All allocation happens here: neither half allocates when reading or writing.
+    pub fn new(capacity: usize, channels: usize) -> Self {
+        assert!(capacity > 0, "the ring buffer capacity must be non-zero");
+        assert!(
+            channels > 0,
+            "the ring buffer must have at least one channel"
+        );
+        let data = (0..capacity * channels)
+            .map(|_| UnsafeCell::new(S::default()))
+            .collect();
+        let shared = Arc::new(Shared {
+            data,
+            channels,
+            capacity,
+            write_pos: AtomicUsize::new(0),
+            read_pos: AtomicUsize::new(0),
+            overruns: AtomicUsize::new(0),
+            underruns: AtomicUsize::new(0),
+        });
+        RingBuffer { shared }
+    }
+
+    /// Split the ring buffer into its producer and consumer halves.
+    pub fn split(self) -> (Producer<S>, Consumer<S>) {
+        let producer = Producer {
+            shared: self.shared.clone(),
+        };
+        let consumer = Consumer {
+            shared: self.shared,
+        };
+        (producer, consumer)
+    }
+}
+
+macro_rules! impl_common {
+    ($T:ident) => {
+        impl<S> $T<S> {
+            /// The number of frames the ring buffer can hold.
+            pub fn capacity(&self) -> usize {
+                self.shared.capacity
+            }
+
+            /// The number of channels per frame.
+            pub fn channels(&self) -> usize {
+                self.shared.channels
+            }
+
+            /// The number of frames that are ready to be read.
+            pub fn available_frames(&self) -> usize {
+                self.shared.available()
+            }
+
+            /// The number of frames that can be written without overrunning.
+            pub fn free_frames(&self) -> usize {
+                self.shared.capacity - self.shared.available()
+            }
+
+            /// The total number of frames that were dropped because the ring buffer was full.
+            pub fn overruns(&self) -> usize {
+                self.shared.overruns.load(Ordering::Relaxed)
+            }
+
+            /// The total number of frames that were requested but not available, and were filled
+            /// with silence instead.
+            pub fn underruns(&self) -> usize {
+                self.shared.underruns.load(Ordering::Relaxed)
+            }
+        }
+
+        unsafe impl<S: Send> Send for $T<S> {}
+    };
+}
+
+impl_common!(Producer);
+impl_common!(Consumer);
+
+impl<S: Sample> Producer<S> {
+    /// Write interleaved frames to the ring buffer.
+    ///
+    /// The length of `data` should be a multiple of the number of channels. Frames that do not fit
+    /// are dropped and counted as overruns.
+    ///
+    /// Returns the number of frames written.
+    pub fn write_interleaved(&mut self, data: &[S]) -> usize {
+        let channels = self.shared.channels;
+        let requested = data.len() / channels;
+        let frames = requested.min(self.free_frames());
+        let write_pos = self.shared.write_pos.load(Ordering::Relaxed);
+        for (i, frame) in data.chunks_exact(channels).take(frames).enumerate() {
+            for (channel, sample) in frame.iter().enumerate() {
+                unsafe { *self.shared.slot(write_pos.wrapping_add(i), channel) = sample.clone() };
+            }
+        }
+        self.commit(write_pos, frames, requested)
+    }
+
+    /// Write one slice per channel to the ring buffer.
+    ///
+    /// All channels should have the same length. Channels beyond the ring buffer's channel count
+    /// are ignored. Frames that do not fit are dropped and counted as overruns.
+    ///
+    /// Returns the number of frames written.
+    pub fn write_non_interleaved<'a, I>(&mut self, channels: I) -> usize
+    where
+        I: IntoIterator<Item = &'a [S]>,
+        S: 'a,
+    {
+        let free = self.free_frames();
+        let write_pos = self.shared.write_pos.load(Ordering::Relaxed);
+        let mut requested = 0;
+        let mut frames = 0;
+        for (channel, data) in channels.into_iter().take(self.shared.channels).enumerate() {
+            requested = requested.max(data.len());
+            let n = data.len().min(free);
+            for (i, sample) in data[..n].iter().enumerate() {
+                unsafe { *self.shared.slot(write_pos.wrapping_add(i), channel) = sample.clone() };
+            }
+            frames = frames.max(n);
+        }
+        self.commit(write_pos, frames, requested)
+    }
+
+    fn commit(&mut self, write_pos: usize, frames: usize, requested: usize) -> usize {
+        self.shared
+            .write_pos
+            .store(write_pos.wrapping_add(frames), Ordering::Release);
+        if requested > frames {
+            self.shared
+                .overruns
+                .fetch_add(requested - frames, Ordering::Relaxed);
+        }
+        frames
+    }
+}
+
+impl<S: Sample> Consumer<S> {
+    /// Read interleaved frames from the ring buffer into `data`.
+    ///
+    /// The length of `data` should be a multiple of the number of channels. If fewer frames are
+    /// available than requested, the remainder of `data` is filled with `S::default()` and the
+    /// missing frames are counted as underruns.
+    ///
+    /// Returns the number of frames read.
+    pub fn read_interleaved(&mut self, data: &mut [S]) -> usize {
+        let channels = self.shared.channels;
+        let requested = data.len() / channels;
+        let frames = requested.min(self.available_frames());
+        let read_pos = self.shared.read_pos.load(Ordering::Relaxed);
+        for (i, frame) in data.chunks_exact_mut(channels).enumerate() {
+            for (channel, sample) in frame.iter_mut().enumerate() {
+                *sample = if i < frames {
+                    unsafe { (*self.shared.slot(read_pos.wrapping_add(i), channel)).clone() }
+                } else {
+                    S::default()
+                };
+            }
+        }
+        self.commit(read_pos, frames, requested)
+    }
+
+    /// Read frames from the ring buffer into one slice per channel.
+    ///
+    /// All channels should have the same length. Channels beyond the ring buffer's channel count
+    /// are left untouched. If fewer frames are available than requested, the remainder of each
+    /// channel is filled with `S::default()` and the missing frames are counted as underruns.
+    ///
+    /// Returns the number of frames read.
+    pub fn read_non_interleaved<'a, I>(&mut self, channels: I) -> usize
+    where
+        I: IntoIterator<Item = &'a mut [S]>,
+        S: 'a,
+    {
+        let available = self.available_frames();
+        let read_pos = self.shared.read_pos.load(Ordering::Relaxed);
+        let mut requested = 0;
+        let mut frames = 0;
+        for (channel, data) in channels.into_iter().take(self.shared.channels).enumerate() {
+            requested = requested.max(data.len());
+            let n = data.len().min(available);
+            let (read, silent) = data.split_at_mut(n);
+            for (i, sample) in read.iter_mut().enumerate() {
+                *sample = unsafe { (*self.shared.slot(read_pos.wrapping_add(i), channel)).clone() };
+            }
+            for sample in silent {
+                *sample = S::default();
+            }
+            frames = frames.max(n);
+        }
+        self.commit(read_pos, frames, requested)
+    }
+
+    /// Discard up to `frames` frames without reading them.
+    ///
+    /// Returns the number of frames discarded.
+    pub fn skip(&mut self, frames: usize) -> usize {
+        let frames = frames.min(self.available_frames());
+        let read_pos = self.shared.read_pos.load(Ordering::Relaxed);
+        self.shared
+            .read_pos
+            .store(read_pos.wrapping_add(frames), Ordering::Release);
+        frames
+    }
+
+    fn commit(&mut self, read_pos: usize, frames: usize, requested: usize) -> usize {
+        self.shared
+            .read_pos
+            .store(read_pos.wrapping_add(frames), Ordering::Release);
+        if requested > frames {
+            self.shared
+                .underruns
+                .fetch_add(requested - frames, Ordering::Relaxed);
+        }
+        frames
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_interleaved_wrap_around() {
+        let (mut producer, mut consumer) = RingBuffer::<i16>::new(4, 2).split();
+        let mut out = [0; 6];
+        for round in 0..10 {
+            let base = round * 6;
+            let frames = [base, base + 1, base + 2, base + 3, base + 4, base + 5];
+            assert_eq!(producer.write_interleaved(&frames), 3);
+            assert_eq!(consumer.available_frames(), 3);
+            assert_eq!(consumer.read_interleaved(&mut out), 3);
+            assert_eq!(out, frames);
+        }
+        assert_eq!(producer.overruns(), 0);
+        assert_eq!(consumer.underruns(), 0);
+    }
+
+    #[test]
+    fn test_non_interleaved() {
+        let (mut producer, mut consumer) = RingBuffer::<f32>::new(8, 2).split();
+        let left = [1.0, 2.0, 3.0];
+        let right = [-1.0, -2.0, -3.0];
+        assert_eq!(
+            producer.write_non_interleaved(vec![&left[..], &right[..]]),
+            3
+        );
+
+        let mut interleaved = [0.0; 4];
+        assert_eq!(consumer.read_interleaved(&mut interleaved), 2);
+        assert_eq!(interleaved, [1.0, -1.0, 2.0, -2.0]);
+
+        let mut left_out = [0.0; 1];
+        let mut right_out = [0.0; 1];
+        let channels = vec![&mut left_out[..], &mut right_out[..]];
+        assert_eq!(consumer.read_non_interleaved(channels), 1);
+        assert_eq!((left_out, right_out), ([3.0], [-3.0]));
+    }
+
+    #[test]
+    fn test_overrun_and_underrun() {
+        let (mut producer, mut consumer) = RingBuffer::<i32>::new(4, 1).split();
+        assert_eq!(producer.write_interleaved(&[1, 2, 3, 4, 5, 6]), 4);
+        assert_eq!(producer.overruns(), 2);
+        assert_eq!(producer.free_frames(), 0);
+
+        let mut out = [9; 6];
+        assert_eq!(consumer.read_interleaved(&mut out), 4);
+        assert_eq!(out, [1, 2, 3, 4, 0, 0]);
+        assert_eq!(consumer.underruns(), 2);
+        assert_eq!(producer.underruns(), 2);
+
+        assert_eq!(producer.write_interleaved(&[7, 8]), 2);
+        assert_eq!(consumer.skip(5), 2);
+        assert_eq!(consumer.available_frames(), 0);
+    }
+
+    #[test]
+    fn test_threaded() {
+        const FRAMES: usize = 20_000;
+        let (mut producer, mut consumer) = RingBuffer::<i32>::new(64, 2).split();
+
+        let writer = thread::spawn(move || {
+            let mut next = 0;
+            while next < FRAMES {
+                let frames = (FRAMES - next).min(17).min(producer.free_frames());
+                let data: Vec<i32> = (next..next + frames)
+                    .flat_map(|i| vec![i as i32, -(i as i32)])
+                    .collect();
+                assert_eq!(producer.write_interleaved(&data), frames);
+                next += frames;
+                thread::yield_now();
+            }
+            producer.overruns()
+        });
+
+        let mut expected = 0;
+        let mut out = [0; 26];
+        while expected < FRAMES {
+            let frames = (out.len() / 2).min(consumer.available_frames());
+            let read = consumer.read_interleaved(&mut out[..frames * 2]);
+            assert_eq!(read, frames);
+            for frame in out[..read * 2].chunks(2) {
+                assert_eq!(frame, [expected as i32, -(expected as i32)]);
+                expected += 1;
+            }
+        }
+
+        assert_eq!(writer.join().unwrap(), 0);
+        assert_eq!(consumer.underruns(), 0);
+    }
+}
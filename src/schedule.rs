@@ -0,0 +1,397 @@
+//! Sample-accurate scheduling of events inside render callbacks.
+//!
+//! A render callback only learns the time stamp of the first frame and the number of frames to
+//! render. To make events (note ons, parameter changes, sample triggers) land on exact frames, a
+//! [**Scheduler**](./struct.Scheduler) holds pending events stamped in sample time or host time,
+//! and splits each render into sub-blocks at the event boundaries.
+//!
+//! ```
+//! use coreaudio::schedule::{Block, EventTime, HostClock, Scheduler};
+//! use coreaudio::sys;
+//!
+//! let mut scheduler = Scheduler::new(64, 44_100.0, HostClock::from_ticks_per_second(1e9));
+//! scheduler.schedule(EventTime::Sample(1_100.0), "note on").ok();
+//! scheduler.schedule(EventTime::Sample(1_300.0), "note off").ok();
+//!
+//! // Inside the render callback, with the time stamp and frame count from `render_callback::Args`.
+//! let time_stamp = sys::AudioTimeStamp {
+//!     mSampleTime: 1_024.0,
+//!     mFlags: sys::kAudioTimeStampSampleTimeValid,
+//!     ..Default::default()
+//! };
+//! let mut blocks = Vec::new();
+//! scheduler.process(&time_stamp, 512, |block| blocks.push(block));
+//! assert_eq!(
+//!     blocks,
+//!     vec![
+//!         Block::Render(0..76),
+//!         Block::Event { frame: 76, event: "note on" },
+//!         Block::Render(76..276),
+//!         Block::Event { frame: 276, event: "note off" },
+//!         Block::Render(276..512),
+//!     ]
+//! );
+//! ```
+//!
+//! Events are usually sent to the callback through a [**command**](../command/index.html)
+//! channel and handed to the scheduler at the start of each render.
+
+use std::ops::Range;
+
+use sys;
+
+/// The time at which an event should occur.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum EventTime {
+    /// A sample time, on the same timeline as the `mSampleTime` of the render time stamps.
+    Sample(f64),
+    /// A host time in host clock ticks, on the same timeline as the `mHostTime` of the render time
+    /// stamps.
+    Host(u64),
+}
+
+/// Converts host time ticks to seconds.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct HostClock {
+    ticks_per_second: f64,
+}
+
+impl HostClock {
+    /// A host clock running at the given frequency.
+    pub fn from_ticks_per_second(ticks_per_second: f64) -> Self {
+        assert!(
+            ticks_per_second > 0.0,
+            "the host clock frequency must be positive"
+        );
+        HostClock { ticks_per_second }
+    }
+
+    /// The host clock of the system, as reported by `AudioGetHostClockFrequency`.
+    ///
+    /// **Available** in macOS only.
+    #[cfg(all(target_os = "macos", feature = "core_audio"))]
+    pub fn system() -> Self {
+        Self::from_ticks_per_second(unsafe { sys::AudioGetHostClockFrequency() })
+    }
+
+    /// The frequency of the clock in ticks per second.
+    pub fn ticks_per_second(&self) -> f64 {
+        self.ticks_per_second
+    }
+
+    /// Convert a number of ticks to seconds.
+    pub fn ticks_to_seconds(&self, ticks: f64) -> f64 {
+        ticks / self.ticks_per_second
+    }
+}
+
+/// A part of a render, produced by [**Scheduler::process**](./struct.Scheduler#method.process).
+#[derive(Clone, Debug, PartialEq)]
+pub enum Block<E> {
+    /// An event occurring at the given frame offset of the render.
+    Event { frame: usize, event: E },
+    /// A range of frames of the render that contains no events.
+    Render(Range<usize>),
+}
+
+/// Holds pending events and splits renders into sub-blocks at their boundaries.
+///
+/// All allocation happens on construction. Scheduling and processing events never allocates,
+/// so the scheduler can live inside a render callback.
+pub struct Scheduler<E> {
+    events: Vec<Pending<E>>,
+    capacity: usize,
+    sample_rate: f64,
+    host_clock: HostClock,
+    // Increases with each scheduled event so that events with equal times keep their order.
+    sequence: u64,
+}
+
+struct Pending<E> {
+    time: EventTime,
+    sequence: u64,
+    event: E,
+}
+
+impl<E> Scheduler<E> {
+    /// Create a scheduler holding up to `capacity` pending events for a stream at the given sample
+    /// rate. The `host_clock` is used to convert events stamped in host time.
+    pub fn new(capacity: usize, sample_rate: f64, host_clock: HostClock) -> Self {
+        Scheduler {
+            events: Vec::with_capacity(capacity),
+            capacity,
+            sample_rate,
+            host_clock,
+            sequence: 0,
+        }
+    }
+
+    /// The maximum number of pending events.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// The number of pending events.
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    /// Whether there are no pending events.
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    /// The sample rate used to convert host times.
+    pub fn sample_rate(&self) -> f64 {
+        self.sample_rate
+    }
+
+    /// Change the sample rate used to convert host times, e.g. after the stream format changed.
+    pub fn set_sample_rate(&mut self, sample_rate: f64) {
+        self.sample_rate = sample_rate;
+    }
+
+    /// Schedule an event at the given time.
+    ///
+    /// Events with equal times are processed in the order they were scheduled. Returns the event
+    /// back if the scheduler is full.
+    pub fn schedule(&mut self, time: EventTime, event: E) -> Result<(), E> {
+        if self.events.len() == self.capacity {
+            return Err(event);
+        }
+        self.events.push(Pending {
+            time,
+            sequence: self.sequence,
+            event,
+        });
+        self.sequence = self.sequence.wrapping_add(1);
+        Ok(())
+    }
+
+    /// Remove all pending events.
+    pub fn clear(&mut self) {
+        self.events.clear();
+    }
+
+    /// Split a render of `num_frames` frames starting at `time_stamp` into blocks.
+    ///
+    /// `f` is called in order with a `Block::Render` for each range of frames between events and
+    /// a `Block::Event` for each event due within the render. Events that are already late occur
+    /// at frame zero. Events after the end of the render stay pending.
+    ///
+    /// Events stamped in host time are converted using the host time of `time_stamp`, and stay
+    /// pending if it has no valid host time.
+    pub fn process<F>(&mut self, time_stamp: &sys::AudioTimeStamp, num_frames: usize, mut f: F)
+    where
+        F: FnMut(Block<E>),
+    {
+        let start = time_stamp.mSampleTime;
+        let host_valid = time_stamp.mFlags & sys::kAudioTimeStampHostTimeValid != 0;
+        if host_valid {
+            for pending in self.events.iter_mut() {
+                if let EventTime::Host(host_time) = pending.time {
+                    let ticks = host_time.wrapping_sub(time_stamp.mHostTime) as i64 as f64;
+                    let seconds = self.host_clock.ticks_to_seconds(ticks);
+                    pending.time = EventTime::Sample(start + seconds * self.sample_rate);
+                }
+            }
+        }
+
+        // `sort_unstable_by` does not allocate, and the sequence keeps the order stable.
+        self.events.sort_unstable_by(|a, b| {
+            sample_time(a)
+                .partial_cmp(&sample_time(b))
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then(a.sequence.cmp(&b.sequence))
+        });
+
+        let end = start + num_frames as f64;
+        let due = self
+            .events
+            .iter()
+            .take_while(|pending| sample_time(pending) < end)
+            .count();
+
+        let mut cursor = 0;
+        for pending in self.events.drain(..due) {
+            let offset = (sample_time(&pending) - start).floor();
+            let frame = if offset > 0.0 { offset as usize } else { 0 };
+            let frame = frame.clamp(cursor, num_frames.saturating_sub(1));
+            if frame > cursor {
+                f(Block::Render(cursor..frame));
+                cursor = frame;
+            }
+            f(Block::Event {
+                frame,
+                event: pending.event,
+            });
+        }
+        if cursor < num_frames {
+            f(Block::Render(cursor..num_frames));
+        }
+    }
+}
+
+fn sample_time<E>(pending: &Pending<E>) -> f64 {
+    match pending.time {
+        EventTime::Sample(sample_time) => sample_time,
+        // Host times that could not be converted are never due.
+        EventTime::Host(_) => f64::INFINITY,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn time_stamp(sample_time: f64, host_time: Option<u64>) -> sys::AudioTimeStamp {
+        let mut time_stamp = sys::AudioTimeStamp {
+            mSampleTime: sample_time,
+            mFlags: sys::kAudioTimeStampSampleTimeValid,
+            ..Default::default()
+        };
+        if let Some(host_time) = host_time {
+            time_stamp.mHostTime = host_time;
+            time_stamp.mFlags |= sys::kAudioTimeStampHostTimeValid;
+        }
+        time_stamp
+    }
+
+    fn process<E>(
+        scheduler: &mut Scheduler<E>,
+        ts: &sys::AudioTimeStamp,
+        n: usize,
+    ) -> Vec<Block<E>> {
+        let mut blocks = Vec::new();
+        scheduler.process(ts, n, |block| blocks.push(block));
+        blocks
+    }
+
+    #[test]
+    fn test_no_events() {
+        let mut scheduler =
+            Scheduler::<()>::new(4, 48_000.0, HostClock::from_ticks_per_second(1e9));
+        let blocks = process(&mut scheduler, &time_stamp(0.0, None), 256);
+        assert_eq!(blocks, vec![Block::Render(0..256)]);
+    }
+
+    #[test]
+    fn test_late_pending_and_ordering() {
+        let mut scheduler = Scheduler::new(8, 48_000.0, HostClock::from_ticks_per_second(1e9));
+        scheduler.schedule(EventTime::Sample(600.0), 'c').unwrap();
+        scheduler.schedule(EventTime::Sample(100.0), 'a').unwrap();
+        scheduler.schedule(EventTime::Sample(300.0), 'b').unwrap();
+        scheduler.schedule(EventTime::Sample(300.0), 'B').unwrap();
+
+        let blocks = process(&mut scheduler, &time_stamp(256.0, None), 256);
+        assert_eq!(
+            blocks,
+            vec![
+                Block::Event {
+                    frame: 0,
+                    event: 'a'
+                },
+                Block::Render(0..44),
+                Block::Event {
+                    frame: 44,
+                    event: 'b'
+                },
+                Block::Event {
+                    frame: 44,
+                    event: 'B'
+                },
+                Block::Render(44..256),
+            ]
+        );
+        assert_eq!(scheduler.len(), 1);
+
+        let blocks = process(&mut scheduler, &time_stamp(512.0, None), 256);
+        assert_eq!(
+            blocks,
+            vec![
+                Block::Render(0..88),
+                Block::Event {
+                    frame: 88,
+                    event: 'c'
+                },
+                Block::Render(88..256),
+            ]
+        );
+        assert!(scheduler.is_empty());
+    }
+
+    #[test]
+    fn test_host_time() {
+        // 1000 ticks per second at a sample rate of 1000 makes a tick equal to a frame.
+        let clock = HostClock::from_ticks_per_second(1_000.0);
+        let mut scheduler = Scheduler::new(4, 1_000.0, clock);
+        scheduler.schedule(EventTime::Host(10_010), 1).unwrap();
+        scheduler.schedule(EventTime::Host(9_000), 0).unwrap();
+        scheduler.schedule(EventTime::Host(10_500), 2).unwrap();
+
+        let blocks = process(&mut scheduler, &time_stamp(0.0, Some(10_000)), 64);
+        assert_eq!(
+            blocks,
+            vec![
+                Block::Event { frame: 0, event: 0 },
+                Block::Render(0..10),
+                Block::Event {
+                    frame: 10,
+                    event: 1
+                },
+                Block::Render(10..64),
+            ]
+        );
+
+        // Converted events stay pending on the sample timeline.
+        assert_eq!(scheduler.len(), 1);
+    }
+
+    #[test]
+    fn test_invalid_host_time() {
+        let clock = HostClock::from_ticks_per_second(1_000.0);
+        let mut scheduler = Scheduler::new(4, 1_000.0, clock);
+        scheduler.schedule(EventTime::Host(10_010), 1).unwrap();
+        scheduler.schedule(EventTime::Sample(20.0), 0).unwrap();
+
+        // Without a valid host time, host time events stay pending.
+        let blocks = process(&mut scheduler, &time_stamp(0.0, None), 64);
+        assert_eq!(
+            blocks,
+            vec![
+                Block::Render(0..20),
+                Block::Event {
+                    frame: 20,
+                    event: 0
+                },
+                Block::Render(20..64),
+            ]
+        );
+        assert_eq!(scheduler.len(), 1);
+
+        // They occur once a render time stamp has a valid host time.
+        let blocks = process(&mut scheduler, &time_stamp(64.0, Some(10_000)), 64);
+        assert_eq!(
+            blocks,
+            vec![
+                Block::Render(0..10),
+                Block::Event {
+                    frame: 10,
+                    event: 1
+                },
+                Block::Render(10..64),
+            ]
+        );
+        assert!(scheduler.is_empty());
+    }
+
+    #[test]
+    fn test_capacity() {
+        let mut scheduler = Scheduler::new(1, 48_000.0, HostClock::from_ticks_per_second(1e9));
+        assert_eq!(scheduler.schedule(EventTime::Sample(0.0), 1), Ok(()));
+        assert_eq!(scheduler.schedule(EventTime::Sample(0.0), 2), Err(2));
+        scheduler.clear();
+        assert_eq!(scheduler.schedule(EventTime::Sample(0.0), 3), Ok(()));
+        assert_eq!(scheduler.capacity(), 1);
+    }
+}
@@ -0,0 +1,178 @@
+//! Smoothing of parameter changes inside render callbacks.
+//!
+//! Applying a new gain or cutoff value at a block boundary produces audible "zipper" noise. A
+//! [**Smoother**](./struct.Smoother) instead ramps linearly from its current value to a new
+//! target over a fixed number of frames, one frame at a time.
+//!
+//! ```
+//! use coreaudio::smoother::Smoother;
+//!
+//! let mut gain = Smoother::new(0.0, 4);
+//! gain.set_target(1.0);
+//!
+//! let mut frames = [0.0; 6];
+//! gain.fill(&mut frames);
+//! assert_eq!(frames, [0.25, 0.5, 0.75, 1.0, 1.0, 1.0]);
+//! ```
+//!
+//! This is the pure-Rust counterpart of the ramped events scheduled with
+//! `AudioUnit::schedule_parameters`, for use in render callbacks.
+
+/// Linearly ramps a parameter value towards a target.
+///
+/// None of the methods allocate, so the smoother may live inside a render callback.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Smoother {
+    current: f32,
+    target: f32,
+    step: f32,
+    remaining: u32,
+    ramp_frames: u32,
+}
+
+impl Smoother {
+    /// A smoother starting at `initial`, ramping to new targets over `ramp_frames` frames.
+    ///
+    /// With `ramp_frames` of `0` new targets are applied immediately.
+    pub fn new(initial: f32, ramp_frames: u32) -> Self {
+        Smoother {
+            current: initial,
+            target: initial,
+            step: 0.0,
+            remaining: 0,
+            ramp_frames,
+        }
+    }
+
+    /// Change the number of frames over which future targets are reached.
+    ///
+    /// A ramp that is already in progress is not affected.
+    pub fn set_ramp_frames(&mut self, ramp_frames: u32) {
+        self.ramp_frames = ramp_frames;
+    }
+
+    /// The number of frames over which new targets are reached.
+    pub fn ramp_frames(&self) -> u32 {
+        self.ramp_frames
+    }
+
+    /// Start ramping from the current value to `target`.
+    ///
+    /// If a ramp is in progress, the new ramp starts from wherever the old one had reached.
+    pub fn set_target(&mut self, target: f32) {
+        if self.ramp_frames == 0 {
+            self.set_immediate(target);
+            return;
+        }
+        self.target = target;
+        self.remaining = self.ramp_frames;
+        self.step = (target - self.current) / self.ramp_frames as f32;
+    }
+
+    /// Jump to `value` without ramping.
+    pub fn set_immediate(&mut self, value: f32) {
+        self.current = value;
+        self.target = value;
+        self.step = 0.0;
+        self.remaining = 0;
+    }
+
+    /// Advance by one frame and return the value for that frame.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> f32 {
+        if self.remaining > 0 {
+            self.remaining -= 1;
+            // Land exactly on the target rather than accumulating rounding errors.
+            self.current = if self.remaining == 0 {
+                self.target
+            } else {
+                self.current + self.step
+            };
+        }
+        self.current
+    }
+
+    /// Fill `frames` with consecutive values, advancing by `frames.len()` frames.
+    pub fn fill(&mut self, frames: &mut [f32]) {
+        for frame in frames {
+            *frame = self.next();
+        }
+    }
+
+    /// Advance by `frames` frames without producing values.
+    pub fn skip(&mut self, frames: u32) {
+        if frames >= self.remaining {
+            self.current = self.target;
+            self.remaining = 0;
+        } else {
+            self.remaining -= frames;
+            self.current += self.step * frames as f32;
+        }
+    }
+
+    /// Whether a ramp is still in progress.
+    pub fn is_ramping(&self) -> bool {
+        self.remaining > 0
+    }
+
+    /// The value returned by the most recent call to [**next**](./struct.Smoother#method.next).
+    pub fn current(&self) -> f32 {
+        self.current
+    }
+
+    /// The value being ramped towards.
+    pub fn target(&self) -> f32 {
+        self.target
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Smoother;
+
+    #[test]
+    fn test_ramp() {
+        let mut smoother = Smoother::new(1.0, 2);
+        assert!(!smoother.is_ramping());
+        assert_eq!(smoother.next(), 1.0);
+
+        smoother.set_target(0.0);
+        assert!(smoother.is_ramping());
+        assert_eq!(smoother.next(), 0.5);
+        assert_eq!(smoother.next(), 0.0);
+        assert!(!smoother.is_ramping());
+        assert_eq!(smoother.next(), 0.0);
+    }
+
+    #[test]
+    fn test_retarget_mid_ramp() {
+        let mut smoother = Smoother::new(0.0, 4);
+        smoother.set_target(4.0);
+        assert_eq!(smoother.next(), 1.0);
+        smoother.set_target(0.0);
+        let mut frames = [0.0; 4];
+        smoother.fill(&mut frames);
+        assert_eq!(frames, [0.75, 0.5, 0.25, 0.0]);
+    }
+
+    #[test]
+    fn test_immediate_and_skip() {
+        let mut smoother = Smoother::new(0.0, 0);
+        smoother.set_target(2.0);
+        assert!(!smoother.is_ramping());
+        assert_eq!(smoother.next(), 2.0);
+
+        smoother.set_ramp_frames(10);
+        smoother.set_target(12.0);
+        smoother.skip(5);
+        assert_eq!(smoother.current(), 7.0);
+        smoother.skip(100);
+        assert_eq!(smoother.current(), 12.0);
+        assert_eq!(smoother.target(), 12.0);
+        assert!(!smoother.is_ramping());
+
+        smoother.set_target(0.0);
+        smoother.set_immediate(3.0);
+        assert_eq!(smoother.next(), 3.0);
+    }
+}